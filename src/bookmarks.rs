@@ -0,0 +1,70 @@
+use yapping_core::{chrono::{self, Local, TimeZone}, l3gion_rust::UUID};
+
+use crate::local_store::Stored;
+
+/// A Message saved by the user, kept as a copy so it survives the Message being deleted or expiring.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Bookmark {
+    pub(crate) chat_uuid: UUID,
+    pub(crate) message_uuid: UUID,
+    pub(crate) sender: UUID,
+    // Message text, with mentions encoded.
+    pub(crate) text: String,
+    pub(crate) saved_at: chrono::DateTime<Local>,
+}
+impl Bookmark {
+    pub(crate) fn new(chat_uuid: UUID, message_uuid: UUID, sender: UUID, text: String) -> Self {
+        Self {
+            chat_uuid,
+            message_uuid,
+            sender,
+            text,
+            saved_at: Local::now(),
+        }
+    }
+}
+
+impl Stored for Bookmark {
+    // Chat UUID, Message UUID, sender, text, unix timestamp.
+    type Bytes = (UUID, UUID, UUID, String, i64);
+
+    fn to_stored(&self) -> Self::Bytes {
+        (self.chat_uuid, self.message_uuid, self.sender, self.text.clone(), self.saved_at.timestamp())
+    }
+
+    fn from_stored((chat_uuid, message_uuid, sender, text, timestamp): Self::Bytes) -> Option<Self> {
+        Some(Self {
+            chat_uuid,
+            message_uuid,
+            sender,
+            text,
+            saved_at: Local.timestamp_opt(timestamp, 0).single()?,
+        })
+    }
+}
+
+/// Saves the Message, or removes it if it was already saved. Returns whether it's saved now.
+pub(crate) fn toggle(bookmarks: &mut Vec<Bookmark>, bookmark: Bookmark) -> bool {
+    let before = bookmarks.len();
+    bookmarks.retain(|saved| saved.message_uuid != bookmark.message_uuid);
+    if bookmarks.len() != before { return false; }
+
+    bookmarks.push(bookmark);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggle_saves_and_removes() {
+        let bookmark = Bookmark::new(UUID::generate(), UUID::generate(), UUID::generate(), String::from("hi"));
+        let mut bookmarks = Vec::default();
+
+        assert!(toggle(&mut bookmarks, bookmark.clone()));
+        assert_eq!(bookmarks.len(), 1);
+        assert!(!toggle(&mut bookmarks, bookmark));
+        assert!(bookmarks.is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use yapping_core::l3gion_rust::{AsLgTime, LgTimer, UUID};
+
+// Typing notifications older than this are ignored, in case the TYPING_STOP never arrives.
+const TYPING_TIMEOUT_SECONDS: u32 = 6;
+
+/// Delivery of a Message sent by the current user.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DeliveryState {
+    // Shown right away, waiting for the server to acknowledge it.
+    SENDING,
+    SENT,
+    // The server echoed it back to the members.
+    DELIVERED,
+    // Another member read up to this Message.
+    READ,
+    FAILED,
+}
+
+/// Client side state of a Chat, everything the server only sends as notifications.
+#[derive(Debug, Default)]
+pub(crate) struct ChatState {
+    // Message UUID, (Emoji, Users that reacted)
+    reactions: HashMap<UUID, Vec<(String, Vec<UUID>)>>,
+    // User UUID, time since the last TYPING_START
+    typing: Vec<(UUID, LgTimer)>,
+    // User UUID, last Message UUID the user has read
+    read_up_to: HashMap<UUID, UUID>,
+    // Messages from other users received since the current user last read the Chat.
+    unread: usize,
+    // Unread messages that mention the current user.
+    unread_mentions: usize,
+    // Message UUID, delivery of the messages sent from this client.
+    delivery: HashMap<UUID, DeliveryState>,
+    // (Message UUID, User that pinned it), oldest pin first.
+    pinned: Vec<(UUID, UUID)>,
+}
+impl ChatState {
+    pub(crate) fn reactions(&self, message_uuid: UUID) -> &[(String, Vec<UUID>)] {
+        self.reactions
+            .get(&message_uuid)
+            .map(|r| r.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn has_reacted(&self, message_uuid: UUID, user_uuid: UUID, emoji: &str) -> bool {
+        self.reactions(message_uuid)
+            .iter()
+            .any(|(e, users)| e == emoji && users.contains(&user_uuid))
+    }
+
+    /// Every Message with reactions, to keep them in the local cache.
+    pub(crate) fn all_reactions(&self) -> &HashMap<UUID, Vec<(String, Vec<UUID>)>> {
+        &self.reactions
+    }
+
+    /// Replaces every reaction, with the ones the Server or the local cache has.
+    pub(crate) fn set_reactions(&mut self, reactions: HashMap<UUID, Vec<(String, Vec<UUID>)>>) {
+        self.reactions = reactions;
+    }
+
+    /// Does nothing if the user already reacted with this emoji, so server echoes are harmless.
+    pub(crate) fn add_reaction(&mut self, message_uuid: UUID, user_uuid: UUID, emoji: &str) {
+        let reactions = self.reactions.entry(message_uuid).or_default();
+
+        match reactions.iter_mut().find(|(e, _)| e == emoji) {
+            Some((_, users)) => if !users.contains(&user_uuid) {
+                users.push(user_uuid);
+            },
+            None => reactions.push((emoji.to_string(), vec![user_uuid])),
+        }
+    }
+
+    pub(crate) fn remove_reaction(&mut self, message_uuid: UUID, user_uuid: UUID, emoji: &str) {
+        if let Some(reactions) = self.reactions.get_mut(&message_uuid) {
+            for (e, users) in reactions.iter_mut() {
+                if e == emoji {
+                    users.retain(|u| *u != user_uuid);
+                }
+            }
+            reactions.retain(|(_, users)| !users.is_empty());
+
+            if reactions.is_empty() {
+                self.reactions.remove(&message_uuid);
+            }
+        }
+    }
+
+    /// Users currently typing, in the order they started.
+    pub(crate) fn typing_users(&self) -> Vec<UUID> {
+        self.typing
+            .iter()
+            .filter(|(_, timer)| timer.elapsed() < TYPING_TIMEOUT_SECONDS.s())
+            .map(|(user, _)| *user)
+            .collect()
+    }
+
+    pub(crate) fn set_typing(&mut self, user_uuid: UUID, typing: bool) {
+        self.typing.retain(|(user, timer)| *user != user_uuid && timer.elapsed() < TYPING_TIMEOUT_SECONDS.s());
+
+        if typing {
+            self.typing.push((user_uuid, LgTimer::new()));
+        }
+    }
+
+    pub(crate) fn last_read(&self, user_uuid: UUID) -> Option<UUID> {
+        self.read_up_to.get(&user_uuid).copied()
+    }
+
+    pub(crate) fn read_up_to(&self) -> &HashMap<UUID, UUID> {
+        &self.read_up_to
+    }
+
+    pub(crate) fn set_read(&mut self, user_uuid: UUID, message_uuid: UUID) {
+        let _ = self.read_up_to.insert(user_uuid, message_uuid);
+    }
+
+    /// Replaces every read receipt, with the ones the local cache has.
+    pub(crate) fn set_read_up_to(&mut self, read_up_to: HashMap<UUID, UUID>) {
+        self.read_up_to = read_up_to;
+    }
+
+    pub(crate) fn unread(&self) -> usize {
+        self.unread
+    }
+
+    pub(crate) fn add_unread(&mut self) {
+        self.unread += 1;
+    }
+
+    /// Replaces both counts, when they are recounted from the loaded messages.
+    pub(crate) fn set_unread(&mut self, unread: usize, unread_mentions: usize) {
+        self.unread = unread;
+        self.unread_mentions = unread_mentions;
+    }
+
+    pub(crate) fn clear_unread(&mut self) {
+        self.unread = 0;
+        self.unread_mentions = 0;
+    }
+
+    pub(crate) fn unread_mentions(&self) -> usize {
+        self.unread_mentions
+    }
+
+    pub(crate) fn add_unread_mention(&mut self) {
+        self.unread_mentions += 1;
+    }
+
+    pub(crate) fn delivery(&self, message_uuid: UUID) -> Option<DeliveryState> {
+        self.delivery.get(&message_uuid).copied()
+    }
+
+    pub(crate) fn set_delivery(&mut self, message_uuid: UUID, state: DeliveryState) {
+        let _ = self.delivery.insert(message_uuid, state);
+    }
+
+    pub(crate) fn remove_delivery(&mut self, message_uuid: UUID) {
+        let _ = self.delivery.remove(&message_uuid);
+    }
+
+    /// Messages that only exist on this client, so far.
+    pub(crate) fn is_unconfirmed(&self, message_uuid: UUID) -> bool {
+        matches!(self.delivery(message_uuid), Some(DeliveryState::SENDING | DeliveryState::FAILED))
+    }
+
+    pub(crate) fn pinned(&self) -> &[(UUID, UUID)] {
+        &self.pinned
+    }
+
+    pub(crate) fn pinned_by(&self, message_uuid: UUID) -> Option<UUID> {
+        self.pinned
+            .iter()
+            .find(|(message, _)| *message == message_uuid)
+            .map(|(_, user)| *user)
+    }
+
+    pub(crate) fn is_pinned(&self, message_uuid: UUID) -> bool {
+        self.pinned_by(message_uuid).is_some()
+    }
+
+    /// Does nothing if the Message is already pinned, so server echoes are harmless.
+    pub(crate) fn pin(&mut self, message_uuid: UUID, user_uuid: UUID) {
+        if !self.is_pinned(message_uuid) {
+            self.pinned.push((message_uuid, user_uuid));
+        }
+    }
+
+    pub(crate) fn unpin(&mut self, message_uuid: UUID) {
+        self.pinned.retain(|(message, _)| *message != message_uuid);
+    }
+
+    /// Replaces every pin, with the ones the Server or the local cache has.
+    pub(crate) fn set_pinned(&mut self, pinned: Vec<(UUID, UUID)>) {
+        self.pinned = pinned;
+    }
+
+    /// Drops everything kept about a Message that no longer exists.
+    pub(crate) fn forget(&mut self, message_uuid: UUID) {
+        self.remove_delivery(message_uuid);
+        self.unpin(message_uuid);
+        let _ = self.reactions.remove(&message_uuid);
+    }
+}
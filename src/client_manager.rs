@@ -1,6 +1,9 @@
-use std::{borrow::BorrowMut, collections::HashMap, rc::Rc};
-use yapping_core::{chat::Chat, client_server_coms::{DbNotificationType, Modification, Notification, NotificationType, Query, Response, ServerMessage, ServerMessageContent, Session}, l3gion_rust::{imgui, lg_core::renderer::Renderer, sllog::{error, info}, Rfc, StdError, UUID}, serde::de::IntoDeserializer, user::User};
-use crate::{gui::{chat_page_gui::ChatGuiManager, config_overlay_gui::ConfigOverlayGuiManager, find_user_gui::FindUserGuiManager, friends_notifications_gui::FriendsNotificationsGuiManager, gui_manager::GuiMannager, show_loading_gui, sidebar_gui::SidebarGuiManager, theme::Theme, validation_gui::validation_gui_manager::ValidationGuiManager}, server_coms::{self, ServerCommunication}};
+use std::{borrow::BorrowMut, collections::{HashMap, HashSet}, rc::Rc};
+use yapping_core::{chat::Chat, chrono, client_server_coms::{DbNotificationType, Modification, Notification, NotificationType, Query, Response, ServerMessage, ServerMessageContent, Session}, date_time::DateTime, l3gion_rust::{imgui, lg_core::renderer::Renderer, sllog::{error, info}, AsLgTime, LgTimer, Rfc, StdError, UUID}, message::{Message, MessageType}, serde::de::IntoDeserializer, user::User};
+use crate::{bookmarks::Bookmark, chat_state::{ChatState, DeliveryState}, e2e::E2e, expiry, gui::{bookmarks_gui::BookmarksGuiManager, chat_page_gui::ChatGuiManager, commands::Command, config_overlay_gui::ConfigOverlayGuiManager, export_gui::ExportGuiManager, find_user_gui::FindUserGuiManager, friends_notifications_gui::FriendsNotificationsGuiManager, gui_manager::GuiMannager, mentions, search_gui::{SearchGuiManager, SearchScope}, show_offline_banner, sidebar_gui::SidebarGuiManager, theme::Theme, time_format::ClockFormat, validation_gui::validation_gui_manager::ValidationGuiManager}, local_store::LocalStore, scheduled::ScheduledMessage, server_coms::{self, ServerCommunication}};
+
+// Time between checks for expired messages.
+const EXPIRY_CHECK_SECONDS: u32 = 1;
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, PartialEq)]
@@ -21,8 +24,27 @@ pub(crate) struct AppState {
 pub(crate) struct SharedMut {
     pub(crate) user: Option<User>,
     pub(crate) chats: HashMap<UUID, Chat>,
+    pub(crate) chat_states: HashMap<UUID, ChatState>,
+    // Users that are not friends, but share a Chat with the current user.
+    pub(crate) users: HashMap<UUID, User>,
     pub(crate) foreground_state: ForegroundState,
     pub(crate) config: bool,
+    // Chat and Message the chat page should scroll to.
+    pub(crate) jump_to: Option<(UUID, UUID)>,
+    // The Server can't be reached, the cached data is shown read only.
+    pub(crate) offline: bool,
+    pub(crate) clock_format: ClockFormat,
+    // Chat UUID, unsent composer text, with mentions encoded.
+    pub(crate) drafts: HashMap<UUID, String>,
+    pub(crate) e2e: E2e,
+    // Messages waiting to be sent by the client, of every Chat.
+    pub(crate) scheduled: Vec<ScheduledMessage>,
+    // Messages the user saved, of every Chat.
+    pub(crate) bookmarks: Vec<Bookmark>,
+    // Chats that don't show the unread badge, mentions still do.
+    pub(crate) muted: HashSet<UUID>,
+    // Chat UUID, time of the last /clear. Older messages are hidden on this device.
+    pub(crate) cleared: HashMap<UUID, chrono::DateTime<chrono::Local>>,
 }
 
 struct GuiManagers {
@@ -32,6 +54,9 @@ struct GuiManagers {
     friends_notifications: FriendsNotificationsGuiManager,
     chat_page: ChatGuiManager,
     config_overlay: ConfigOverlayGuiManager,
+    search: SearchGuiManager,
+    export: ExportGuiManager,
+    bookmarks: BookmarksGuiManager,
 }
 impl GuiManagers {
     fn new(app_state: AppState) -> Self {
@@ -42,6 +67,9 @@ impl GuiManagers {
             friends_notifications: FriendsNotificationsGuiManager::new(app_state.clone()),
             chat_page: ChatGuiManager::new(app_state.clone()),
             config_overlay: ConfigOverlayGuiManager::new(app_state.clone()),
+            search: SearchGuiManager::new(app_state.clone()),
+            export: ExportGuiManager::new(app_state.clone()),
+            bookmarks: BookmarksGuiManager::new(app_state.clone()),
         }
     }
 
@@ -78,6 +106,16 @@ impl GuiManagers {
                 .map_err(|err| errors.push(err))
                 .ok() 
             { continue; }
+
+            if let Some(true) = self.search.on_responded_messages(m, server_coms)
+                .map_err(|err| errors.push(err))
+                .ok() 
+            { continue; }
+
+            if let Some(true) = self.export.on_responded_messages(m, server_coms)
+                .map_err(|err| errors.push(err))
+                .ok() 
+            { continue; }
         }
         
         errors
@@ -98,6 +136,11 @@ pub(crate) struct ClientManager {
     app_state: AppState,
     server_coms: Rfc<ServerCommunication>,
     gui_managers: GuiManagers,
+    local_store: LocalStore,
+    was_connected: bool,
+    retry_connection: bool,
+    // Time since expired messages were last removed.
+    expiry_timer: LgTimer,
     // background: BackgroundState,
 }
 impl ClientManager {
@@ -109,8 +152,19 @@ impl ClientManager {
             shared_mut: Rfc::new(SharedMut {
                 user: None,
                 chats: HashMap::default(),
+                chat_states: HashMap::default(),
+                users: HashMap::default(),
                 foreground_state: ForegroundState::VALIDATION,
                 config: false,
+                jump_to: None,
+                offline: false,
+                clock_format: ClockFormat::from_locale(),
+                drafts: HashMap::default(),
+                e2e: E2e::default(),
+                scheduled: Vec::default(),
+                bookmarks: Vec::default(),
+                muted: HashSet::default(),
+                cleared: HashMap::default(),
             }),
             theme: Rc::clone(&theme),
         };
@@ -119,18 +173,33 @@ impl ClientManager {
             app_state: app_state.clone(),
             server_coms,
             gui_managers: GuiManagers::new(app_state),
+            local_store: LocalStore::new(),
+            was_connected: false,
+            retry_connection: false,
+            expiry_timer: LgTimer::new(),
         }
     } 
 
     pub(crate) fn init(&mut self) -> Result<(), StdError> {
         // TODO: Initialize user if the user has session.
+        // The cached chats show up right away, the server reconciles them after login.
+        if let Err(e) = self.local_store.load_last_account(&mut self.app_state.shared_mut.borrow_mut()) {
+            error!("In ClientManager::init: Failed to load the local cache: {e}");
+        }
+        if let Err(e) = self.local_store.load_settings(&mut self.app_state.shared_mut.borrow_mut()) {
+            error!("In ClientManager::init: Failed to load the settings: {e}");
+        }
+
         Ok(())
     }
 
     pub(crate) fn on_update(&mut self) {
-        if !self.server_coms.borrow().connected() {
-            // TODO: Query All of the information again
+        if std::mem::take(&mut self.retry_connection) {
+            if let Err(e) = self.server_coms.borrow_mut().retry_now() {
+                error!("In ClientManager::on_update: Failed to reconnect: {e}");
+            }
         }
+        self.update_connection();
 
         let foreground = self.app_state.shared_mut.borrow().foreground_state.clone();
         if let Err(e) = match foreground {
@@ -142,6 +211,16 @@ impl ClientManager {
         } {
             error!("{e}");
         }
+
+        if let Err(e) = self.gui_managers.chat_page.send_due_scheduled(&mut self.server_coms.borrow_mut()) {
+            error!("{e}");
+        }
+
+        for (chat_uuid, command) in self.gui_managers.chat_page.take_commands() {
+            if let Err(e) = self.run_command(chat_uuid, command) {
+                error!("{e}");
+            }
+        }
         
         if let Err(e) = self.gui_managers.sidebar.on_update(&mut self.server_coms.borrow_mut()) {
             error!("{e}");
@@ -150,6 +229,35 @@ impl ClientManager {
         if let Err(e) = self.gui_managers.config_overlay.on_update(&mut self.server_coms.borrow_mut()) {
             error!("{e}");
         }
+
+        if let Err(e) = self.gui_managers.search.on_update(&mut self.server_coms.borrow_mut()) {
+            error!("{e}");
+        }
+
+        if let Some((chat_uuid, format)) = self.gui_managers.sidebar.take_export() {
+            self.gui_managers.export.start(chat_uuid, format);
+        }
+        if let Err(e) = self.gui_managers.export.on_update(&mut self.server_coms.borrow_mut()) {
+            error!("{e}");
+        }
+
+        if self.gui_managers.sidebar.take_open_bookmarks() {
+            self.gui_managers.bookmarks.open();
+        }
+        if let Err(e) = self.gui_managers.bookmarks.on_update(&mut self.server_coms.borrow_mut()) {
+            error!("{e}");
+        }
+
+        // Disappearing messages are removed even while offline, the local cache follows.
+        if self.expiry_timer.elapsed() >= EXPIRY_CHECK_SECONDS.s() {
+            self.expiry_timer.restart();
+            let changed = expiry::remove_expired(&mut self.app_state.shared_mut.borrow_mut(), &chrono::Local::now());
+            self.local_store.mark_chats(changed.iter());
+        }
+
+        if let Err(e) = self.local_store.on_update(&mut self.app_state.shared_mut.borrow_mut()) {
+            error!("{e}");
+        }
     }
 
     pub(crate) fn on_responded_messages(&mut self, mut messages: Vec<(ServerMessage, Response)>) -> Result<(), StdError> {
@@ -158,20 +266,95 @@ impl ClientManager {
             match response {
                 Response::OK_QUERY(query) => match query {
                     Query::RESULT_CHAT_MESSAGES(messages) => match message.content {
-                        ServerMessageContent::QUERY(Query::CHAT_MESSAGES(chat_uuid)) => if let Some(chat) = self.app_state.shared_mut.borrow_mut().chats.get_mut(&chat_uuid) {
-                            chat.clear_messages();
-                            chat.append_messages(messages);
+                        ServerMessageContent::QUERY(Query::CHAT_MESSAGES(chat_uuid)) => {
+                            let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+                            reveal_messages(shared_mut, &mut self.server_coms.borrow_mut(), chat_uuid, messages)?;
+                            if let Some(chat) = shared_mut.chats.get_mut(&chat_uuid) {
+                                // The answer can be just a page of the history, so it's merged with what is shown.
+                                // That also keeps the messages still being sent, or that failed, which are not on the server yet.
+                                let mut merged = chat.messages()
+                                    .iter()
+                                    .filter(|m| !messages.iter().any(|sm| sm.uuid() == m.uuid()))
+                                    .cloned()
+                                    .collect::<Vec<_>>();
+                                merged.append(messages);
+                                merged.sort_by_key(|m| m.date_time().to_local().ok());
+
+                                chat.clear_messages();
+                                chat.append_messages(&mut merged);
+                                self.local_store.mark_chat(chat_uuid);
+                            }
                         },
                         _ => error!("In ClientManager::on_responded_messages: Wrong response from server!"),
                     },
                     Query::RESULT_CHATS(chats) => {
-                        let current_chats = &mut self.app_state.shared_mut.borrow_mut().chats;
-                        current_chats.clear();
-
-                        *current_chats = chats.clone()
+                        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+                        let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+                        let mut server_chats: HashMap<UUID, Chat> = chats.clone()
                             .into_iter()
                             .map(|chat| (chat.uuid(), chat))
                             .collect();
+                        
+                        // The server decides which chats exist, the cache fills in the messages it didn't send.
+                        for (chat_uuid, chat) in &mut server_chats {
+                            let mut messages = chat.messages().to_vec();
+                            if messages.iter().any(|m| matches!(m.content(), MessageType::TEXT(_))) {
+                                reveal_messages(shared_mut, &mut self.server_coms.borrow_mut(), *chat_uuid, &mut messages)?;
+                                chat.clear_messages();
+                                chat.append_messages(&mut messages);
+                            }
+
+                            if let Some(cached) = shared_mut.chats.get(chat_uuid) {
+                                if chat.messages().is_empty() {
+                                    chat.append_messages(&mut cached.messages().to_vec());
+                                }
+                                // The messages still being sent, or that failed, are not on the server yet.
+                                else if let Some(chat_state) = shared_mut.chat_states.get(chat_uuid) {
+                                    let mut merged = cached.messages()
+                                        .iter()
+                                        .filter(|m| chat_state.is_unconfirmed(m.uuid()) && !chat.messages().iter().any(|sm| sm.uuid() == m.uuid()))
+                                        .cloned()
+                                        .collect::<Vec<_>>();
+
+                                    if !merged.is_empty() {
+                                        merged.extend(chat.messages().iter().cloned());
+                                        merged.sort_by_key(|m| m.date_time().to_local().ok());
+
+                                        chat.clear_messages();
+                                        chat.append_messages(&mut merged);
+                                    }
+                                }
+                            }
+                            let chat_state = shared_mut.chat_states.entry(*chat_uuid).or_default();
+                            chat_state.set_pinned(chat.pinned().to_vec());
+                            chat_state.set_reactions(chat.reactions().clone());
+                            if let Some(current_user) = current_user {
+                                count_unread(chat_state, chat, current_user);
+                            }
+                        }
+                        // Chats we left or were removed from while away.
+                        let removed = shared_mut.chats
+                            .keys()
+                            .filter(|chat_uuid| !server_chats.contains_key(chat_uuid))
+                            .copied()
+                            .collect::<Vec<_>>();
+                        for chat_uuid in removed {
+                            forget_chat(shared_mut, chat_uuid);
+                        }
+                        shared_mut.chats = server_chats;
+                        self.local_store.mark_chats(shared_mut.chats.keys());
+                        
+                        request_unknown_users(shared_mut, &mut self.server_coms.borrow_mut())?;
+                    },
+                    Query::RESULT_USER(users) => match message.content {
+                        ServerMessageContent::QUERY(Query::USERS_BY_UUID(_)) => {
+                            self.app_state.shared_mut
+                                .borrow_mut()
+                                .users
+                                .extend(users.iter().map(|user| (user.uuid(), user.clone())));
+                            self.local_store.mark_account();
+                        },
+                        _ => (),
                     },
                     _ => (),
                 }
@@ -200,18 +383,146 @@ impl ClientManager {
                     server_coms.send(ServerMessage::from(ServerMessageContent::QUERY(Query::USER_CHATS)))?;
                 }
                 ServerMessageContent::NOTIFICATION(notification) => match notification.notification_type {
-                    NotificationType::NEW_MESSAGE(chat_uuid, message) => if self.app_state.shared_mut
+                    NotificationType::NEW_MESSAGE(chat_uuid, message) => {
+                        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+                        let mut revealed = vec![message];
+                        reveal_messages(shared_mut, &mut server_coms, chat_uuid, &mut revealed)?;
+                        let message = revealed.remove(0);
+
+                        let chat_state = shared_mut.chat_states.entry(chat_uuid).or_default();
+                        chat_state.set_typing(message.sender(), false);
+                        // Nobody sends a message without reading the ones before it.
+                        chat_state.set_read(message.sender(), message.uuid());
+                        // Cleared by the chat page if this Chat is open.
+                        let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+                        if current_user != Some(message.sender()) {
+                            // Thread replies are not on the timeline, they would be unread messages nobody can find.
+                            if message.thread_root().is_none() {
+                                chat_state.add_unread();
+                            }
+
+                            if let (Some(current_user), MessageType::TEXT(text), None) = (current_user, message.content(), message.thread_root()) {
+                                if mentions::mentions(text, current_user) {
+                                    chat_state.add_unread_mention();
+                                }
+                            }
+                        }
+                        else {
+                            // Our own message coming back, it was already shown when sent.
+                            chat_state.set_delivery(message.uuid(), DeliveryState::DELIVERED);
+                        }
+
+                        if shared_mut.chats
+                            .get_mut(&chat_uuid)
+                            .map(|chat| if !chat.messages().iter().any(|m| m.uuid() == message.uuid()) {
+                                chat.push_message(message)
+                            })
+                            .is_none()
+                        {
+                            error!("In ClientManager::on_received_messages: Got a NEW_MESSAGE for a Chat that does't exist on client side!");
+                        }
+                        self.local_store.mark_chat(chat_uuid);
+                    },
+
+                    NotificationType::REACTION_ADDED(chat_uuid, message_uuid, user_uuid, emoji) => self.app_state.shared_mut
+                        .borrow_mut()
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .add_reaction(message_uuid, user_uuid, &emoji),
+
+                    NotificationType::REACTION_REMOVED(chat_uuid, message_uuid, user_uuid, emoji) => self.app_state.shared_mut
+                        .borrow_mut()
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .remove_reaction(message_uuid, user_uuid, &emoji),
+
+                    NotificationType::TYPING_START(chat_uuid, user_uuid) => self.app_state.shared_mut
+                        .borrow_mut()
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .set_typing(user_uuid, true),
+
+                    NotificationType::TYPING_STOP(chat_uuid, user_uuid) => self.app_state.shared_mut
+                        .borrow_mut()
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .set_typing(user_uuid, false),
+
+                    NotificationType::CHAT_UPDATED(mut chat) => {
+                        self.local_store.mark_chat(chat.uuid());
+                        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+                        let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+
+                        if current_user.map(|u| chat.users().contains(&u)).unwrap_or(false) {
+                            // Updates only carry the Chat info, the messages we already have are kept.
+                            if let Some(old_chat) = shared_mut.chats.get(&chat.uuid()) {
+                                chat.clear_messages();
+                                chat.append_messages(&mut old_chat.messages().to_vec());
+                            }
+                            let chat_state = shared_mut.chat_states.entry(chat.uuid()).or_default();
+                            chat_state.set_pinned(chat.pinned().to_vec());
+                            chat_state.set_reactions(chat.reactions().clone());
+                            let _ = shared_mut.chats.insert(chat.uuid(), chat);
+                            request_unknown_users(shared_mut, &mut server_coms)?;
+                        }
+                        // We were removed from the Chat.
+                        else {
+                            forget_chat(shared_mut, chat.uuid());
+                        }
+                    },
+
+                    NotificationType::NEW_CHAT(chat) => {
+                        self.local_store.mark_chat(chat.uuid());
+                        let _ = self.app_state.shared_mut.borrow_mut().chats.insert(chat.uuid(), chat);
+                        request_unknown_users(&self.app_state.shared_mut.borrow(), &mut server_coms)?;
+                    },
+                    // Only says that someone read the Chat, the receipts come as MESSAGE_READ_UP_TO.
+                    NotificationType::MESSAGE_READ(_) => (),
+                    NotificationType::MESSAGE_READ_UP_TO(chat_uuid, user_uuid, message_uuid) => self.app_state.shared_mut
+                        .borrow_mut()
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .set_read(user_uuid, message_uuid),
+
+                    NotificationType::MESSAGE_PINNED(chat_uuid, message_uuid, user_uuid) => self.app_state.shared_mut
+                        .borrow_mut()
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .pin(message_uuid, user_uuid),
+
+                    NotificationType::MESSAGE_UNPINNED(chat_uuid, message_uuid, _) => self.app_state.shared_mut
                         .borrow_mut()
-                        .chats
-                        .get_mut(&chat_uuid)
-                        .map(|chat| chat.push_message(message))
-                        .is_none()
-                    {
-                        error!("In ClientManager::on_received_messages: Got a NEW_MESSAGE for a Chat that does't exist on client side!");
+                        .chat_states
+                        .entry(chat_uuid)
+                        .or_default()
+                        .unpin(message_uuid),
+
+                    // Bookmarks are copies, so they keep the deleted Message.
+                    NotificationType::MESSAGE_DELETED(chat_uuid, message_uuid) => {
+                        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+                        if let Some(chat) = shared_mut.chats.get_mut(&chat_uuid) {
+                            let mut messages = chat.messages()
+                                .iter()
+                                .filter(|m| m.uuid() != message_uuid)
+                                .cloned()
+                                .collect::<Vec<_>>();
+
+                            chat.clear_messages();
+                            chat.append_messages(&mut messages);
+                        }
+                        if let Some(chat_state) = shared_mut.chat_states.get_mut(&chat_uuid) {
+                            chat_state.forget(message_uuid);
+                        }
+                        shared_mut.e2e.forget(message_uuid);
+                        self.local_store.mark_chat(chat_uuid);
                     },
 
-                    NotificationType::NEW_CHAT(chat) => { let _ = self.app_state.shared_mut.borrow_mut().chats.insert(chat.uuid(), chat); },
-                    NotificationType::MESSAGE_READ(_) => panic!("In ClientManager::on_received_messages: Received MESSAGE_READ(), this shoudn't happen!"),
                     NotificationType::MESSAGE(_) => panic!("In ClientManager::on_received_messages: Received MESSAGE(), this shoudn't happen!"),
                     _ => ()
                 },
@@ -225,12 +536,6 @@ impl ClientManager {
     }
 
     pub(crate) fn on_imgui(&mut self, ui: &mut imgui::Ui, renderer: &Renderer) {
-        if !self.server_coms.borrow().connected() {
-            show_loading_gui(ui, renderer, [0.0, 0.0], ui.io().display_size, self.app_state.theme.main_bg_color);
-
-            return;
-        }
-
         {
             let shared_mut = &mut self.app_state.shared_mut.borrow_mut();
             if shared_mut.config {
@@ -240,6 +545,7 @@ impl ClientManager {
         }
 
         self.gui_managers.config_overlay.on_imgui(ui, renderer);
+        self.handle_shortcuts(ui);
 
         match &self.app_state.shared_mut.borrow().foreground_state {
             ForegroundState::MAIN_PAGE => {
@@ -259,6 +565,60 @@ impl ClientManager {
                 self.gui_managers.find_user.on_imgui(ui, renderer);
             },
         }
+
+        self.gui_managers.search.on_imgui(ui, renderer);
+        self.gui_managers.export.on_imgui(ui, renderer);
+        self.gui_managers.bookmarks.on_imgui(ui, renderer);
+
+        if self.app_state.shared_mut.borrow().offline {
+            let reconnect_in = self.server_coms.borrow().reconnect_in();
+            self.retry_connection = show_offline_banner(ui, &self.app_state.theme, reconnect_in) || self.retry_connection;
+        }
+    }
+
+    // Keeps the UI usable while offline, with whatever is cached.
+    fn update_connection(&mut self) {
+        let connected = self.server_coms.borrow().connected();
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+        shared_mut.offline = !connected;
+
+        if connected && !self.was_connected {
+            // A new connection has no session, so the user signs in again. The cached data stays until then.
+            shared_mut.foreground_state = ForegroundState::VALIDATION;
+        }
+        else if !connected && shared_mut.foreground_state == ForegroundState::VALIDATION && shared_mut.user.is_some() {
+            // Can't sign in without the Server, but the last account can still be browsed.
+            shared_mut.foreground_state = ForegroundState::MAIN_PAGE;
+        }
+
+        self.was_connected = connected;
+    }
+    
+    fn handle_shortcuts(&mut self, ui: &imgui::Ui) {
+        let foreground = self.app_state.shared_mut.borrow().foreground_state.clone();
+        if foreground == ForegroundState::VALIDATION || !ui.io().key_ctrl || !ui.is_key_pressed(imgui::Key::F) { return; }
+
+        if ui.io().key_shift {
+            self.gui_managers.search.open(SearchScope::ALL);
+        }
+        else if let (ForegroundState::CHAT_PAGE(_), Some(chat_uuid)) = (foreground, self.gui_managers.chat_page.chat_uuid()) {
+            self.gui_managers.search.open(SearchScope::CHAT(chat_uuid));
+        }
+    }
+    
+    // Composer commands the chat page hands over.
+    fn run_command(&mut self, chat_uuid: UUID, command: Command) -> Result<(), StdError> {
+        match command {
+            Command::NICK(tag) => if let Some(user) = &self.app_state.shared_mut.borrow().user {
+                self.server_coms.borrow_mut().send(ServerMessage::from(ServerMessageContent::MODIFICATION(Modification::USER_TAG(user.uuid(), tag))))?;
+            },
+            Command::MUTE => self.gui_managers.sidebar.toggle_mute(chat_uuid),
+            Command::SEARCH(query) => self.gui_managers.search.open_with_query(SearchScope::CHAT(chat_uuid), query),
+            Command::EXPORT(format) => self.gui_managers.export.start(chat_uuid, format),
+            _ => return Err(std::format!("In ClientManager::run_command: {:?} is run by the chat page!", command).into()),
+        }
+
+        Ok(())
     }
     
     pub(crate) fn show_debug_gui(&self, ui: &imgui::Ui) {
@@ -293,8 +653,81 @@ impl ClientManager {
     }
 
     pub(crate) fn shutdown(&mut self) -> Result<(), StdError> {
-        error!("Shutdown: Not Implemented!");
+        self.local_store.flush(&mut self.app_state.shared_mut.borrow_mut())?;
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Encrypted messages are decrypted before anything else sees them, and the contact gets our key if they asked for it.
+fn reveal_messages(shared_mut: &mut SharedMut, server_coms: &mut ServerCommunication, chat_uuid: UUID, messages: &mut [Message]) -> Result<(), StdError> {
+    let current_user = if let Some(user) = &shared_mut.user { user.uuid() }
+    else { return Ok(()); };
+
+    let mut asked_by = None;
+    for message in messages.iter_mut() {
+        if shared_mut.e2e.reveal(chat_uuid, current_user, message) {
+            asked_by = Some(message.sender());
+        }
+    }
+
+    if let Some(key) = asked_by.and_then(|peer| shared_mut.e2e.enable(chat_uuid, peer)) {
+        let key_message = Message::new(current_user, MessageType::TEXT(key), DateTime::from_utc(&chrono::Utc::now()));
+        server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::NEW_MESSAGE(chat_uuid, key_message)))))?;
+    }
+
+    Ok(())
+}
+
+// Asks the server for the members of our chats that we don't know yet.
+/// Drops a Chat we are no longer in, with everything kept about it on this client.
+pub(crate) fn forget_chat(shared_mut: &mut SharedMut, chat_uuid: UUID) {
+    if let Some(chat) = shared_mut.chats.remove(&chat_uuid) {
+        shared_mut.e2e.forget_chat(chat_uuid, chat.messages());
+    }
+    let _ = shared_mut.chat_states.remove(&chat_uuid);
+    let _ = shared_mut.drafts.remove(&chat_uuid);
+    let _ = shared_mut.muted.remove(&chat_uuid);
+    let _ = shared_mut.cleared.remove(&chat_uuid);
+}
+
+// Messages from others after the last one the current user read, or sent, are unread.
+fn count_unread(chat_state: &mut ChatState, chat: &Chat, user_uuid: UUID) {
+    let last_read = chat_state.last_read(user_uuid);
+    let messages = chat.messages();
+
+    // Without a read marker there's no telling, the counts are left as they are.
+    let start = match messages.iter().rposition(|m| Some(m.uuid()) == last_read || m.sender() == user_uuid) {
+        Some(i) => i + 1,
+        None => return,
+    };
+
+    // Thread replies are not on the timeline, like with NEW_MESSAGE.
+    let unread = messages[start..]
+        .iter()
+        .filter(|m| m.sender() != user_uuid && m.thread_root().is_none());
+    let unread_mentions = unread
+        .clone()
+        .filter(|m| matches!(m.content(), MessageType::TEXT(text) if mentions::mentions(text, user_uuid)))
+        .count();
+
+    chat_state.set_unread(unread.count(), unread_mentions);
+}
+
+fn request_unknown_users(shared_mut: &SharedMut, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+    let current_user = if let Some(user) = &shared_mut.user { user }
+    else { return Ok(()); };
+
+    let unknown = shared_mut.chats
+        .values()
+        .flat_map(|chat| chat.users().iter().copied())
+        .filter(|u| *u != current_user.uuid()
+            && !shared_mut.users.contains_key(u)
+            && !current_user.friends().iter().any(|f| f.uuid() == *u)
+        )
+        .collect::<HashSet<_>>();
+
+    if unknown.is_empty() { return Ok(()); }
+
+    server_coms.send(ServerMessage::from(ServerMessageContent::QUERY(Query::USERS_BY_UUID(unknown.into_iter().collect()))))
+}
@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use chacha20poly1305::{aead::{Aead, KeyInit, Payload}, Key, XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+use yapping_core::{l3gion_rust::{StdError, UUID}, message::{Message, MessageType}};
+
+// Encrypted payloads travel as TEXT messages with these prefixes, so the Server doesn't need to know about them.
+const KEY_PREFIX: &str = "e2e:key:";
+const MESSAGE_PREFIX: &str = "e2e:msg:";
+const KEY_NOTICE: &str = "*Turned on end-to-end encryption*";
+const UNREADABLE_NOTICE: &str = "*Encrypted message, it can't be read on this device*";
+// Messages that may be missing in a row before we give up on decrypting.
+const MAX_SKIP: u32 = 1000;
+
+type Bytes32 = [u8; 32];
+// Root, own ratchet secret, remote ratchet key, sending chain, receiving chain, sent, received, previous chain length, skipped keys.
+type RatchetBytes = (Bytes32, Bytes32, Option<Bytes32>, Bytes32, Option<Bytes32>, u32, u32, u32, Vec<((Bytes32, u32), Bytes32)>);
+// Peer, peer identity key, enabled, sent our key, verified, peer key changed, ratchet.
+type SessionBytes = (UUID, Option<Bytes32>, bool, bool, bool, bool, Option<RatchetBytes>);
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum E2eStatus {
+    OFF,
+    // We sent our key, the contact didn't answer with theirs yet.
+    WAITING,
+    ON { verified: bool },
+    // The contact has a new identity key, the safety number has to be compared again.
+    KEY_CHANGED,
+}
+
+/// End-to-end encryption of direct Chats, a Double Ratchet over X25519 with XChaCha20-Poly1305.
+///
+/// Identity keys belong to this device. Messages can't be decrypted twice, so the plaintexts are kept locally.
+/// Both are written to e2e.bin unencrypted, so they are as safe as the user's data directory.
+#[derive(Default)]
+pub(crate) struct E2e {
+    identity: Option<Bytes32>,
+    sessions: HashMap<UUID, Session>,
+    // Message UUID, plaintext. Also holds our own encrypted messages.
+    plaintexts: HashMap<UUID, String>,
+    dirty: bool,
+}
+impl E2e {
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, StdError> {
+        let (identity, sessions, plaintexts): (Option<Bytes32>, Vec<(UUID, SessionBytes)>, HashMap<UUID, String>) = yapping_core::bincode::deserialize(bytes)?;
+
+        Ok(Self {
+            identity,
+            sessions: sessions.into_iter().map(|(chat_uuid, session)| (chat_uuid, Session::from_bytes(session))).collect(),
+            plaintexts,
+            dirty: false,
+        })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>, StdError> {
+        let sessions = self.sessions
+            .iter()
+            .map(|(chat_uuid, session)| (*chat_uuid, session.to_bytes()))
+            .collect::<Vec<_>>();
+
+        Ok(yapping_core::bincode::serialize(&(self.identity, sessions, &self.plaintexts))?)
+    }
+
+    /// Creates the identity key of this device, if there is none yet.
+    pub(crate) fn ensure_identity(&mut self) {
+        if self.identity.is_none() {
+            self.identity = Some(StaticSecret::random_from_rng(OsRng).to_bytes());
+            self.dirty = true;
+        }
+    }
+
+    /// True once after every change that should be written to disk.
+    pub(crate) fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub(crate) fn status(&self, chat_uuid: UUID) -> E2eStatus {
+        match self.sessions.get(&chat_uuid) {
+            Some(session) if session.key_changed => E2eStatus::KEY_CHANGED,
+            Some(session) if session.enabled && session.peer_identity.is_some() => E2eStatus::ON { verified: session.verified },
+            Some(session) if session.enabled => E2eStatus::WAITING,
+            _ => E2eStatus::OFF,
+        }
+    }
+
+    /// Turns encryption on for a direct Chat, returns the message with our key for the contact.
+    pub(crate) fn enable(&mut self, chat_uuid: UUID, peer: UUID) -> Option<String> {
+        let key_message = self.key_message()?;
+
+        let session = self.sessions.entry(chat_uuid).or_insert_with(|| Session::new(peer));
+        session.enabled = true;
+        session.sent_key = true;
+        self.dirty = true;
+
+        Some(key_message)
+    }
+
+    /// Marks the contact as verified after comparing the safety numbers, or accepts a changed key.
+    pub(crate) fn set_verified(&mut self, chat_uuid: UUID, verified: bool) {
+        if let Some(session) = self.sessions.get_mut(&chat_uuid) {
+            session.verified = verified;
+            session.key_changed = false;
+            self.dirty = true;
+        }
+    }
+
+    /// The text to send instead of the plaintext, None if the Chat is not encrypted.
+    ///
+    /// Errors while encryption is turned on but can't be used yet, the message must not go out in plaintext.
+    pub(crate) fn encrypt(&mut self, chat_uuid: UUID, current_user: UUID, message_uuid: UUID, plaintext: &str) -> Result<Option<String>, StdError> {
+        match self.status(chat_uuid) {
+            E2eStatus::OFF => return Ok(None),
+            E2eStatus::WAITING => return Err("In E2e::encrypt: The contact didn't send their key yet!".into()),
+            E2eStatus::KEY_CHANGED => return Err("In E2e::encrypt: The contact's key changed, compare the safety number first!".into()),
+            E2eStatus::ON { .. } => (),
+        }
+
+        let ratchet = self.ratchet(chat_uuid, current_user)?;
+        let wire = ratchet.encrypt(chat_uuid, plaintext.as_bytes())?;
+
+        let _ = self.plaintexts.insert(message_uuid, plaintext.to_string());
+        self.dirty = true;
+
+        Ok(Some(std::format!("{MESSAGE_PREFIX}{}", hex_encode(&wire))))
+    }
+
+    /// Replaces encrypted content with what can be shown, in the order messages arrive.
+    ///
+    /// Returns true when the contact sent their key and is still waiting for ours.
+    pub(crate) fn reveal(&mut self, chat_uuid: UUID, current_user: UUID, message: &mut Message) -> bool {
+        let text = if let MessageType::TEXT(text) = message.content() { text.clone() }
+        else { return false; };
+
+        if let Some(plaintext) = self.plaintexts.get(&message.uuid()) {
+            message.set_content(MessageType::TEXT(plaintext.clone()));
+            return false;
+        }
+
+        if let Some(key) = text.strip_prefix(KEY_PREFIX) {
+            message.set_content(MessageType::TEXT(String::from(KEY_NOTICE)));
+            // Processed only once, an old key further back in the history must not replace a newer one.
+            let _ = self.plaintexts.insert(message.uuid(), String::from(KEY_NOTICE));
+            self.dirty = true;
+
+            if message.sender() == current_user { return false; }
+            return match hex_decode(key).ok().and_then(|key| Bytes32::try_from(key).ok()) {
+                Some(key) => self.receive_key(chat_uuid, message.sender(), key),
+                None => false,
+            };
+        }
+
+        if let Some(wire) = text.strip_prefix(MESSAGE_PREFIX) {
+            // Our own messages are only readable through the plaintexts we kept when sending.
+            let plaintext = if message.sender() == current_user { None }
+            else { self.decrypt(chat_uuid, current_user, wire).ok() };
+
+            match plaintext {
+                Some(plaintext) => {
+                    let _ = self.plaintexts.insert(message.uuid(), plaintext.clone());
+                    self.dirty = true;
+                    message.set_content(MessageType::TEXT(plaintext));
+                },
+                None => message.set_content(MessageType::TEXT(String::from(UNREADABLE_NOTICE))),
+            }
+        }
+
+        false
+    }
+
+    /// Drops the plaintext of a Message that no longer exists.
+    pub(crate) fn forget(&mut self, message_uuid: UUID) {
+        if self.plaintexts.remove(&message_uuid).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// Drops the session of a Chat we are no longer in, and the plaintexts of its messages.
+    pub(crate) fn forget_chat(&mut self, chat_uuid: UUID, messages: &[Message]) {
+        if self.sessions.remove(&chat_uuid).is_some() {
+            self.dirty = true;
+        }
+        for message in messages {
+            self.forget(message.uuid());
+        }
+    }
+
+    /// 60 digits, the same on both devices when nobody is in the middle.
+    pub(crate) fn safety_number(&self, chat_uuid: UUID, current_user: UUID) -> Option<String> {
+        let session = self.sessions.get(&chat_uuid)?;
+        let own = PublicKey::from(&StaticSecret::from(self.identity?)).to_bytes();
+
+        let mut halves = [fingerprint(current_user, &own), fingerprint(session.peer, &session.peer_identity?)];
+        halves.sort();
+
+        Some(halves.concat())
+    }
+}
+// Private
+impl E2e {
+    fn key_message(&self) -> Option<String> {
+        let identity = StaticSecret::from(self.identity?);
+        Some(std::format!("{KEY_PREFIX}{}", hex_encode(PublicKey::from(&identity).as_bytes())))
+    }
+
+    fn receive_key(&mut self, chat_uuid: UUID, peer: UUID, key: Bytes32) -> bool {
+        let session = self.sessions.entry(chat_uuid).or_insert_with(|| Session::new(peer));
+
+        if session.peer_identity != Some(key) {
+            // A new device or reinstall on their side, so everything starts over.
+            if session.peer_identity.is_some() {
+                session.key_changed = true;
+                session.verified = false;
+                session.sent_key = false;
+            }
+            session.peer_identity = Some(key);
+            session.ratchet = None;
+        }
+        // The contact turned it on, so we follow.
+        session.enabled = true;
+        self.dirty = true;
+
+        !std::mem::replace(&mut session.sent_key, true)
+    }
+
+    fn decrypt(&mut self, chat_uuid: UUID, current_user: UUID, wire: &str) -> Result<String, StdError> {
+        let wire = hex_decode(wire)?;
+        let ratchet = self.ratchet(chat_uuid, current_user)?;
+
+        // Changes are only kept if the message could be decrypted.
+        let mut updated = ratchet.clone();
+        let plaintext = updated.decrypt(chat_uuid, &wire)?;
+        *ratchet = updated;
+        self.dirty = true;
+
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    // Started on first use, the user with the greater UUID takes the initiator role.
+    fn ratchet(&mut self, chat_uuid: UUID, current_user: UUID) -> Result<&mut Ratchet, StdError> {
+        let identity = self.identity.ok_or("In E2e::ratchet: This device has no identity key!")?;
+        let session = self.sessions.get_mut(&chat_uuid).ok_or("In E2e::ratchet: Chat is not encrypted!")?;
+        let peer_identity = session.peer_identity.ok_or("In E2e::ratchet: Missing the contact's key!")?;
+
+        if session.ratchet.is_none() {
+            let initiator = current_user.to_string() > session.peer.to_string();
+            session.ratchet = Some(Ratchet::new(initiator, identity, peer_identity)?);
+        }
+
+        Ok(session.ratchet.as_mut().expect("Just created"))
+    }
+}
+
+struct Session {
+    peer: UUID,
+    peer_identity: Option<Bytes32>,
+    enabled: bool,
+    sent_key: bool,
+    verified: bool,
+    key_changed: bool,
+    ratchet: Option<Ratchet>,
+}
+impl Session {
+    fn new(peer: UUID) -> Self {
+        Self {
+            peer,
+            peer_identity: None,
+            enabled: false,
+            sent_key: false,
+            verified: false,
+            key_changed: false,
+            ratchet: None,
+        }
+    }
+
+    fn from_bytes((peer, peer_identity, enabled, sent_key, verified, key_changed, ratchet): SessionBytes) -> Self {
+        Self { peer, peer_identity, enabled, sent_key, verified, key_changed, ratchet: ratchet.map(Ratchet::from_bytes) }
+    }
+
+    fn to_bytes(&self) -> SessionBytes {
+        (self.peer, self.peer_identity, self.enabled, self.sent_key, self.verified, self.key_changed, self.ratchet.as_ref().map(Ratchet::to_bytes))
+    }
+}
+
+#[derive(Clone)]
+struct Ratchet {
+    root: Bytes32,
+    dh_secret: Bytes32,
+    dh_remote: Option<Bytes32>,
+    send_chain: Bytes32,
+    recv_chain: Option<Bytes32>,
+    sent: u32,
+    received: u32,
+    previous: u32,
+    // (Ratchet key, message number), message key.
+    skipped: HashMap<(Bytes32, u32), Bytes32>,
+}
+impl Ratchet {
+    // The responder can send before hearing from the initiator, on a chain both derive from the identity keys.
+    fn new(initiator: bool, identity: Bytes32, peer_identity: Bytes32) -> Result<Self, StdError> {
+        let root = derive_key(None, &dh(&identity, &peer_identity), b"yapping-e2e-root")?;
+        let initial_chain = derive_key(None, &root, b"yapping-e2e-initial")?;
+
+        if initiator {
+            let dh_secret = StaticSecret::random_from_rng(OsRng).to_bytes();
+            let (root, send_chain) = kdf_root(&root, &dh(&dh_secret, &peer_identity))?;
+
+            Ok(Self { root, dh_secret, dh_remote: Some(peer_identity), send_chain, recv_chain: Some(initial_chain), sent: 0, received: 0, previous: 0, skipped: HashMap::default() })
+        }
+        else {
+            Ok(Self { root, dh_secret: identity, dh_remote: None, send_chain: initial_chain, recv_chain: None, sent: 0, received: 0, previous: 0, skipped: HashMap::default() })
+        }
+    }
+
+    fn from_bytes((root, dh_secret, dh_remote, send_chain, recv_chain, sent, received, previous, skipped): RatchetBytes) -> Self {
+        Self { root, dh_secret, dh_remote, send_chain, recv_chain, sent, received, previous, skipped: skipped.into_iter().collect() }
+    }
+
+    fn to_bytes(&self) -> RatchetBytes {
+        let skipped = self.skipped.iter().map(|(id, key)| (*id, *key)).collect();
+        (self.root, self.dh_secret, self.dh_remote, self.send_chain, self.recv_chain, self.sent, self.received, self.previous, skipped)
+    }
+
+    fn encrypt(&mut self, chat_uuid: UUID, plaintext: &[u8]) -> Result<Vec<u8>, StdError> {
+        let (send_chain, message_key) = kdf_chain(&self.send_chain)?;
+        self.send_chain = send_chain;
+
+        let header = (PublicKey::from(&StaticSecret::from(self.dh_secret)).to_bytes(), self.sent, self.previous);
+        self.sent += 1;
+
+        let ciphertext = seal(&message_key, &associated_data(chat_uuid, &header)?, plaintext)?;
+        Ok(yapping_core::bincode::serialize(&(header, ciphertext))?)
+    }
+
+    fn decrypt(&mut self, chat_uuid: UUID, wire: &[u8]) -> Result<Vec<u8>, StdError> {
+        let (header, ciphertext): ((Bytes32, u32, u32), Vec<u8>) = yapping_core::bincode::deserialize(wire)?;
+        let (dh_remote, number, previous) = header;
+        let associated_data = associated_data(chat_uuid, &header)?;
+
+        // Arrived after a newer one.
+        if let Some(message_key) = self.skipped.remove(&(dh_remote, number)) {
+            return open(&message_key, &associated_data, &ciphertext);
+        }
+
+        if self.dh_remote != Some(dh_remote) {
+            self.skip(previous)?;
+            self.step(dh_remote)?;
+        }
+        self.skip(number)?;
+
+        let (recv_chain, message_key) = kdf_chain(&self.recv_chain.ok_or("In Ratchet::decrypt: No receiving chain!")?)?;
+        self.recv_chain = Some(recv_chain);
+        self.received += 1;
+
+        open(&message_key, &associated_data, &ciphertext)
+    }
+
+    // Keeps the keys of the messages that didn't arrive yet on the current receiving chain.
+    fn skip(&mut self, until: u32) -> Result<(), StdError> {
+        let (mut recv_chain, dh_remote) = match (self.recv_chain, self.dh_remote) {
+            (Some(recv_chain), Some(dh_remote)) => (recv_chain, dh_remote),
+            _ => return Ok(()),
+        };
+        if until > self.received + MAX_SKIP { return Err("In Ratchet::skip: Too many missing messages!".into()); }
+
+        while self.received < until {
+            let (next, message_key) = kdf_chain(&recv_chain)?;
+            let _ = self.skipped.insert((dh_remote, self.received), message_key);
+            recv_chain = next;
+            self.received += 1;
+        }
+        self.recv_chain = Some(recv_chain);
+
+        Ok(())
+    }
+
+    // The contact has a new ratchet key, so we get new chains and a new key of our own.
+    fn step(&mut self, dh_remote: Bytes32) -> Result<(), StdError> {
+        self.previous = self.sent;
+        self.sent = 0;
+        self.received = 0;
+        self.dh_remote = Some(dh_remote);
+
+        let (root, recv_chain) = kdf_root(&self.root, &dh(&self.dh_secret, &dh_remote))?;
+        self.dh_secret = StaticSecret::random_from_rng(OsRng).to_bytes();
+        let (root, send_chain) = kdf_root(&root, &dh(&self.dh_secret, &dh_remote))?;
+
+        self.root = root;
+        self.recv_chain = Some(recv_chain);
+        self.send_chain = send_chain;
+
+        Ok(())
+    }
+}
+
+fn dh(secret: &Bytes32, public: &Bytes32) -> Bytes32 {
+    StaticSecret::from(*secret).diffie_hellman(&PublicKey::from(*public)).to_bytes()
+}
+
+fn derive_key(salt: Option<&[u8]>, input: &[u8], info: &[u8]) -> Result<Bytes32, StdError> {
+    let mut output = [0; 32];
+    Hkdf::<Sha256>::new(salt, input).expand(info, &mut output).map_err(|e| e.to_string())?;
+
+    Ok(output)
+}
+
+// New root key and chain key.
+fn kdf_root(root: &Bytes32, dh_output: &Bytes32) -> Result<(Bytes32, Bytes32), StdError> {
+    Ok((derive_key(Some(root), dh_output, b"yapping-e2e-ratchet-root")?, derive_key(Some(root), dh_output, b"yapping-e2e-ratchet-chain")?))
+}
+
+// Next chain key and message key.
+fn kdf_chain(chain: &Bytes32) -> Result<(Bytes32, Bytes32), StdError> {
+    Ok((derive_key(None, chain, b"yapping-e2e-chain")?, derive_key(None, chain, b"yapping-e2e-message")?))
+}
+
+fn associated_data(chat_uuid: UUID, header: &(Bytes32, u32, u32)) -> Result<Vec<u8>, StdError> {
+    Ok(yapping_core::bincode::serialize(&(chat_uuid, header))?)
+}
+
+// The ratchet is only written to disk every few seconds, so after a crash a message key can be used again.
+// A random nonce, sent in front of the ciphertext, keeps that safe.
+fn seal(message_key: &Bytes32, associated_data: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, StdError> {
+    let mut nonce = [0; 24];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(message_key))
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: associated_data })
+        .map_err(|_| "In e2e::seal: Failed to encrypt!")?;
+
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+fn open(message_key: &Bytes32, associated_data: &[u8], sealed: &[u8]) -> Result<Vec<u8>, StdError> {
+    if sealed.len() < 24 { return Err("In e2e::open: Missing the nonce!".into()); }
+    let (nonce, ciphertext) = sealed.split_at(24);
+
+    XChaCha20Poly1305::new(Key::from_slice(message_key))
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: associated_data })
+        .map_err(|_| "In e2e::open: Failed to decrypt!".into())
+}
+
+// 30 digits for one side of the safety number.
+fn fingerprint(user_uuid: UUID, identity: &Bytes32) -> String {
+    let digest = Sha256::new()
+        .chain_update(b"yapping-e2e-safety-number")
+        .chain_update(user_uuid.to_string().as_bytes())
+        .chain_update(identity)
+        .finalize();
+
+    digest[..30]
+        .chunks(5)
+        .map(|chunk| std::format!("{:05}", chunk.iter().fold(0u64, |n, byte| (n << 8) | *byte as u64) % 100_000))
+        .collect()
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| std::format!("{byte:02x}")).collect()
+}
+
+fn hex_decode(text: &str) -> Result<Vec<u8>, StdError> {
+    if text.len() % 2 != 0 { return Err("In e2e::hex_decode: Odd length!".into()); }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2).ok_or("In e2e::hex_decode: Invalid text!")?, 16).map_err(|e| e.into()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (E2e, UUID, E2e, UUID) {
+        let (alice, bob) = (UUID::generate(), UUID::generate());
+        let (mut alice_e2e, mut bob_e2e) = (E2e::default(), E2e::default());
+        alice_e2e.ensure_identity();
+        bob_e2e.ensure_identity();
+
+        let chat_uuid = UUID::default();
+        let alice_key = alice_e2e.enable(chat_uuid, bob).unwrap();
+        let bob_key = bob_e2e.enable(chat_uuid, alice).unwrap();
+        assert!(!bob_e2e.reveal(chat_uuid, bob, &mut text_message(alice, &alice_key)));
+        assert!(!alice_e2e.reveal(chat_uuid, alice, &mut text_message(bob, &bob_key)));
+
+        (alice_e2e, alice, bob_e2e, bob)
+    }
+
+    fn text_message(sender: UUID, text: &str) -> Message {
+        Message::new(sender, MessageType::TEXT(text.to_string()), yapping_core::date_time::DateTime::from_utc(&yapping_core::chrono::Utc::now()))
+    }
+
+    fn send(from: &mut E2e, sender: UUID, text: &str) -> Message {
+        let mut message = text_message(sender, text);
+        let wire = from.encrypt(UUID::default(), sender, message.uuid(), text).unwrap().unwrap();
+        message.set_content(MessageType::TEXT(wire));
+
+        message
+    }
+
+    fn read(to: &mut E2e, receiver: UUID, mut message: Message) -> String {
+        to.reveal(UUID::default(), receiver, &mut message);
+        match message.content() {
+            MessageType::TEXT(text) => text.clone(),
+            MessageType::FILE(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn ratchet_round_trip() {
+        let (mut alice_e2e, alice, mut bob_e2e, bob) = pair();
+
+        // Both may talk first, and a side may send many in a row.
+        let early = send(&mut bob_e2e, bob, "hi from bob");
+        let first = send(&mut alice_e2e, alice, "hi from alice");
+        assert_eq!(read(&mut alice_e2e, alice, early), "hi from bob");
+        assert_eq!(read(&mut bob_e2e, bob, first), "hi from alice");
+
+        for i in 0..3 {
+            let reply = send(&mut bob_e2e, bob, &std::format!("reply {i}"));
+            assert_eq!(read(&mut alice_e2e, alice, reply), std::format!("reply {i}"));
+        }
+        let answer = send(&mut alice_e2e, alice, "answer");
+        assert_eq!(read(&mut bob_e2e, bob, answer), "answer");
+    }
+
+    #[test]
+    fn out_of_order_and_safety_number() {
+        let (mut alice_e2e, alice, mut bob_e2e, bob) = pair();
+
+        let one = send(&mut alice_e2e, alice, "one");
+        let two = send(&mut alice_e2e, alice, "two");
+        assert_eq!(read(&mut bob_e2e, bob, two), "two");
+        assert_eq!(read(&mut bob_e2e, bob, one), "one");
+
+        let safety_number = alice_e2e.safety_number(UUID::default(), alice).unwrap();
+        assert_eq!(safety_number.len(), 60);
+        assert_eq!(Some(safety_number), bob_e2e.safety_number(UUID::default(), bob));
+    }
+
+    #[test]
+    fn tampered_message_is_not_revealed() {
+        let (mut alice_e2e, alice, mut bob_e2e, bob) = pair();
+
+        let message = send(&mut alice_e2e, alice, "secret");
+        let tampered = match message.content() {
+            MessageType::TEXT(text) => std::format!("{}{}", &text[..text.len() - 2], if text.ends_with("00") { "01" } else { "00" }),
+            MessageType::FILE(_) => unreachable!(),
+        };
+        assert_eq!(read(&mut bob_e2e, bob, text_message(alice, &tampered)), UNREADABLE_NOTICE);
+        assert_eq!(read(&mut bob_e2e, bob, message), "secret");
+    }
+
+    #[test]
+    fn never_sends_plaintext_while_waiting() {
+        let mut alice_e2e = E2e::default();
+        alice_e2e.ensure_identity();
+        let (alice, bob) = (UUID::generate(), UUID::generate());
+
+        assert!(matches!(alice_e2e.encrypt(UUID::default(), alice, UUID::generate(), "hi"), Ok(None)));
+        alice_e2e.enable(UUID::default(), bob).unwrap();
+        assert!(alice_e2e.encrypt(UUID::default(), alice, UUID::generate(), "hi").is_err());
+    }
+
+    #[test]
+    fn reused_message_key_gets_a_new_nonce() {
+        let key = [7; 32];
+
+        let (first, second) = (seal(&key, b"ad", b"same").unwrap(), seal(&key, b"ad", b"same").unwrap());
+        assert_ne!(first, second);
+        assert_eq!(open(&key, b"ad", &second).unwrap(), b"same");
+    }
+}
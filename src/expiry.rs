@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+use yapping_core::{chrono::{self, Local}, l3gion_rust::UUID, message::Message};
+
+use crate::client_manager::SharedMut;
+
+/// Choices of the disappearing messages setting, in seconds.
+pub(crate) const EXPIRY_OPTIONS: [(&str, Option<u32>); 5] = [
+    ("Off", None),
+    ("30 seconds", Some(30)),
+    ("1 hour", Some(60 * 60)),
+    ("1 day", Some(24 * 60 * 60)),
+    ("1 week", Some(7 * 24 * 60 * 60)),
+];
+
+pub(crate) fn label(expiry: Option<u32>) -> String {
+    match EXPIRY_OPTIONS.iter().find(|(_, option)| *option == expiry) {
+        Some((label, _)) => label.to_string(),
+        None => countdown(chrono::Duration::seconds(expiry.unwrap_or_default() as i64)),
+    }
+}
+
+/// When the Message disappears, counted from the time it was sent.
+pub(crate) fn expires_at(message: &Message) -> Option<chrono::DateTime<Local>> {
+    let expiry = message.expiry()?;
+    let sent_at = message.date_time().to_local().ok()?;
+
+    Some(sent_at + chrono::Duration::seconds(expiry as i64))
+}
+
+/// "45s", "12m", "5h" or "6d", rounded up so it never shows 0 before the Message is gone.
+pub(crate) fn countdown(remaining: chrono::Duration) -> String {
+    let seconds = remaining.num_seconds().max(0);
+
+    match seconds {
+        s if s < 60 => std::format!("{s}s"),
+        s if s < 60 * 60 => std::format!("{}m", (s + 59) / 60),
+        s if s < 24 * 60 * 60 => std::format!("{}h", (s + 60 * 60 - 1) / (60 * 60)),
+        s => std::format!("{}d", (s + 24 * 60 * 60 - 1) / (24 * 60 * 60)),
+    }
+}
+
+/// Drops the expired messages and everything kept about them, returns the Chats that changed.
+pub(crate) fn remove_expired(shared_mut: &mut SharedMut, now: &chrono::DateTime<Local>) -> HashSet<UUID> {
+    let mut changed = HashSet::default();
+
+    let is_expired = |message: &Message| expires_at(message).map_or(false, |expires_at| expires_at <= *now);
+
+    for chat in shared_mut.chats.values_mut() {
+        // Runs every few seconds, most Chats have nothing to drop.
+        if !chat.messages().iter().any(is_expired) { continue; }
+
+        let (expired, mut kept): (Vec<_>, Vec<_>) = chat.messages()
+            .iter()
+            .cloned()
+            .partition(is_expired);
+
+        chat.clear_messages();
+        chat.append_messages(&mut kept);
+
+        let mut chat_state = shared_mut.chat_states.get_mut(&chat.uuid());
+        for message in expired {
+            shared_mut.e2e.forget(message.uuid());
+            if let Some(chat_state) = chat_state.as_mut() {
+                chat_state.forget(message.uuid());
+            }
+        }
+        changed.insert(chat.uuid());
+    }
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn countdown_rounds_up() {
+        assert_eq!(countdown(chrono::Duration::seconds(-3)), "0s");
+        assert_eq!(countdown(chrono::Duration::seconds(59)), "59s");
+        assert_eq!(countdown(chrono::Duration::seconds(61)), "2m");
+        assert_eq!(countdown(chrono::Duration::minutes(60)), "1h");
+        assert_eq!(countdown(chrono::Duration::hours(23) + chrono::Duration::seconds(1)), "24h");
+        assert_eq!(countdown(chrono::Duration::days(6)), "6d");
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(label(None), "Off");
+        assert_eq!(label(Some(60 * 60)), "1 hour");
+        assert_eq!(label(Some(90)), "2m");
+    }
+}
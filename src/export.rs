@@ -0,0 +1,398 @@
+use std::{collections::HashMap, fs::File, io::{BufWriter, Write}, path::{Path, PathBuf}};
+use yapping_core::{chat::Chat, chrono, client_server_coms::{Query, Response, ServerMessage, ServerMessageContent, Session}, l3gion_rust::{AsLgTime, StdError, UUID}, message::{Message, MessageType}, user::UserCreationInfo};
+
+use crate::{e2e::E2e, gui::{self, mentions}, local_store, server_coms::ServerCommunication};
+
+/// Messages asked per query while exporting.
+pub(crate) const EXPORT_PAGE_SIZE: u32 = 200;
+/// Time an export waits for each page before giving up.
+pub(crate) const PAGE_TIMEOUT_SECONDS: u32 = 10;
+const DEFAULT_SERVER: &str = "ws://127.0.0.1:8080";
+
+const CLI_USAGE: &str = "Usage: client export --email <email> --chat <chat uuid or name> [--format json|html|txt] [--out <file>] [--server <ws://ip:port>]
+The password is read from the YAPPING_PASSWORD environment variable.";
+
+const HTML_STYLE: &str = "body { background: #1e1f22; color: #dbdee1; font-family: sans-serif; max-width: 900px; margin: 0 auto; padding: 20px; }
+h1 { font-size: 24px; } .members, .meta, .time { color: #949ba4; font-size: 13px; }
+.message { padding: 6px 10px; border-radius: 6px; } .message:hover { background: #2b2d31; }
+.sender { font-weight: bold; margin-right: 8px; } .text { white-space: pre-wrap; margin-top: 2px; }
+.meta a { color: #00a8fc; } .file { font-style: italic; color: #949ba4; }";
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ExportFormat {
+    JSON,
+    HTML,
+    TXT,
+}
+impl ExportFormat {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(Self::JSON),
+            "html" | "htm" => Some(Self::HTML),
+            "txt" | "text" => Some(Self::TXT),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            Self::JSON => "json",
+            Self::HTML => "html",
+            Self::TXT => "txt",
+        }
+    }
+}
+
+/// Writes the history of a Chat page by page, so it never has to be in memory at once.
+pub(crate) struct ChatExporter {
+    writer: BufWriter<File>,
+    format: ExportFormat,
+    // Tag by User UUID, as written on the mentions.
+    tags: HashMap<String, String>,
+    // Sender of every written message, for the replies.
+    senders: HashMap<UUID, UUID>,
+    written: usize,
+}
+impl ChatExporter {
+    /// Creates the file and writes everything that comes before the messages.
+    pub(crate) fn create(path: &Path, format: ExportFormat, chat: &Chat, name: &str, tags: impl IntoIterator<Item = (UUID, String)>) -> Result<Self, StdError> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let mut exporter = Self {
+            writer: BufWriter::new(File::create(path)?),
+            format,
+            tags: tags.into_iter().map(|(user_uuid, tag)| (user_uuid.to_string(), tag)).collect(),
+            senders: HashMap::default(),
+            written: 0,
+        };
+
+        let members = chat.users()
+            .iter()
+            .map(|member| (member.to_string(), exporter.tag(*member)))
+            .collect::<Vec<_>>();
+        let exported_at = chrono::Local::now();
+
+        let w = &mut exporter.writer;
+        match format {
+            ExportFormat::JSON => {
+                writeln!(w, "{{")?;
+                writeln!(w, "  \"chat\": {{ \"uuid\": {}, \"name\": {}, \"members\": [", json_string(&chat.uuid().to_string()), json_string(name))?;
+                for (i, (member_uuid, tag)) in members.iter().enumerate() {
+                    let comma = if i + 1 < members.len() { "," } else { "" };
+                    writeln!(w, "    {{ \"uuid\": {}, \"tag\": {} }}{comma}", json_string(member_uuid), json_string(tag))?;
+                }
+                writeln!(w, "  ] }},")?;
+                writeln!(w, "  \"exported_at\": {},", json_string(&exported_at.to_rfc3339()))?;
+                write!(w, "  \"messages\": [")?;
+            },
+            ExportFormat::HTML => {
+                writeln!(w, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{HTML_STYLE}\n</style>\n</head>\n<body>", html_escape(name))?;
+                writeln!(w, "<h1>{}</h1>", html_escape(name))?;
+                writeln!(w, "<div class=\"members\">Members: {}</div>", html_escape(&members.iter().map(|(_, tag)| tag.as_str()).collect::<Vec<_>>().join(", ")))?;
+                writeln!(w, "<div class=\"members\">Exported {}</div>\n<hr>", exported_at.format("%Y-%m-%d %H:%M:%S"))?;
+            },
+            ExportFormat::TXT => {
+                writeln!(w, "{name}")?;
+                writeln!(w, "Members: {}", members.iter().map(|(_, tag)| tag.as_str()).collect::<Vec<_>>().join(", "))?;
+                writeln!(w, "Exported {}\n", exported_at.format("%Y-%m-%d %H:%M:%S"))?;
+            },
+        }
+
+        Ok(exporter)
+    }
+
+    /// Messages are expected oldest first, one page after the other.
+    pub(crate) fn write_messages(&mut self, messages: &[Message]) -> Result<(), StdError> {
+        for message in messages {
+            match self.format {
+                ExportFormat::JSON => self.write_json(message)?,
+                ExportFormat::HTML => self.write_html(message)?,
+                ExportFormat::TXT => self.write_txt(message)?,
+            }
+
+            let _ = self.senders.insert(message.uuid(), message.sender());
+            self.written += 1;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn written(&self) -> usize {
+        self.written
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), StdError> {
+        match self.format {
+            ExportFormat::JSON => writeln!(self.writer, "\n  ]\n}}")?,
+            ExportFormat::HTML => writeln!(self.writer, "</body>\n</html>")?,
+            ExportFormat::TXT => (),
+        }
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+// Private
+impl ChatExporter {
+    fn tag(&self, user_uuid: UUID) -> String {
+        self.tags
+            .get(&user_uuid.to_string())
+            .cloned()
+            .unwrap_or_else(|| String::from("Unknown User"))
+    }
+
+    fn write_json(&mut self, message: &Message) -> Result<(), StdError> {
+        let separator = if self.written > 0 { "," } else { "" };
+        let (kind, text, file) = match message.content() {
+            MessageType::TEXT(text) => ("text", json_string(&self.display_text(text)), String::from("null")),
+            MessageType::FILE(file_uuid) => ("file", String::from("null"), json_string(&file_uuid.to_string())),
+        };
+        let optional_uuid = |uuid: Option<UUID>| uuid.map(|uuid| json_string(&uuid.to_string())).unwrap_or_else(|| String::from("null"));
+
+        write!(
+            self.writer,
+            "{separator}\n    {{ \"uuid\": {}, \"sender\": {}, \"sender_tag\": {}, \"sent_at\": {}, \"type\": {}, \"text\": {text}, \"file\": {file}, \"reply_to\": {}, \"thread_root\": {} }}",
+            json_string(&message.uuid().to_string()),
+            json_string(&message.sender().to_string()),
+            json_string(&self.tag(message.sender())),
+            message.date_time().to_local().map(|date_time| json_string(&date_time.to_rfc3339())).unwrap_or_else(|_| String::from("null")),
+            json_string(kind),
+            optional_uuid(message.reply_to()),
+            optional_uuid(message.thread_root()),
+        )?;
+
+        Ok(())
+    }
+
+    fn write_html(&mut self, message: &Message) -> Result<(), StdError> {
+        let meta = self.reply_info(message)
+            .map(|(target, tag)| std::format!("<div class=\"meta\">{} <a href=\"#m-{}\">{}</a></div>", if message.thread_root().is_some() { "In the thread of" } else { "Replying to" }, target.to_string(), html_escape(&tag)))
+            .unwrap_or_default();
+        let body = match message.content() {
+            MessageType::TEXT(text) => std::format!("<div class=\"text\">{}</div>", html_escape(&self.display_text(text))),
+            MessageType::FILE(file_uuid) => std::format!("<div class=\"file\">[File {}]</div>", file_uuid.to_string()),
+        };
+
+        writeln!(
+            self.writer,
+            "<div class=\"message\" id=\"m-{}\">{meta}<span class=\"sender\">{}</span><span class=\"time\">{}</span>{body}</div>",
+            message.uuid().to_string(),
+            html_escape(&self.tag(message.sender())),
+            timestamp(message),
+        )?;
+
+        Ok(())
+    }
+
+    fn write_txt(&mut self, message: &Message) -> Result<(), StdError> {
+        let meta = self.reply_info(message)
+            .map(|(_, tag)| std::format!(" ({} {tag})", if message.thread_root().is_some() { "in the thread of" } else { "replying to" }))
+            .unwrap_or_default();
+        let body = match message.content() {
+            MessageType::TEXT(text) => self.display_text(text),
+            MessageType::FILE(file_uuid) => std::format!("[File {}]", file_uuid.to_string()),
+        };
+
+        // Continuation lines are indented, so every message still starts with its header.
+        writeln!(self.writer, "[{}] {}{meta}: {}", timestamp(message), self.tag(message.sender()), body.replace('\n', "\n    "))?;
+
+        Ok(())
+    }
+
+    // Message being answered and its sender tag, threads first.
+    fn reply_info(&self, message: &Message) -> Option<(UUID, String)> {
+        let target = message.thread_root().or(message.reply_to())?;
+        let tag = self.senders
+            .get(&target)
+            .map(|sender| self.tag(*sender))
+            .unwrap_or_else(|| String::from("an earlier message"));
+
+        Some((target, tag))
+    }
+
+    // Mentions are written as "@tag".
+    fn display_text(&self, text: &str) -> String {
+        mentions::decode_by(text, |id| Some((self.tags.get(id)?.clone(), UUID::from_string(id).ok()?))).0
+    }
+}
+
+/// "<Downloads or data dir>/<chat name>-<date>.<extension>"
+pub(crate) fn default_path(name: &str, format: ExportFormat) -> PathBuf {
+    let dir = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join("Downloads"))
+        .filter(|downloads| downloads.is_dir())
+        .unwrap_or_else(|| local_store::data_dir().join("exports"));
+
+    let name = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect::<String>();
+
+    dir.join(std::format!("{name}-{}.{}", chrono::Local::now().format("%Y-%m-%d"), format.extension()))
+}
+
+/// `client export ...`, exports a Chat without opening a window.
+pub(crate) fn run_cli(args: &[String]) -> Result<(), StdError> {
+    let mut options: HashMap<&str, &str> = HashMap::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg.strip_prefix("--").ok_or_else(|| std::format!("Unexpected argument {arg}\n{CLI_USAGE}"))?;
+        let value = args.next().ok_or_else(|| std::format!("Missing value for --{name}\n{CLI_USAGE}"))?;
+        let _ = options.insert(name, value);
+    }
+
+    let email = options.get("email").ok_or_else(|| std::format!("Missing --email\n{CLI_USAGE}"))?;
+    let chat_query = options.get("chat").ok_or_else(|| std::format!("Missing --chat\n{CLI_USAGE}"))?;
+    // Arguments end up in the shell history and the process list.
+    if options.contains_key("password") { return Err(std::format!("--password is not supported, set YAPPING_PASSWORD instead\n{CLI_USAGE}").into()); }
+    let password = std::env::var("YAPPING_PASSWORD").map_err(|_| std::format!("Missing password\n{CLI_USAGE}"))?;
+    let out = options.get("out").map(PathBuf::from);
+    let format = match (options.get("format"), &out) {
+        (Some(format), _) => ExportFormat::from_name(format).ok_or_else(|| std::format!("Unknown format {format}\n{CLI_USAGE}"))?,
+        (None, Some(out)) => out.extension().and_then(|ext| ExportFormat::from_name(&ext.to_string_lossy())).unwrap_or(ExportFormat::JSON),
+        (None, None) => ExportFormat::JSON,
+    };
+
+    let mut server_coms = ServerCommunication::new();
+    server_coms.try_connect(options.get("server").copied().unwrap_or(DEFAULT_SERVER))?;
+
+    let user = match cli_request(&mut server_coms, ServerMessageContent::SESSION(Session::LOGIN(UserCreationInfo {
+        email: email.to_string(),
+        password: UUID::from_string(&password).map_err(|_| "In export::run_cli: Invalid password!")?,
+        ..Default::default()
+    })))? {
+        Response::OK_SESSION(Session::TOKEN(user)) => user,
+        _ => return Err("In export::run_cli: Wrong response from Server!".into()),
+    };
+
+    let chats = match cli_request(&mut server_coms, ServerMessageContent::QUERY(Query::USER_CHATS))? {
+        Response::OK_QUERY(Query::RESULT_CHATS(chats)) => chats,
+        _ => return Err("In export::run_cli: Wrong response from Server!".into()),
+    };
+
+    // Tags of everyone we may need, the friends are already known.
+    let mut tags: HashMap<UUID, String> = std::iter::once(&user)
+        .chain(user.friends().iter())
+        .map(|u| (u.uuid(), u.tag().to_string()))
+        .collect();
+    let unknown = chats
+        .iter()
+        .flat_map(|chat| chat.users().iter().copied())
+        .filter(|member| !tags.contains_key(member))
+        .collect::<std::collections::HashSet<_>>();
+    if !unknown.is_empty() {
+        if let Response::OK_QUERY(Query::RESULT_USER(users)) = cli_request(&mut server_coms, ServerMessageContent::QUERY(Query::USERS_BY_UUID(unknown.into_iter().collect())))? {
+            tags.extend(users.into_iter().map(|u| (u.uuid(), u.tag().to_string())));
+        }
+    }
+
+    // Direct chats are named after the other participant, as on the sidebar.
+    let name_of = |chat: &Chat| -> String {
+        if gui::is_group(chat) { return chat.tag().to_string(); }
+
+        chat.users()
+            .iter()
+            .find(|member| **member != user.uuid())
+            .and_then(|member| tags.get(member).cloned())
+            .unwrap_or_else(|| String::from("Empty Chat"))
+    };
+    let chat = chats
+        .iter()
+        .find(|chat| chat.uuid().to_string() == *chat_query)
+        .or_else(|| chats.iter().find(|chat| name_of(chat).eq_ignore_ascii_case(chat_query)))
+        .ok_or_else(|| std::format!("No chat named {chat_query}"))?;
+
+    let name = name_of(chat);
+    let path = out.unwrap_or_else(|| default_path(&name, format));
+    let mut exporter = ChatExporter::create(&path, format, chat, &name, tags.clone())?;
+
+    // Encrypted chats can only be read with the keys of this device, nothing is written back.
+    let e2e_path = local_store::data_dir().join(user.uuid().to_string()).join("e2e.bin");
+    let mut e2e = if e2e_path.exists() { E2e::from_bytes(&std::fs::read(e2e_path)?)? }
+    else { E2e::default() };
+
+    let mut after = None;
+    loop {
+        let mut page = match cli_request(&mut server_coms, ServerMessageContent::QUERY(Query::CHAT_MESSAGES_PAGE(chat.uuid(), after, EXPORT_PAGE_SIZE)))? {
+            Response::OK_QUERY(Query::RESULT_CHAT_MESSAGES_PAGE(page)) => page,
+            _ => return Err("In export::run_cli: Wrong response from Server!".into()),
+        };
+        for message in page.iter_mut() {
+            let _ = e2e.reveal(chat.uuid(), user.uuid(), message);
+        }
+        exporter.write_messages(&page)?;
+
+        after = page.last().map(|message| message.uuid());
+        if page.len() < EXPORT_PAGE_SIZE as usize { break; }
+    }
+
+    let written = exporter.written();
+    exporter.finish()?;
+    eprintln!("Exported {written} messages to {}", path.display());
+
+    Ok(())
+}
+
+fn cli_request(server_coms: &mut ServerCommunication, content: ServerMessageContent) -> Result<Response, StdError> {
+    match server_coms.send_and_wait(PAGE_TIMEOUT_SECONDS.s(), ServerMessage::from(content))? {
+        ServerMessageContent::RESPONSE(Response::Err(e)) => Err(e.into()),
+        ServerMessageContent::RESPONSE(response) => Ok(response),
+        _ => Err("In export::cli_request: Got wrong message from Server!".into()),
+    }
+}
+
+fn timestamp(message: &Message) -> String {
+    message.date_time()
+        .to_local()
+        .map(|date_time| date_time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::with_capacity(text.len() + 2);
+    result.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&std::format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    result
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(json_string("hi"), "\"hi\"");
+        assert_eq!(json_string("say \"hi\"\\"), "\"say \\\"hi\\\"\\\\\"");
+        assert_eq!(json_string("a\nb\tc\r"), "\"a\\nb\\tc\\r\"");
+        assert_eq!(json_string("\u{1}é"), "\"\\u0001é\"");
+    }
+
+    #[test]
+    fn html_escapes() {
+        assert_eq!(html_escape("<b>\"Tom & Jerry\"</b>"), "&lt;b&gt;&quot;Tom &amp; Jerry&quot;&lt;/b&gt;");
+        assert_eq!(html_escape("&lt;"), "&amp;lt;");
+    }
+}
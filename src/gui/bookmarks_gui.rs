@@ -0,0 +1,164 @@
+use yapping_core::l3gion_rust::{imgui, lg_core::renderer::Renderer, StdError, UUID};
+
+use crate::{client_manager::{AppState, ForegroundState}, server_coms::ServerCommunication};
+
+use super::{button, chat_name, gui_manager::GuiMannager, mentions, no_resize_child_window, spacing, time_format, use_font, user_tag, window, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+
+/// Messages the user saved from the context menu of the chat page, newest first.
+pub(crate) struct BookmarksGuiManager {
+    app_state: AppState,
+    open: bool,
+    // Chat UUID, Message UUID
+    selected: Option<(UUID, UUID)>,
+    remove: Option<UUID>,
+}
+impl BookmarksGuiManager {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            open: false,
+            selected: None,
+            remove: None,
+        }
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.open = true;
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.open = false;
+    }
+}
+
+impl GuiMannager for BookmarksGuiManager {
+    fn on_imgui(&mut self, ui: &imgui::Ui, _renderer: &Renderer) {
+        if !self.open { return; }
+
+        if ui.is_key_pressed(imgui::Key::Escape) {
+            self.close();
+            return;
+        }
+
+        let (page_pos, page_size) = unsafe { NEXT_WINDOW_SPECS };
+        let size = [(page_size[0] - 40.0).min(600.0), (page_size[1] - 80.0).min(500.0)];
+        let position = [page_pos[0] + (page_size[0] - size[0]) / 2.0, page_pos[1] + 40.0];
+
+        window(
+            ui,
+            "bookmarks_window",
+            None,
+            position,
+            size,
+            [10.0, 10.0],
+            size,
+            self.app_state.theme.mini_pop_up_color,
+            |ui| {
+                let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
+                ui.text("Bookmarks");
+
+                _fonts.push(use_font(ui, super::FontType::REGULAR17));
+                ui.same_line_with_pos(ui.content_region_max()[0] - 25.0);
+                if button(
+                    ui,
+                    "X##close_bookmarks",
+                    [25.0, 25.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.accent_color,
+                    self.app_state.theme.sign_up_btn_color,
+                    self.app_state.theme.sign_up_btn_color,
+                ) {
+                    self.close();
+                }
+
+                spacing(ui, 2);
+                ui.separator();
+
+                no_resize_child_window(
+                    ui,
+                    "bookmarks_list",
+                    imgui::WindowFlags::empty(),
+                    ui.content_region_avail(),
+                    [0.0; 2],
+                    self.app_state.theme.mini_pop_up_color,
+                    |ui| self.show_bookmarks(ui));
+            });
+    }
+
+    fn on_update(&mut self, _server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if let Some(message_uuid) = self.remove.take() {
+            self.app_state.shared_mut.borrow_mut().bookmarks.retain(|bookmark| bookmark.message_uuid != message_uuid);
+        }
+
+        if let Some((chat_uuid, message_uuid)) = self.selected.take() {
+            let mut shared_mut = self.app_state.shared_mut.borrow_mut();
+            shared_mut.foreground_state = ForegroundState::CHAT_PAGE(chat_uuid);
+            shared_mut.jump_to = Some((chat_uuid, message_uuid));
+            self.close();
+        }
+
+        Ok(())
+    }
+}
+impl BookmarksGuiManager {
+    fn show_bookmarks(&mut self, ui: &imgui::Ui) {
+        let shared_mut = self.app_state.shared_mut.borrow();
+        if shared_mut.bookmarks.is_empty() {
+            ui.text_colored([1.0, 1.0, 1.0, 0.5], "Right click a message and pick Bookmark to save it here.");
+            return;
+        }
+
+        let mut bookmarks = shared_mut.bookmarks.iter().collect::<Vec<_>>();
+        bookmarks.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+
+        for bookmark in bookmarks {
+            let start = ui.cursor_screen_pos();
+            // The copy stays even after we leave the Chat, it just can't be opened anymore.
+            let chat = shared_mut.chats.get(&bookmark.chat_uuid);
+
+            let mut header = user_tag(&shared_mut, bookmark.sender);
+            if let Some(chat) = chat {
+                header = std::format!("{header} in {}", chat_name(&shared_mut, chat));
+            }
+
+            let mut _fonts = vec![use_font(ui, super::FontType::BOLD17)];
+            ui.text(header);
+            _fonts.push(use_font(ui, super::FontType::BOLD15));
+            ui.same_line();
+            ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("saved {} {}", time_format::date(bookmark.saved_at.date_naive()), time_format::time(&bookmark.saved_at, shared_mut.clock_format)));
+
+            _fonts.push(use_font(ui, super::FontType::REGULAR17));
+            ui.same_line_with_pos(ui.content_region_max()[0] - 70.0);
+            if button(
+                ui,
+                &std::format!("Remove##bookmark_{}", bookmark.message_uuid.to_string()),
+                [70.0, 20.0],
+                BORDER_RADIUS,
+                self.app_state.theme.negative_btn_color,
+                self.app_state.theme.negative_actv_btn_color,
+                self.app_state.theme.negative_actv_btn_color,
+            ) {
+                self.remove = Some(bookmark.message_uuid);
+            }
+            let remove_hovered = ui.is_item_hovered();
+
+            ui.text_wrapped(mentions::display_text(&shared_mut, &bookmark.text));
+
+            let end = [ui.window_pos()[0] + ui.content_region_max()[0], ui.cursor_screen_pos()[1]];
+            if chat.is_some() && !remove_hovered && ui.is_window_hovered() && ui.is_mouse_hovering_rect(start, end) {
+                ui.get_window_draw_list()
+                    .add_rect(start, end, [1.0, 1.0, 1.0, 0.05])
+                    .filled(true)
+                    .rounding(BORDER_RADIUS)
+                    .build();
+                ui.set_mouse_cursor(Some(imgui::MouseCursor::Hand));
+
+                if ui.is_mouse_clicked(imgui::MouseButton::Left) {
+                    self.selected = Some((bookmark.chat_uuid, bookmark.message_uuid));
+                }
+            }
+
+            ui.separator();
+        }
+    }
+}
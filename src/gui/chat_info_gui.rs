@@ -0,0 +1,356 @@
+use yapping_core::{chat::{Chat, ChatRole}, client_server_coms::Modification, l3gion_rust::{imgui, UUID}, user::User};
+
+use crate::client_manager::AppState;
+
+use super::{button, no_resize_child_window, spacing, text_input, use_font, user_tag, window, BORDER_RADIUS};
+
+pub(crate) const CHAT_INFO_PANEL_WIDTH: f32 = 320.0;
+
+// Client side permission checks, the server still has the final word.
+fn can_manage_members(role: ChatRole) -> bool {
+    matches!(role, ChatRole::OWNER | ChatRole::ADMIN)
+}
+
+fn can_rename(role: ChatRole) -> bool {
+    matches!(role, ChatRole::OWNER | ChatRole::ADMIN)
+}
+
+fn can_set_roles(role: ChatRole) -> bool {
+    role == ChatRole::OWNER
+}
+
+fn can_remove(role: ChatRole, target_role: ChatRole) -> bool {
+    match role {
+        ChatRole::OWNER => target_role != ChatRole::OWNER,
+        ChatRole::ADMIN => target_role == ChatRole::MEMBER,
+        ChatRole::MEMBER => false,
+    }
+}
+
+// The last owner can't leave, nobody would be able to manage roles after that.
+fn can_leave_as(role: ChatRole, owners: usize) -> bool {
+    role != ChatRole::OWNER || owners > 1
+}
+
+/// Whether the user can leave the Chat, used by the panel and by /leave.
+pub(crate) fn can_leave(chat: &Chat, user_uuid: UUID) -> bool {
+    let owners = chat.users()
+        .iter()
+        .filter(|member| chat.role(**member) == ChatRole::OWNER)
+        .count();
+
+    can_leave_as(chat.role(user_uuid), owners)
+}
+
+/// Side panel with the members of a group Chat, and the actions the current user is allowed to take.
+pub(crate) struct ChatInfoPanel {
+    app_state: AppState,
+    open: bool,
+    name_buffer: String,
+    modifications: Vec<Modification>,
+    leave: bool,
+}
+impl ChatInfoPanel {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            open: false,
+            name_buffer: String::default(),
+            modifications: Vec::default(),
+            leave: false,
+        }
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.open = true;
+        self.name_buffer.clear();
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.open = false;
+        self.name_buffer.clear();
+    }
+
+    pub(crate) fn take_modifications(&mut self) -> Vec<Modification> {
+        std::mem::take(&mut self.modifications)
+    }
+
+    /// True once, after the current user chose to leave the Chat.
+    pub(crate) fn take_leave(&mut self) -> bool {
+        std::mem::take(&mut self.leave)
+    }
+
+    pub(crate) fn show(
+        &mut self,
+        ui: &imgui::Ui,
+        position: [f32; 2],
+        size: [f32; 2],
+        current_user: &User,
+        chat: &Chat,
+    ) {
+        if !self.open { return; }
+
+        let role = chat.role(current_user.uuid());
+
+        window(
+            ui,
+            "chat_info_window",
+            None,
+            position,
+            size,
+            [10.0, 10.0],
+            size,
+            self.app_state.theme.left_panel_bg_color,
+            |ui| {
+                // Header
+                let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
+                ui.text("Chat info");
+                ui.same_line_with_pos(ui.content_region_max()[0] - 30.0);
+                if button(
+                    ui,
+                    "X##close_chat_info",
+                    [30.0, 30.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.accent_color,
+                    self.app_state.theme.sign_up_btn_color,
+                    self.app_state.theme.sign_up_btn_color,
+                ) {
+                    self.close();
+                    return;
+                }
+                ui.separator();
+                spacing(ui, 3);
+
+                _fonts.push(use_font(ui, super::FontType::REGULAR17));
+                self.show_name(ui, chat, role);
+
+                spacing(ui, 3);
+                ui.text(std::format!("Members ({})", chat.users().len()));
+                self.show_members(ui, current_user, chat, role);
+
+                if can_manage_members(role) {
+                    spacing(ui, 3);
+                    ui.text("Add members");
+                    self.show_add_members(ui, current_user, chat);
+                }
+
+                spacing(ui, 3);
+                if !can_leave(chat, current_user.uuid()) {
+                    let _font = use_font(ui, super::FontType::BOLD15);
+                    ui.text_colored([1.0, 1.0, 1.0, 0.5], "Make another member owner to leave the group");
+                }
+                else if button(
+                    ui,
+                    "Leave group",
+                    [ui.content_region_avail()[0], 30.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.negative_btn_color,
+                    self.app_state.theme.negative_actv_btn_color,
+                    self.app_state.theme.negative_actv_btn_color,
+                ) {
+                    self.modifications.push(Modification::CHAT_LEAVE(chat.uuid(), current_user.uuid()));
+                    self.leave = true;
+                    self.close();
+                }
+            });
+    }
+}
+impl ChatInfoPanel {
+    fn show_name(&mut self, ui: &imgui::Ui, chat: &Chat, role: ChatRole) {
+        if !can_rename(role) {
+            ui.text(chat.tag());
+            return;
+        }
+
+        ui.set_next_item_width(ui.content_region_avail()[0] - 75.0);
+        let rename = text_input(
+            ui,
+            chat.tag(),
+            &mut self.name_buffer,
+            "##chat_info_name",
+            self.app_state.theme.input_text_bg_light,
+            [0.0, 0.0, 0.0, 1.0],
+            BORDER_RADIUS,
+            imgui::InputTextFlags::CALLBACK_RESIZE
+            | imgui::InputTextFlags::ENTER_RETURNS_TRUE
+        );
+
+        ui.same_line();
+        let rename = button(
+            ui,
+            "Rename",
+            [70.0, 0.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) || rename;
+
+        let name = self.name_buffer.trim();
+        if rename && !name.is_empty() && name != chat.tag() {
+            self.modifications.push(Modification::CHAT_RENAME(chat.uuid(), name.to_string()));
+            self.name_buffer.clear();
+        }
+    }
+
+    fn show_members(&mut self, ui: &imgui::Ui, current_user: &User, chat: &Chat, role: ChatRole) {
+        no_resize_child_window(
+            ui,
+            "chat_info_members",
+            imgui::WindowFlags::empty(),
+            [ui.content_region_avail()[0], 200.0],
+            [5.0, 5.0],
+            self.app_state.theme.main_bg_color,
+            |ui| {
+                for (i, member) in chat.users().iter().enumerate() {
+                    let member_role = chat.role(*member);
+
+                    ui.text(user_tag(&self.app_state.shared_mut.borrow(), *member));
+                    ui.same_line();
+                    ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("{:?}", member_role));
+
+                    if *member == current_user.uuid() { continue; }
+
+                    let mut x = ui.content_region_max()[0];
+                    if can_remove(role, member_role) {
+                        x -= 25.0;
+                        ui.same_line_with_pos(x);
+                        if button(
+                            ui,
+                            &std::format!("X##remove_member_{i}"),
+                            [25.0, 0.0],
+                            BORDER_RADIUS,
+                            self.app_state.theme.negative_btn_color,
+                            self.app_state.theme.negative_actv_btn_color,
+                            self.app_state.theme.negative_actv_btn_color,
+                        ) {
+                            self.modifications.push(Modification::CHAT_REMOVE_MEMBER(chat.uuid(), *member));
+                        }
+                    }
+
+                    // Admins can be made owners, so the current owner is free to leave.
+                    if can_set_roles(role) && member_role == ChatRole::ADMIN {
+                        x -= 65.0;
+                        ui.same_line_with_pos(x);
+                        if button(
+                            ui,
+                            &std::format!("Owner##member_owner_{i}"),
+                            [60.0, 0.0],
+                            BORDER_RADIUS,
+                            self.app_state.theme.accent_color,
+                            self.app_state.theme.sign_up_btn_color,
+                            self.app_state.theme.sign_up_btn_color,
+                        ) {
+                            self.modifications.push(Modification::CHAT_SET_ROLE(chat.uuid(), *member, ChatRole::OWNER));
+                        }
+                    }
+
+                    if can_set_roles(role) && member_role != ChatRole::OWNER {
+                        let (label, new_role) = if member_role == ChatRole::ADMIN { ("Demote", ChatRole::MEMBER) }
+                        else { ("Promote", ChatRole::ADMIN) };
+
+                        x -= 75.0;
+                        ui.same_line_with_pos(x);
+                        if button(
+                            ui,
+                            &std::format!("{label}##member_role_{i}"),
+                            [70.0, 0.0],
+                            BORDER_RADIUS,
+                            self.app_state.theme.accent_color,
+                            self.app_state.theme.sign_up_btn_color,
+                            self.app_state.theme.sign_up_btn_color,
+                        ) {
+                            self.modifications.push(Modification::CHAT_SET_ROLE(chat.uuid(), *member, new_role));
+                        }
+                    }
+                }
+            });
+    }
+
+    fn show_add_members(&mut self, ui: &imgui::Ui, current_user: &User, chat: &Chat) {
+        let candidates: Vec<&User> = current_user.friends()
+            .iter()
+            .filter(|f| !chat.users().contains(&f.uuid()))
+            .collect();
+
+        no_resize_child_window(
+            ui,
+            "chat_info_add_members",
+            imgui::WindowFlags::empty(),
+            [ui.content_region_avail()[0], 120.0],
+            [5.0, 5.0],
+            self.app_state.theme.main_bg_color,
+            |ui| {
+                if candidates.is_empty() {
+                    ui.text_colored([1.0, 1.0, 1.0, 0.5], "All your friends are here!");
+                }
+
+                for (i, friend) in candidates.iter().enumerate() {
+                    ui.text(friend.tag());
+                    ui.same_line_with_pos(ui.content_region_max()[0] - 25.0);
+                    if button(
+                        ui,
+                        &std::format!("+##add_member_{i}"),
+                        [25.0, 0.0],
+                        BORDER_RADIUS,
+                        self.app_state.theme.positive_btn_color,
+                        self.app_state.theme.positive_actv_btn_color,
+                        self.app_state.theme.positive_actv_btn_color,
+                    ) {
+                        self.modifications.push(Modification::CHAT_ADD_MEMBERS(chat.uuid(), vec![friend.uuid()]));
+                    }
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_permissions() {
+        // (role, manage members, rename, set roles)
+        let table = [
+            (ChatRole::OWNER, true, true, true),
+            (ChatRole::ADMIN, true, true, false),
+            (ChatRole::MEMBER, false, false, false),
+        ];
+        for (role, manage_members, rename, set_roles) in table {
+            assert_eq!(can_manage_members(role), manage_members, "{:?}", role);
+            assert_eq!(can_rename(role), rename, "{:?}", role);
+            assert_eq!(can_set_roles(role), set_roles, "{:?}", role);
+        }
+    }
+
+    #[test]
+    fn remove_matrix() {
+        // (role, target role, can remove)
+        let table = [
+            (ChatRole::OWNER, ChatRole::OWNER, false),
+            (ChatRole::OWNER, ChatRole::ADMIN, true),
+            (ChatRole::OWNER, ChatRole::MEMBER, true),
+            (ChatRole::ADMIN, ChatRole::OWNER, false),
+            (ChatRole::ADMIN, ChatRole::ADMIN, false),
+            (ChatRole::ADMIN, ChatRole::MEMBER, true),
+            (ChatRole::MEMBER, ChatRole::OWNER, false),
+            (ChatRole::MEMBER, ChatRole::ADMIN, false),
+            (ChatRole::MEMBER, ChatRole::MEMBER, false),
+        ];
+        for (role, target_role, expected) in table {
+            assert_eq!(can_remove(role, target_role), expected, "{:?} removing {:?}", role, target_role);
+        }
+    }
+
+    #[test]
+    fn last_owner_stays() {
+        assert!(!can_leave_as(ChatRole::OWNER, 1));
+        assert!(can_leave_as(ChatRole::OWNER, 2));
+        assert!(can_leave_as(ChatRole::ADMIN, 1));
+        assert!(can_leave_as(ChatRole::MEMBER, 1));
+    }
+}
@@ -1,14 +1,133 @@
-use yapping_core::{chat::Chat, chrono::{self, Datelike, Timelike}, client_server_coms::{Notification, NotificationType, ServerMessage, ServerMessageContent}, date_time::DateTime, l3gion_rust::{imgui, lg_core::renderer::Renderer, sllog::{error, warn}, StdError, UUID}, message::{Message, MessageType}, user::User};
+use std::collections::{HashMap, HashSet};
 
-use crate::{client_manager::{AppState, ForegroundState}, server_coms::ServerCommunication};
+use yapping_core::{chat::Chat, chrono::{self, NaiveDate}, client_server_coms::{Modification, Notification, NotificationType, Query, Response, ServerMessage, ServerMessageContent}, date_time::DateTime, l3gion_rust::{imgui, lg_core::renderer::Renderer, sllog::{error, warn}, AsLgTime, LgTimer, StdError, UUID}, message::{Message, MessageType}, user::User};
 
-use super::{button, gui_manager::GuiMannager, multiline_text_input, no_resize_child_window, spacing, text_input, use_font, window, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+use crate::{bookmarks::{self, Bookmark}, chat_state::{ChatState, DeliveryState}, client_manager::{self, AppState, ForegroundState, SharedMut}, e2e::E2eStatus, expiry, scheduled::{self, ScheduledMessage}, server_coms::ServerCommunication};
+
+use super::{button, chat_info_gui::{self, ChatInfoPanel, CHAT_INFO_PANEL_WIDTH}, chat_name, commands::{self, Command, CommandSpec, CommandTarget, Palette}, emoji::{self, EmojiPicker}, forward_gui::ForwardDialog, is_group, gui_manager::GuiMannager, markdown::{self, Block}, mentions, message_snippet, multiline_text_input_with_replacement, no_resize_child_window, safety_number_gui::SafetyNumberDialog, spacing, text_input, thread_panel_gui::{ThreadPanel, THREAD_PANEL_WIDTH}, time_format, use_font, user_tag, window, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+
+const QUOTE_SNIPPET_LEN: usize = 60;
+const THREAD_AVATARS: usize = 3;
+const REACTION_EMOJIS: [&str; 6] = ["👍", "❤", "😂", "😮", "😢", "🎉"];
+const MAX_EMOJI_SUGGESTIONS: usize = 6;
+const MAX_MENTION_SUGGESTIONS: usize = 5;
+// Escaped, so markdown keeps the backslash and underscores.
+const SHRUG: &str = r"¯\\\_(ツ)\_/¯";
+// Minimum time between TYPING_START notifications, and idle time before sending TYPING_STOP.
+const TYPING_THROTTLE_SECONDS: u32 = 3;
+const TYPING_IDLE_SECONDS: u32 = 5;
+const TYPING_NAMES: usize = 2;
+const SEEN_BY_NAMES: usize = 3;
+// Messages without an acknowledgement after this are marked as failed.
+const SEND_TIMEOUT_SECONDS: u32 = 15;
+// Consecutive messages from the same sender within this window share one header.
+const GROUP_WINDOW_MINUTES: i64 = 5;
+const CONTEXT_MENU_ID: &str = "##message_context_menu";
+const MESSAGE_LINK_SCHEME: &str = "yapping://";
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+enum MessageAction {
+    REACT,
+    THREAD,
+    REPLY,
+}
+
+// Label, width
+const MESSAGE_ACTIONS: [(&str, f32, MessageAction); 3] = [
+    ("+", 20.0, MessageAction::REACT),
+    ("Thread", 60.0, MessageAction::THREAD),
+    ("Reply", 60.0, MessageAction::REPLY),
+];
+
+// What to do with a Message that failed to send.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+enum FailedAction {
+    RETRY,
+    DELETE,
+}
+
+// What to do with a scheduled Message.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+enum ScheduledAction {
+    EDIT,
+    SEND_NOW,
+    CANCEL,
+}
+
+// What to do with the messages picked on the context menu or the selection.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+enum MenuAction {
+    PIN,
+    BOOKMARK,
+    DELETE,
+}
+
+// Replies count and the last distinct repliers of a thread.
+struct ThreadSummary {
+    replies: usize,
+    last_repliers: Vec<UUID>,
+}
 
 pub(crate) struct ChatGuiManager {
     app_state: AppState,
     chat_uuid: Option<UUID>,
     message_buffer: String,
     send_message: bool,
+    reply_to: Option<UUID>,
+    hovered_message: Option<UUID>,
+    // Message to scroll to, and the one currently being highlighted.
+    scroll_to: Option<UUID>,
+    highlighted: Option<(UUID, LgTimer)>,
+    history_requested: bool,
+    waiting_history: UUID,
+    thread_panel: ThreadPanel,
+    chat_info: ChatInfoPanel,
+    // Message UUID, Emoji
+    reaction_toggle: Option<(UUID, String)>,
+    // Composer text on the last update and time since it last changed.
+    typed_buffer: String,
+    last_keystroke: Option<LgTimer>,
+    // Time since the last TYPING_START we sent, None if we are not typing.
+    typing_sent: Option<LgTimer>,
+    // Parsed text of the messages, by Message UUID.
+    markdown_cache: HashMap<UUID, Vec<Block>>,
+    emoji_picker: EmojiPicker,
+    // Text to put on the composer, even if it's being edited.
+    buffer_replacement: Option<String>,
+    // Server message UUID, (Chat UUID, Message UUID, time since it was sent, the Message as sent)
+    // Kept until the Server answers, a retry sends the very same message again.
+    sending: HashMap<UUID, (UUID, UUID, LgTimer, Message)>,
+    failed_action: Option<(UUID, FailedAction)>,
+    // Mentions picked on the composer, (Tag, User UUID).
+    composer_mentions: Vec<(String, UUID)>,
+    // Why the last command was rejected, and the composer text it was rejected for.
+    command_error: Option<(String, String)>,
+    // Commands run by other managers, (Chat UUID, Command).
+    forwarded_commands: Vec<(UUID, Command)>,
+    // Set by /leave.
+    leave: bool,
+    // Messages up to this time are hidden by /clear, by Chat UUID.
+    enable_e2e: bool,
+    safety_number: SafetyNumberDialog,
+    // Time picked on the schedule popup for the composer text.
+    schedule_at: Option<chrono::DateTime<chrono::Local>>,
+    schedule_input: String,
+    schedule_error: Option<String>,
+    // Scheduled Message whose text is on the composer.
+    editing_scheduled: Option<UUID>,
+    scheduled_action: Option<(UUID, ScheduledAction)>,
+    // New disappearing messages setting for the open Chat, None turns it off.
+    expiry_change: Option<Option<u32>>,
+    // Message the context menu was opened on.
+    context_message: Option<UUID>,
+    menu_action: Option<(MenuAction, Vec<UUID>)>,
+    forward: ForwardDialog,
+    // Messages picked on multi-select mode, None when not selecting.
+    selection: Option<HashSet<UUID>>,
 }
 impl GuiMannager for ChatGuiManager {
     fn on_imgui(&mut self, ui: &imgui::Ui, renderer: &Renderer) {
@@ -16,31 +135,363 @@ impl GuiMannager for ChatGuiManager {
     }
 
     fn on_update(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        self.save_draft();
+
+        // Leaving a Chat, so we stop typing on it.
+        let switching_chat = match &self.app_state.shared_mut.borrow().foreground_state {
+            ForegroundState::CHAT_PAGE(chat_uuid) => chat_uuid.is_valid() && self.chat_uuid != Some(*chat_uuid),
+            _ => false,
+        };
+        if switching_chat {
+            self.stop_typing(server_coms)?;
+        }
+
         match &mut self.app_state.shared_mut.borrow_mut().foreground_state {
             ForegroundState::CHAT_PAGE(chat_uuid) => if chat_uuid.is_valid() {
-                server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::MESSAGE_READ(*chat_uuid)))))?;
+                if self.chat_uuid != Some(*chat_uuid) {
+                    self.reply_to = None;
+                    self.scroll_to = None;
+                    self.highlighted = None;
+                    // A pending load belongs to the previous Chat.
+                    self.history_requested = false;
+                    self.waiting_history = UUID::default();
+                    self.thread_panel.close();
+                    self.chat_info.close();
+                    self.markdown_cache.clear();
+                    self.editing_scheduled = None;
+                    self.context_message = None;
+                    self.selection = None;
+                }
                 self.chat_uuid = Some(std::mem::take(chat_uuid));
             },
             _ => ()
         };
-       
-        if let Some(user) = &self.app_state.shared_mut.borrow().user { if let Some(chat_uuid) = &self.chat_uuid { if self.send_message && !self.message_buffer.is_empty() {
+        if switching_chat {
+            self.restore_draft();
+        }
+
+        // Search results and other pages can ask us to show a message in context.
+        let jump_to = self.app_state.shared_mut.borrow_mut().jump_to.take();
+        if let Some((chat_uuid, message_uuid)) = jump_to {
+            if self.chat_uuid == Some(chat_uuid) {
+                self.scroll_to = Some(message_uuid);
+                self.thread_panel.close();
+            }
+        }
+
+        // Messages the server didn't acknowledge in time. They stay in `sending`, so a late answer still marks them as sent.
+        let timed_out = self.sending
+            .values()
+            .filter(|(chat_uuid, message_uuid, timer, _)| timer.elapsed() >= SEND_TIMEOUT_SECONDS.s() && self.delivery(*chat_uuid, *message_uuid) == Some(DeliveryState::SENDING))
+            .map(|(chat_uuid, message_uuid, _, _)| (*chat_uuid, *message_uuid))
+            .collect::<Vec<_>>();
+        for (chat_uuid, message_uuid) in timed_out {
+            self.set_delivery(chat_uuid, message_uuid, DeliveryState::FAILED);
+        }
+
+        // Scheduling only touches what we keep locally, so it works offline too.
+        self.update_scheduled();
+        // So are bookmarks.
+        if let Some(chat_uuid) = self.chat_uuid {
+            match self.menu_action.take() {
+                Some((MenuAction::BOOKMARK, message_uuids)) => self.toggle_bookmarks(chat_uuid, &message_uuids),
+                action => self.menu_action = action,
+            }
+        }
+
+        // Read only while offline, whatever was typed stays in the composer.
+        if !server_coms.connected() {
             self.send_message = false;
+            self.reaction_toggle = None;
+            self.failed_action = None;
+            self.enable_e2e = false;
+            self.expiry_change = None;
+            self.menu_action = None;
+            let _ = self.forward.take_forward();
+
+            return Ok(());
+        }
+       
+        let mut to_send = Vec::default();
+        let mut to_run = Vec::default();
+        if let Some(user) = &self.app_state.shared_mut.borrow().user { if let Some(chat_uuid) = self.chat_uuid {
+            if self.send_message && !self.message_buffer.is_empty() {
+                self.send_message = false;
+
+                match commands::parse(&self.message_buffer) {
+                    Some(Ok(command)) => {
+                        self.message_buffer.clear();
+                        to_run.push((chat_uuid, command, std::mem::take(&mut self.composer_mentions)));
+                    },
+                    Some(Err(e)) => self.command_error = Some((e, self.message_buffer.clone())),
+                    None => {
+                        let text = mentions::encode_all(commands::unescape(&std::mem::take(&mut self.message_buffer)), &std::mem::take(&mut self.composer_mentions));
+                        let text = emoji::replace_shortcodes(&text);
+                        let mut message = Message::new(user.uuid(), MessageType::TEXT(text), DateTime::from_utc(&chrono::Utc::now()));
+                        if let Some(reply_to) = self.reply_to.take() {
+                            message = message.with_reply_to(reply_to);
+                        }
+
+                        to_send.push((chat_uuid, message));
+                    },
+                }
+            }
+
+            if let Some(message) = self.app_state.shared_mut.borrow().chats.get(&chat_uuid).and_then(|chat| self.thread_panel.take_message(user.uuid(), chat)) {
+                to_send.push((chat_uuid, message));
+            }
+        }}
+        for (chat_uuid, message) in to_send {
+            self.send_chat_message(server_coms, chat_uuid, message)?;
+        }
+        for (chat_uuid, command, composer_mentions) in to_run {
+            match command.target() {
+                CommandTarget::CHAT_PAGE => self.run_command(server_coms, chat_uuid, command, &composer_mentions)?,
+                _ => self.forwarded_commands.push((chat_uuid, command)),
+            }
+        }
+
+        if let (Some((message_uuid, action)), Some(chat_uuid)) = (self.failed_action.take(), self.chat_uuid) {
+            let message = self.app_state.shared_mut.borrow().chats
+                .get(&chat_uuid)
+                .and_then(|chat| chat.messages().iter().find(|m| m.uuid() == message_uuid).cloned());
+
+            match (action, message) {
+                (FailedAction::RETRY, Some(message)) => match self.sending.iter().find(|(_, (_, sent_uuid, _, _))| *sent_uuid == message_uuid).map(|(msg_uuid, _)| *msg_uuid) {
+                    Some(msg_uuid) => self.resend_chat_message(server_coms, msg_uuid)?,
+                    // It never got to be sent, e.g. the encryption failed.
+                    None => self.send_chat_message(server_coms, chat_uuid, message)?,
+                },
+                (FailedAction::DELETE, Some(_)) => self.delete_local_message(chat_uuid, message_uuid),
+                _ => (),
+            }
+        }
+
+        match (self.menu_action.take(), self.chat_uuid) {
+            (Some((MenuAction::DELETE, message_uuids)), Some(chat_uuid)) => {
+                // One failure doesn't keep the rest of the selection from being deleted.
+                let errors = message_uuids
+                    .into_iter()
+                    .filter_map(|message_uuid| {
+                        let sent = server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::MESSAGE_DELETED(chat_uuid, message_uuid)))));
+                        if sent.is_ok() { self.delete_local_message(chat_uuid, message_uuid); }
+                        sent.err()
+                    })
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>();
+
+                if !errors.is_empty() {
+                    return Err(std::format!("In ChatGuiManager::on_update: Failed to delete {} messages: {}", errors.len(), errors.join(", ")).into());
+                }
+            },
+            (Some((MenuAction::PIN, message_uuids)), Some(chat_uuid)) => self.toggle_pins(server_coms, chat_uuid, &message_uuids)?,
+            _ => (),
+        }
+
+        if let Some((target, message_uuids)) = self.forward.take_forward() {
+            for message in self.forwarded_messages(&message_uuids) {
+                self.send_chat_message(server_coms, target, message)?;
+            }
+            self.selection = None;
+        }
+
+        if let Some(chat_uuid) = self.chat_uuid {
+            for modification in self.chat_info.take_modifications() {
+                server_coms.send(ServerMessage::from(ServerMessageContent::MODIFICATION(modification)))?;
+            }
+
+            // The Server answers with a CHAT_UPDATED, so every member gets the new setting.
+            if let Some(expiry) = self.expiry_change.take() {
+                server_coms.send(ServerMessage::from(ServerMessageContent::MODIFICATION(Modification::CHAT_SET_EXPIRY(chat_uuid, expiry))))?;
+            }
+
+            if let Some(verified) = self.safety_number.take_verify() {
+                self.app_state.shared_mut.borrow_mut().e2e.set_verified(chat_uuid, verified);
+            }
+            if std::mem::take(&mut self.enable_e2e) {
+                self.send_e2e_key(server_coms, chat_uuid)?;
+            }
+
+            if self.chat_info.take_leave() || std::mem::take(&mut self.leave) {
+                client_manager::forget_chat(&mut self.app_state.shared_mut.borrow_mut(), chat_uuid);
+            }
+
+            // We left the Chat or were removed from it.
+            let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+            if !shared_mut.chats.contains_key(&chat_uuid) {
+                shared_mut.foreground_state = ForegroundState::MAIN_PAGE;
+                self.chat_uuid = None;
+                self.chat_info.close();
+                self.thread_panel.close();
+            }
+        }
+
+        if let Some((message_uuid, emoji)) = self.reaction_toggle.take() {
+            let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+            if let (Some(user), Some(chat_uuid)) = (&shared_mut.user, self.chat_uuid) {
+                let chat_state = shared_mut.chat_states.entry(chat_uuid).or_default();
+
+                let notification = if chat_state.has_reacted(message_uuid, user.uuid(), &emoji) {
+                    chat_state.remove_reaction(message_uuid, user.uuid(), &emoji);
+                    NotificationType::REACTION_REMOVED(chat_uuid, message_uuid, user.uuid(), emoji)
+                }
+                else {
+                    chat_state.add_reaction(message_uuid, user.uuid(), &emoji);
+                    NotificationType::REACTION_ADDED(chat_uuid, message_uuid, user.uuid(), emoji)
+                };
+
+                server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(notification))))?;
+            }
+        }
+
+        self.update_typing(server_coms)?;
+        self.update_read(server_coms)?;
 
-            let message = Message::new(user.uuid(), MessageType::TEXT(std::mem::take(&mut self.message_buffer)), DateTime::from_utc(&chrono::Utc::now()));
-            server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::NEW_MESSAGE(*chat_uuid, message)))))?;
-        }}}
+        // The message we want to jump to is not loaded, so we ask for the history again.
+        if let Some(chat_uuid) = self.chat_uuid { if self.history_requested && !self.waiting_history.is_valid() {
+            let msg_uuid = UUID::generate();
+            server_coms.send(ServerMessage::new(msg_uuid, ServerMessageContent::QUERY(Query::CHAT_MESSAGES(chat_uuid))))?;
+            self.waiting_history = msg_uuid;
+        }}
 
         Ok(())
     }
+
+    fn on_responded_messages(&mut self, message: &(ServerMessage, Response), _server_coms: &mut ServerCommunication) -> Result<bool, StdError> {
+        if let Some((chat_uuid, message_uuid)) = self.sending.get(&message.0.uuid).map(|(chat_uuid, message_uuid, _, _)| (*chat_uuid, *message_uuid)) {
+            match &message.1 {
+                // Kept, so a retry doesn't have to encrypt it again.
+                Response::Err(e) => {
+                    self.set_delivery(chat_uuid, message_uuid, DeliveryState::FAILED);
+                    return Err(e.clone().into());
+                },
+                // The echo may arrive before the acknowledgement, and the answer after the timeout.
+                _ => {
+                    let _ = self.sending.remove(&message.0.uuid);
+                    if matches!(self.delivery(chat_uuid, message_uuid), Some(DeliveryState::SENDING | DeliveryState::FAILED)) {
+                        self.set_delivery(chat_uuid, message_uuid, DeliveryState::SENT);
+                    }
+                },
+            }
+
+            Ok(true)
+        }
+        else if message.0.uuid == self.waiting_history
+        {
+            self.waiting_history = UUID::default();
+            self.history_requested = false;
+
+            // ClientManager already merged the history, if the message is still missing it doesn't exist anymore.
+            let found = match (self.scroll_to, self.chat_uuid) {
+                (Some(target), Some(chat_uuid)) => self.app_state.shared_mut.borrow().chats
+                    .get(&chat_uuid)
+                    .map(|chat| chat.messages().iter().any(|m| m.uuid() == target))
+                    .unwrap_or(false),
+                _ => false,
+            };
+            if !found {
+                warn!("In ChatGuiManager::on_responded_messages: Quoted message was not found in the Chat history!");
+                self.scroll_to = None;
+            }
+
+            match &message.1 {
+                Response::Err(e) => return Err(e.clone().into()),
+                _ => (),
+            }
+
+            Ok(true)
+        }
+        else { Ok(false) }
+    }
 }
 impl ChatGuiManager {
+    /// The Chat currently shown on the page.
+    pub(crate) fn chat_uuid(&self) -> Option<UUID> {
+        self.chat_uuid
+    }
+
+    /// Sends the scheduled messages that are due, of every Chat, whatever page is open.
+    pub(crate) fn send_due_scheduled(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if !server_coms.connected() { return Ok(()); }
+
+        let now = chrono::Local::now();
+        let (user_uuid, due) = {
+            let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+            // The cached user is there before the session is.
+            let user_uuid = match (&shared_mut.user, &shared_mut.foreground_state) {
+                (Some(_), ForegroundState::VALIDATION) | (None, _) => return Ok(()),
+                (Some(user), _) => user.uuid(),
+            };
+            // The one being edited waits for the edit to be saved.
+            let is_due = |scheduled: &ScheduledMessage| scheduled.is_due(&now) && Some(scheduled.uuid) != self.editing_scheduled;
+            if !shared_mut.scheduled.iter().any(is_due) { return Ok(()); }
+
+            let (mut due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut shared_mut.scheduled).into_iter().partition(is_due);
+            shared_mut.scheduled = waiting;
+            // Chats we left in the meantime.
+            due.retain(|scheduled| shared_mut.chats.contains_key(&scheduled.chat_uuid));
+
+            (user_uuid, due)
+        };
+
+        let mut due = due.into_iter();
+        while let Some(scheduled) = due.next() {
+            let message = Message::new(user_uuid, MessageType::TEXT(scheduled.text), DateTime::from_utc(&chrono::Utc::now()));
+
+            // A failed one stays on the chat with its retry button, the rest go back to wait for the next try.
+            if let Err(e) = self.send_chat_message(server_coms, scheduled.chat_uuid, message) {
+                self.app_state.shared_mut.borrow_mut().scheduled.extend(due);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Commands typed on the composer that the chat page doesn't run itself.
+    pub(crate) fn take_commands(&mut self) -> Vec<(UUID, Command)> {
+        std::mem::take(&mut self.forwarded_commands)
+    }
+
     pub(crate) fn new(app_state: AppState) -> Self {
         Self {
-            app_state,
+            app_state: app_state.clone(),
             chat_uuid: None,
             message_buffer: String::default(),
             send_message: false,
+            reply_to: None,
+            hovered_message: None,
+            scroll_to: None,
+            highlighted: None,
+            history_requested: false,
+            waiting_history: UUID::default(),
+            thread_panel: ThreadPanel::new(app_state.clone()),
+            chat_info: ChatInfoPanel::new(app_state.clone()),
+            reaction_toggle: None,
+            typed_buffer: String::default(),
+            last_keystroke: None,
+            typing_sent: None,
+            markdown_cache: HashMap::default(),
+            emoji_picker: EmojiPicker::default(),
+            buffer_replacement: None,
+            sending: HashMap::default(),
+            failed_action: None,
+            composer_mentions: Vec::default(),
+            command_error: None,
+            forwarded_commands: Vec::default(),
+            leave: false,
+            enable_e2e: false,
+            safety_number: SafetyNumberDialog::new(app_state.clone()),
+            schedule_at: None,
+            schedule_input: String::default(),
+            schedule_error: None,
+            editing_scheduled: None,
+            scheduled_action: None,
+            expiry_change: None,
+            context_message: None,
+            menu_action: None,
+            forward: ForwardDialog::new(app_state.clone()),
+            selection: None,
         }
     }
 }
@@ -50,10 +501,17 @@ impl ChatGuiManager {
         ui: &imgui::Ui,
         renderer: &Renderer
     ) {
-        let (window_pos, window_size) = unsafe { NEXT_WINDOW_SPECS };
+        let (window_pos, mut window_size) = unsafe { NEXT_WINDOW_SPECS };
+        if self.thread_panel.is_open() {
+            window_size[0] -= THREAD_PANEL_WIDTH;
+        }
+        else if self.chat_info.is_open() {
+            window_size[0] -= CHAT_INFO_PANEL_WIDTH;
+        }
 
         let shared = self.app_state.shared_mut.clone();
         let chats = &shared.borrow().chats;
+        let chat_states = &shared.borrow().chat_states;
 
         let chat = if let Some(chat_uuid) = self.chat_uuid {
             if let Some(chat) = chats.get(&chat_uuid) {
@@ -73,17 +531,39 @@ impl ChatGuiManager {
             window_size, 
             self.app_state.theme.main_bg_color,
             |ui| {
+                self.show_chat_header(ui, chat);
+
                 if let Some(user) = &shared.borrow().user {
-                    self.show_chat_messages(ui, renderer, user, chat);
+                    self.show_chat_messages(ui, renderer, user, chat, chat_states.get(&chat.uuid()));
+                    self.show_typing_indicator(ui, user, chat_states.get(&chat.uuid()));
+                    self.show_composer_bar(ui, chat);
+                }
+                else {
+                    ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 55.0]);
                 }
 
                 let should_focus_keyboard = ui.is_window_hovered() || ui.is_item_hovered();
 
+                // Tab only completes while there is something to complete, otherwise ImGui moves the focus with it.
+                let completing = matches!(commands::palette(&self.message_buffer), Some(Palette::NAMES(_)))
+                    || !self.mention_suggestions().is_empty()
+                    || !self.emoji_suggestions().is_empty();
+                if completing && ui.is_key_pressed(imgui::Key::Tab) {
+                    if let Some(Palette::NAMES(specs)) = commands::palette(&self.message_buffer) {
+                        self.complete_command(specs[0]);
+                    }
+                    else if let Some((tag, user_uuid)) = self.mention_suggestions().into_iter().next() {
+                        self.complete_mention(tag, user_uuid);
+                    }
+                    else if let Some(suggestion) = self.emoji_suggestions().first() {
+                        self.complete_shortcode(suggestion.emoji);
+                    }
+                }
+
                 let _font = use_font(ui, super::FontType::REGULAR24);
-                ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 35.0]);
-                self.send_message = multiline_text_input(
+                self.send_message = multiline_text_input_with_replacement(
                     ui, 
-                    [ui.content_region_avail()[0], 60.0],
+                    [ui.content_region_avail()[0] - 90.0, 60.0],
                     &mut self.message_buffer, 
                     "##message_text_input", 
                     [1.0, 1.0, 1.0, 0.3], 
@@ -93,71 +573,1843 @@ impl ChatGuiManager {
                     | imgui::InputTextFlags::CALLBACK_RESIZE
                     | imgui::InputTextFlags::CTRL_ENTER_FOR_NEW_LINE
                     | imgui::InputTextFlags::ENTER_RETURNS_TRUE
+                    // The input keeps the Tab, the completion replaces the text the same frame.
+                    | if completing { imgui::InputTextFlags::ALLOW_TAB_INPUT } else { imgui::InputTextFlags::empty() },
+                    self.buffer_replacement.take(),
                 );
                 if should_focus_keyboard || ui.is_item_hovered() {
                     ui.set_keyboard_focus_here_with_offset(imgui::FocusedWidget::Previous);
                 }
                 ui.set_item_default_focus();
+
+                ui.same_line();
+                if button(
+                    ui,
+                    "☺##composer_emoji",
+                    [40.0, 60.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.accent_color,
+                    self.app_state.theme.sign_up_btn_color,
+                    self.app_state.theme.sign_up_btn_color,
+                ) {
+                    ui.open_popup("##composer_emoji_picker");
+                }
+
+                ui.same_line();
+                if button(
+                    ui,
+                    "⏰##composer_schedule",
+                    [40.0, 60.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.accent_color,
+                    self.app_state.theme.sign_up_btn_color,
+                    self.app_state.theme.sign_up_btn_color,
+                ) {
+                    self.schedule_error = None;
+                    ui.open_popup("##composer_schedule_popup");
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Send later");
+                }
+
+                if let Some(_popup) = ui.begin_popup("##composer_schedule_popup") {
+                    self.show_schedule_popup(ui);
+                }
+
+                if let Some(_popup) = ui.begin_popup("##composer_emoji_picker") {
+                    if let Some(emoji) = self.emoji_picker.show(ui, &self.app_state.theme, "composer") {
+                        self.message_buffer.push_str(emoji);
+                        self.buffer_replacement = Some(self.message_buffer.clone());
+                        ui.close_current_popup();
+                    }
+                }
+
+                self.forward.show(ui);
             });
+
+        self.thread_panel.show(
+            ui,
+            [window_pos[0] + window_size[0], window_pos[1]],
+            [THREAD_PANEL_WIDTH, window_size[1]],
+            chat
+        );
+
+        if let Some(user) = &shared.borrow().user {
+            self.chat_info.show(
+                ui,
+                [window_pos[0] + window_size[0], window_pos[1]],
+                [CHAT_INFO_PANEL_WIDTH, window_size[1]],
+                user,
+                chat
+            );
+        }
+    }
+    
+    fn show_chat_header(&mut self, ui: &imgui::Ui, chat: &Chat) {
+        let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
+
+        let name = chat_name(&self.app_state.shared_mut.borrow(), chat);
+        match chat.icon() {
+            Some(icon) => ui.text(std::format!("{icon} {name}")),
+            None => ui.text(name),
+        }
+
+        if is_group(chat) {
+            _fonts.push(use_font(ui, super::FontType::REGULAR17));
+            ui.same_line();
+            ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 5.0]);
+
+            let members = if chat.users().len() == 1 { String::from("1 member") }
+            else { std::format!("{} members", chat.users().len()) };
+            ui.text_colored([1.0, 1.0, 1.0, 0.5], members);
+
+            ui.same_line_with_pos(ui.content_region_max()[0] - 60.0);
+            if button(
+                ui,
+                "Info##chat_info",
+                [60.0, 25.0],
+                BORDER_RADIUS,
+                self.app_state.theme.accent_color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                if self.chat_info.is_open() { self.chat_info.close(); }
+                else {
+                    self.thread_panel.close();
+                    self.chat_info.open();
+                }
+            }
+        }
+        else {
+            self.show_encryption_status(ui, chat);
+        }
+        self.show_expiry_setting(ui, chat);
+        self.show_pinned(ui, chat);
+
+        ui.separator();
+    }
+
+    // Dropdown with the pinned messages of the Chat, left of the disappearing messages setting.
+    fn show_pinned(&mut self, ui: &imgui::Ui, chat: &Chat) {
+        let pinned = self.app_state.shared_mut.borrow().chat_states
+            .get(&chat.uuid())
+            .map(|state| state.pinned().to_vec())
+            .unwrap_or_default();
+        if pinned.is_empty() { return; }
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        let offset = if is_group(chat) { 60.0 } else { 130.0 };
+        ui.same_line_with_pos(ui.content_region_max()[0] - offset - 120.0 - 60.0);
+        if button(
+            ui,
+            &std::format!("📌 {}##chat_pinned", pinned.len()),
+            [50.0, 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) {
+            ui.open_popup("##chat_pinned_popup");
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Pinned messages");
+        }
+
+        let _popup = if let Some(popup) = ui.begin_popup("##chat_pinned_popup") { popup }
+        else { return; };
+
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let offline = shared_mut.offline;
+        // Newest pin first.
+        let entries = pinned
+            .iter()
+            .rev()
+            .map(|(message_uuid, pinned_by)| {
+                // Pins can point to history we haven't loaded, jumping to them loads it.
+                let preview = chat.messages()
+                    .iter()
+                    .find(|m| m.uuid() == *message_uuid)
+                    .map(|m| std::format!("{}: {}", user_tag(&shared_mut, m.sender()), message_snippet(&shared_mut, m, QUOTE_SNIPPET_LEN)))
+                    .unwrap_or_else(|| String::from("Older message"));
+
+                (*message_uuid, preview, user_tag(&shared_mut, *pinned_by))
+            })
+            .collect::<Vec<_>>();
+        drop(shared_mut);
+
+        {
+            let _font = use_font(ui, super::FontType::BOLD17);
+            ui.text("Pinned messages");
+        }
+        for (message_uuid, preview, pinned_by) in entries {
+            ui.separator();
+            if ui.selectable(std::format!("{preview}##pinned_{}", message_uuid.to_string())) {
+                self.scroll_to = Some(message_uuid);
+                self.thread_panel.close();
+            }
+
+            let _font = use_font(ui, super::FontType::BOLD15);
+            ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("Pinned by {pinned_by}"));
+            if offline { continue; }
+
+            ui.same_line_with_pos(ui.content_region_max()[0] - 50.0);
+            if button(
+                ui,
+                &std::format!("Unpin##unpin_{}", message_uuid.to_string()),
+                [50.0, 18.0],
+                BORDER_RADIUS,
+                self.app_state.theme.accent_color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                self.menu_action = Some((MenuAction::PIN, vec![message_uuid]));
+                ui.close_current_popup();
+            }
+        }
+    }
+
+    // Disappearing messages setting, left of the other header buttons.
+    fn show_expiry_setting(&mut self, ui: &imgui::Ui, chat: &Chat) {
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        let offset = if is_group(chat) { 60.0 } else { 130.0 };
+        ui.same_line_with_pos(ui.content_region_max()[0] - offset - 120.0);
+
+        let color = if chat.expiry().is_some() { self.app_state.theme.positive_btn_color } else { self.app_state.theme.accent_color };
+        if button(
+            ui,
+            &std::format!("⏳ {}##chat_expiry", expiry::label(chat.expiry())),
+            [110.0, 25.0],
+            BORDER_RADIUS,
+            color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) {
+            ui.open_popup("##chat_expiry_popup");
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Disappearing messages");
+        }
+
+        if let Some(_popup) = ui.begin_popup("##chat_expiry_popup") {
+            ui.text("New messages disappear after");
+            for (label, expiry) in expiry::EXPIRY_OPTIONS {
+                if ui.selectable_config(std::format!("{label}##expiry_option")).selected(chat.expiry() == expiry).build() && chat.expiry() != expiry {
+                    self.expiry_change = Some(expiry);
+                }
+            }
+        }
+    }
+
+    // Lock indicator of a direct Chat, opens the safety number once encryption is on.
+    fn show_encryption_status(&mut self, ui: &imgui::Ui, chat: &Chat) {
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let status = shared_mut.e2e.status(chat.uuid());
+        let safety_number = shared_mut.user.as_ref().and_then(|user| shared_mut.e2e.safety_number(chat.uuid(), user.uuid()));
+        let contact = chat_name(&shared_mut, chat);
+        drop(shared_mut);
+
+        let theme = &self.app_state.theme;
+        let (label, color) = match status {
+            E2eStatus::OFF => ("Encrypt##chat_e2e", theme.accent_color),
+            E2eStatus::WAITING => ("Waiting for key##chat_e2e", theme.accent_color),
+            E2eStatus::ON { verified: false } => ("Encrypted##chat_e2e", theme.positive_btn_color),
+            E2eStatus::ON { verified: true } => ("Verified##chat_e2e", theme.positive_btn_color),
+            E2eStatus::KEY_CHANGED => ("Key changed!##chat_e2e", theme.negative_btn_color),
+        };
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        ui.same_line_with_pos(ui.content_region_max()[0] - 130.0);
+        if button(
+            ui,
+            label,
+            [130.0, 25.0],
+            BORDER_RADIUS,
+            color,
+            theme.sign_up_btn_color,
+            theme.sign_up_btn_color,
+        ) {
+            match status {
+                E2eStatus::OFF => self.enable_e2e = true,
+                E2eStatus::WAITING => (),
+                E2eStatus::ON { .. } | E2eStatus::KEY_CHANGED => self.safety_number.open(),
+            }
+        }
+        if ui.is_item_hovered() && status == E2eStatus::OFF {
+            ui.tooltip_text("Encrypt the new messages of this chat end-to-end");
+        }
+
+        self.safety_number.show(ui, &contact, safety_number.as_deref(), status);
     }
     
     fn show_chat_messages(
-        &self, 
+        &mut self,
         ui: &imgui::Ui,
         renderer: &Renderer,
         current_user: &User,
         chat: &Chat,
+        chat_state: Option<&ChatState>,
     ) {
         no_resize_child_window(
             ui, 
             "chat_messages", 
             imgui::WindowFlags::empty(), 
-            [ui.content_region_avail()[0], ui.content_region_avail()[1] - 100.0], 
+            [ui.content_region_avail()[0], ui.content_region_avail()[1] - 120.0], 
             [0.0; 2], 
             self.app_state.theme.main_bg_color, 
             |ui| {
+                let mut hovered_message = None;
+                let mut scrolled = false;
+                let threads = thread_summaries(chat);
+                let seen_by = chat_state.map(|state| seen_by(chat, state, current_user.uuid())).unwrap_or_default();
+                let last_read = chat_state.and_then(|state| last_read_by_others(chat, state, current_user.uuid()));
+                let last_own = chat.messages()
+                    .iter()
+                    .rev()
+                    .find(|m| m.thread_root().is_none() && m.sender() == current_user.uuid())
+                    .map(|m| m.uuid());
+                let clock = self.app_state.shared_mut.borrow().clock_format;
+                let mention_names = mentions::names(&self.app_state.shared_mut.borrow(), chat);
+                let mut last_day = None;
+                let mut previous: Option<&Message> = None;
+                let cleared = self.app_state.shared_mut.borrow().cleared.get(&chat.uuid()).copied();
+
                 for (i, message) in chat.messages().iter().enumerate() {
+                    // Thread replies only show up on the thread panel.
+                    if message.thread_root().is_some() { continue; }
+
+                    let date_time = message.date_time().to_local().ok();
+                    if cleared.map_or(false, |cleared| date_time.map_or(true, |date_time| date_time <= cleared)) { continue; }
+
+                    let day = date_time.map(|date_time| date_time.date_naive());
+                    if let Some(day) = day.filter(|day| last_day != Some(*day)) {
+                        self.show_day_separator(ui, day);
+                        last_day = Some(day);
+                        previous = None;
+                    }
+
+                    // Replies always get their own header, since they quote another message.
+                    let grouped = message.reply_to().is_none() && previous.map_or(false, |previous| is_grouped(previous, message));
+                    previous = Some(message);
+
+                    let message_start = ui.cursor_screen_pos();
+
+                    if let Some(reply_to) = message.reply_to() {
+                        self.show_quote(ui, chat, reply_to);
+                    }
+
                     let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
-                    button(
-                        ui, 
-                        &std::format!("##user_pic_{i}"), 
-                        [30.0, 30.0], 
-                        BORDER_RADIUS, 
-                        self.app_state.theme.positive_btn_color, 
-                        self.app_state.theme.positive_btn_color, 
-                        self.app_state.theme.positive_btn_color, 
-                    );
-                    
-                    ui.same_line();
-                    let cursor_pos_message = ui.cursor_pos()[0];
-                    let cursor_pos_date_time = ui.cursor_pos()[1] + 9.0;
-                    if let Some(sender) = current_user.friends()
-                        .iter()
-                        .find(|u| u.uuid() == message.sender())
-                        .map(|u| u.tag())
-                        .or_else(|| {
-                            if current_user.uuid() == message.sender() { Some(current_user.tag()) }
-                            else { Some("Unknown User") }
-                        })
-                    { ui.text(sender); }
+                    let cursor_pos_message = if grouped {
+                        ui.cursor_pos()[0] + 30.0 + ui.clone_style().item_spacing[0]
+                    }
+                    else {
+                        button(
+                            ui, 
+                            &std::format!("##user_pic_{i}"), 
+                            [30.0, 30.0], 
+                            BORDER_RADIUS, 
+                            self.app_state.theme.positive_btn_color, 
+                            self.app_state.theme.positive_btn_color, 
+                            self.app_state.theme.positive_btn_color, 
+                        );
                     
-                    if let Ok(date_time) = message.date_time().to_local() {
-                        _fonts.push(use_font(ui, super::FontType::BOLD15));
                         ui.same_line();
-                        ui.set_cursor_pos([ui.cursor_pos()[0], cursor_pos_date_time]);
+                        let cursor_pos_message = ui.cursor_pos()[0];
+                        let cursor_pos_date_time = ui.cursor_pos()[1] + 9.0;
+                        ui.text(user_tag(&self.app_state.shared_mut.borrow(), message.sender()));
+                    
+                        if let Some(date_time) = &date_time {
+                            _fonts.push(use_font(ui, super::FontType::BOLD15));
+                            ui.same_line();
+                            ui.set_cursor_pos([ui.cursor_pos()[0], cursor_pos_date_time]);
 
-                        ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("{}/{}/{} {}:{}", date_time.day(), date_time.month(), date_time.year(), date_time.hour(), date_time.minute()));
-                    }
+                            ui.text_colored([1.0, 1.0, 1.0, 0.5], time_format::time(date_time, clock));
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text(time_format::full(date_time, clock));
+                            }
+                        }
+
+                        if self.hovered_message == Some(message.uuid()) {
+                            self.show_message_actions(ui, message.uuid(), i);
+                        }
                     
+                        cursor_pos_message
+                    };
+                    self.show_reaction_picker(ui, message.uuid(), i);
+
                     _fonts.push(use_font(ui, super::FontType::REGULAR24));
                     ui.set_cursor_pos([cursor_pos_message, ui.cursor_pos()[1]]);
+                    let body_pos = ui.cursor_pos();
                     match message.content() {
-                        MessageType::TEXT(text) => ui.text(text),
+                        MessageType::TEXT(text) => {
+                            let _id = ui.push_id_usize(i);
+                            let blocks = self.markdown_cache
+                                .entry(message.uuid())
+                                .or_insert_with(|| markdown::parse(text));
+
+                            markdown::show_markdown(ui, &self.app_state.theme, blocks, &mention_names);
+                        },
                         MessageType::FILE(_) => todo!(),
                     }
+
+                    if let Some(expires_at) = expiry::expires_at(message) {
+                        let resume_pos = ui.cursor_pos();
+                        _fonts.push(use_font(ui, super::FontType::BOLD15));
+                        ui.set_cursor_pos([ui.content_region_max()[0] - 50.0, body_pos[1]]);
+
+                        ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("⏳ {}", expiry::countdown(expires_at - chrono::Local::now())));
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text(std::format!("Disappears {}", time_format::full(&expires_at, clock)));
+                        }
+                        ui.set_cursor_pos(resume_pos);
+                    }
+
+                    if let Some(pinned_by) = chat_state.and_then(|state| state.pinned_by(message.uuid())) {
+                        let resume_pos = ui.cursor_pos();
+                        _fonts.push(use_font(ui, super::FontType::BOLD15));
+                        // Left of the disappearing countdown.
+                        ui.set_cursor_pos([ui.content_region_max()[0] - 75.0, body_pos[1]]);
+
+                        ui.text_colored(self.app_state.theme.sign_up_btn_color, "📌");
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text(std::format!("Pinned by {}", user_tag(&self.app_state.shared_mut.borrow(), pinned_by)));
+                        }
+                        ui.set_cursor_pos(resume_pos);
+                    }
+
+                    // Grouped messages have no header, so the time and actions go over the first line.
+                    if grouped && self.hovered_message == Some(message.uuid()) {
+                        let resume_pos = ui.cursor_pos();
+                        _fonts.push(use_font(ui, super::FontType::BOLD15));
+                        ui.set_cursor_pos(body_pos);
+                        ui.dummy([0.0, 0.0]);
+
+                        if let Some(date_time) = &date_time {
+                            let time = time_format::time(date_time, clock);
+                            ui.same_line_with_pos(message_actions_x(ui) - ui.calc_text_size(&time)[0] - 10.0);
+                            ui.text_colored([1.0, 1.0, 1.0, 0.5], time);
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text(time_format::full(date_time, clock));
+                            }
+                        }
+                        self.show_message_actions(ui, message.uuid(), i);
+
+                        ui.set_cursor_pos(resume_pos);
+                    }
+
+                    if message.sender() == current_user.uuid() {
+                        // Messages without a state came from the server.
+                        let state = match chat_state.and_then(|state| state.delivery(message.uuid())).unwrap_or(DeliveryState::DELIVERED) {
+                            DeliveryState::SENT | DeliveryState::DELIVERED if last_read.map_or(false, |read| i <= read) => DeliveryState::READ,
+                            state => state,
+                        };
+
+                        // Only the last message tells how far the conversation got, unless something is off.
+                        if !matches!(state, DeliveryState::DELIVERED | DeliveryState::READ) || last_own == Some(message.uuid()) {
+                            self.show_delivery_state(ui, state, message.uuid(), i, cursor_pos_message);
+                        }
+                    }
+
+                    if let Some(chat_state) = chat_state {
+                        self.show_reactions(ui, current_user, chat_state, message.uuid(), cursor_pos_message, i);
+                    }
+                    if let Some(summary) = threads.get(&message.uuid()) {
+                        self.show_thread_summary(ui, summary, message.uuid(), cursor_pos_message, i);
+                    }
+                    if let Some(readers) = seen_by.get(&message.uuid()) {
+                        self.show_seen_by(ui, readers, cursor_pos_message);
+                    }
                     
+                    let message_end = [ui.window_pos()[0] + ui.content_region_max()[0], ui.cursor_screen_pos()[1]];
+                    if ui.is_window_hovered() && ui.is_mouse_hovering_rect(message_start, message_end) {
+                        hovered_message = Some(message.uuid());
+
+                        if ui.is_mouse_clicked(imgui::MouseButton::Right) {
+                            self.context_message = Some(message.uuid());
+                            ui.open_popup(CONTEXT_MENU_ID);
+                        }
+                        // Clicks on the buttons of the message don't change the selection.
+                        else if ui.is_mouse_clicked(imgui::MouseButton::Left) && !ui.is_any_item_hovered() {
+                            if let Some(selection) = &mut self.selection {
+                                if !selection.remove(&message.uuid()) {
+                                    selection.insert(message.uuid());
+                                }
+                            }
+                        }
+                    }
+
+                    if self.scroll_to == Some(message.uuid()) {
+                        ui.set_scroll_here_y_with_ratio(0.5);
+                        self.scroll_to = None;
+                        self.highlighted = Some((message.uuid(), LgTimer::new()));
+                        scrolled = true;
+                    }
+                    self.show_highlight(ui, message.uuid(), message_start, message_end);
+                    if self.selection.as_ref().map_or(false, |selection| selection.contains(&message.uuid())) {
+                        self.show_selection_highlight(ui, message_start, message_end);
+                    }
+                    if let MessageType::TEXT(text) = message.content() {
+                        if message.sender() != current_user.uuid() && mentions::mentions(text, current_user.uuid()) {
+                            self.show_mention_highlight(ui, message_start, message_end);
+                        }
+                    }
+
                     spacing(ui, 5);
                 }
+
+                self.hovered_message = hovered_message;
+                if let Some(_popup) = ui.begin_popup(CONTEXT_MENU_ID) {
+                    self.show_context_menu(ui, current_user, chat, chat_state);
+                }
+
+                if self.scroll_to.is_some() && !scrolled && !self.waiting_history.is_valid() {
+                    self.history_requested = true;
+                }
             });
     }
-}
\ No newline at end of file
+
+    fn show_day_separator(&self, ui: &imgui::Ui, day: NaiveDate) {
+        let _font = use_font(ui, super::FontType::BOLD15);
+        let label = time_format::day_label(day);
+        let label_size = ui.calc_text_size(&label);
+
+        let start = ui.cursor_screen_pos();
+        let end_x = ui.window_pos()[0] + ui.content_region_max()[0];
+        let center_x = (start[0] + end_x) / 2.0;
+        let line_y = start[1] + label_size[1] / 2.0;
+
+        let draw_list = ui.get_window_draw_list();
+        draw_list
+            .add_line([start[0], line_y], [center_x - label_size[0] / 2.0 - 10.0, line_y], [1.0, 1.0, 1.0, 0.2])
+            .build();
+        draw_list
+            .add_line([center_x + label_size[0] / 2.0 + 10.0, line_y], [end_x, line_y], [1.0, 1.0, 1.0, 0.2])
+            .build();
+
+        ui.set_cursor_screen_pos([center_x - label_size[0] / 2.0, start[1]]);
+        ui.text_colored([1.0, 1.0, 1.0, 0.5], label);
+        spacing(ui, 3);
+    }
+
+    fn show_delivery_state(
+        &mut self,
+        ui: &imgui::Ui,
+        state: DeliveryState,
+        message_uuid: UUID,
+        index: usize,
+        cursor_pos_x: f32,
+    ) {
+        let _font = use_font(ui, super::FontType::BOLD15);
+        ui.set_cursor_pos([cursor_pos_x, ui.cursor_pos()[1]]);
+
+        let (label, color) = match state {
+            DeliveryState::SENDING => ("Sending...", [1.0, 1.0, 1.0, 0.4]),
+            DeliveryState::SENT => ("Sent", [1.0, 1.0, 1.0, 0.4]),
+            DeliveryState::DELIVERED => ("Delivered", [1.0, 1.0, 1.0, 0.4]),
+            DeliveryState::READ => ("Read", self.app_state.theme.positive_btn_color),
+            DeliveryState::FAILED => ("Failed to send", self.app_state.theme.negative_btn_color),
+        };
+        ui.text_colored(color, label);
+
+        if state != DeliveryState::FAILED { return; }
+
+        for (action, width) in [(FailedAction::RETRY, 45.0), (FailedAction::DELETE, 50.0)] {
+            ui.same_line();
+
+            let label = match action {
+                FailedAction::RETRY => "Retry",
+                FailedAction::DELETE => "Delete",
+            };
+            if button(
+                ui,
+                &std::format!("{label}##failed_{index}"),
+                [width, 18.0],
+                BORDER_RADIUS,
+                self.app_state.theme.accent_color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                self.failed_action = Some((message_uuid, action));
+            }
+        }
+    }
+
+    fn show_quote(
+        &mut self,
+        ui: &imgui::Ui,
+        chat: &Chat,
+        quoted_uuid: UUID,
+    ) {
+        let _font = use_font(ui, super::FontType::BOLD15);
+        let quote = chat.messages()
+            .iter()
+            .find(|m| m.uuid() == quoted_uuid)
+            .map(|m| std::format!("{}: {}", user_tag(&self.app_state.shared_mut.borrow(), m.sender()), message_snippet(&self.app_state.shared_mut.borrow(), m, QUOTE_SNIPPET_LEN)))
+            .unwrap_or_else(|| String::from("Original message"));
+
+        let bar_start = ui.cursor_screen_pos();
+        ui.set_cursor_pos([ui.cursor_pos()[0] + 40.0, ui.cursor_pos()[1]]);
+        ui.text_colored([1.0, 1.0, 1.0, 0.6], quote);
+
+        ui.get_window_draw_list()
+            .add_rect(
+                [bar_start[0] + 32.0, bar_start[1]],
+                [bar_start[0] + 35.0, ui.cursor_screen_pos()[1] - 2.0],
+                self.app_state.theme.accent_color
+            )
+            .filled(true)
+            .build();
+
+        if ui.is_item_hovered() {
+            ui.set_mouse_cursor(Some(imgui::MouseCursor::Hand));
+        }
+        if ui.is_item_clicked() {
+            self.scroll_to = Some(quoted_uuid);
+        }
+    }
+
+    fn show_message_actions(&mut self, ui: &imgui::Ui, message_uuid: UUID, index: usize) {
+        let mut x = message_actions_x(ui);
+        for (label, width, action) in MESSAGE_ACTIONS {
+            ui.same_line_with_pos(x);
+            x += width + 5.0;
+
+            if button(
+                ui,
+                &std::format!("{label}##{:?}_{index}", action),
+                [width, 20.0],
+                BORDER_RADIUS,
+                self.app_state.theme.accent_color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                match action {
+                    MessageAction::REACT => ui.open_popup(std::format!("##reaction_picker_{index}")),
+                    MessageAction::THREAD => self.open_thread(message_uuid),
+                    MessageAction::REPLY => self.reply_to = Some(message_uuid),
+                }
+            }
+        }
+    }
+
+    fn show_reaction_picker(&mut self, ui: &imgui::Ui, message_uuid: UUID, index: usize) {
+        if let Some(_popup) = ui.begin_popup(std::format!("##reaction_picker_{index}")) {
+            let _font = use_font(ui, super::FontType::REGULAR24);
+
+            for (j, emoji) in REACTION_EMOJIS.iter().enumerate() {
+                if j > 0 { ui.same_line(); }
+
+                if button(
+                    ui,
+                    emoji,
+                    [35.0, 35.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.mini_pop_up_color,
+                    self.app_state.theme.accent_color,
+                    self.app_state.theme.accent_color,
+                ) {
+                    self.reaction_toggle = Some((message_uuid, emoji.to_string()));
+                    ui.close_current_popup();
+                }
+            }
+            ui.separator();
+
+            if let Some(emoji) = self.emoji_picker.show(ui, &self.app_state.theme, "reaction") {
+                self.reaction_toggle = Some((message_uuid, emoji.to_string()));
+                ui.close_current_popup();
+            }
+        }
+    }
+
+    fn show_reactions(
+        &mut self,
+        ui: &imgui::Ui,
+        current_user: &User,
+        chat_state: &ChatState,
+        message_uuid: UUID,
+        cursor_pos_x: f32,
+        index: usize,
+    ) {
+        let reactions = chat_state.reactions(message_uuid);
+        if reactions.is_empty() { return; }
+
+        let _font = use_font(ui, super::FontType::BOLD17);
+        ui.set_cursor_pos([cursor_pos_x, ui.cursor_pos()[1]]);
+
+        for (j, (emoji, users)) in reactions.iter().enumerate() {
+            if j > 0 { ui.same_line(); }
+
+            let color = if users.contains(&current_user.uuid()) { self.app_state.theme.sign_up_btn_color }
+            else { self.app_state.theme.accent_color };
+
+            if button(
+                ui,
+                &std::format!("{} {}##reaction_{index}_{j}", emoji, users.len()),
+                [0.0, 0.0],
+                BORDER_RADIUS,
+                color,
+                self.app_state.theme.sign_up_actv_btn_color,
+                self.app_state.theme.sign_up_actv_btn_color,
+            ) {
+                self.reaction_toggle = Some((message_uuid, emoji.clone()));
+            }
+        }
+    }
+
+    fn show_thread_summary(
+        &mut self,
+        ui: &imgui::Ui,
+        summary: &ThreadSummary,
+        root: UUID,
+        cursor_pos_x: f32,
+        index: usize,
+    ) {
+        let _font = use_font(ui, super::FontType::BOLD15);
+        ui.set_cursor_pos([cursor_pos_x, ui.cursor_pos()[1]]);
+
+        for (j, _) in summary.last_repliers.iter().enumerate() {
+            button(
+                ui,
+                &std::format!("##thread_avatar_{index}_{j}"),
+                [16.0, 16.0],
+                BORDER_RADIUS,
+                self.app_state.theme.positive_btn_color,
+                self.app_state.theme.positive_btn_color,
+                self.app_state.theme.positive_btn_color,
+            );
+            ui.same_line_with_spacing(0.0, 2.0);
+        }
+
+        let label = if summary.replies == 1 { String::from("1 reply") }
+        else { std::format!("{} replies", summary.replies) };
+
+        ui.text_colored(self.app_state.theme.sign_up_actv_btn_color, label);
+        if ui.is_item_hovered() {
+            ui.set_mouse_cursor(Some(imgui::MouseCursor::Hand));
+        }
+        if ui.is_item_clicked() {
+            self.open_thread(root);
+        }
+    }
+
+    fn show_seen_by(
+        &self,
+        ui: &imgui::Ui,
+        readers: &[UUID],
+        cursor_pos_x: f32,
+    ) {
+        let _font = use_font(ui, super::FontType::BOLD15);
+
+        let mut names = readers
+            .iter()
+            .take(SEEN_BY_NAMES)
+            .map(|u| user_tag(&self.app_state.shared_mut.borrow(), *u))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if readers.len() > SEEN_BY_NAMES {
+            names.push_str(&std::format!(" +{}", readers.len() - SEEN_BY_NAMES));
+        }
+
+        ui.set_cursor_pos([cursor_pos_x, ui.cursor_pos()[1]]);
+        ui.text_colored([1.0, 1.0, 1.0, 0.4], std::format!("Seen by {names}"));
+    }
+
+    // Only one side panel is shown at a time.
+    fn open_thread(&mut self, root: UUID) {
+        self.chat_info.close();
+        self.thread_panel.open(root);
+    }
+
+    // Messages that mention the current user stand out from the rest.
+    fn show_mention_highlight(&self, ui: &imgui::Ui, start: [f32; 2], end: [f32; 2]) {
+        let mut color = self.app_state.theme.sign_up_btn_color;
+        color[3] = 0.12;
+
+        let draw_list = ui.get_window_draw_list();
+        draw_list
+            .add_rect(start, end, color)
+            .filled(true)
+            .rounding(BORDER_RADIUS)
+            .build();
+        draw_list
+            .add_rect(start, [start[0] + 3.0, end[1]], self.app_state.theme.sign_up_btn_color)
+            .filled(true)
+            .build();
+    }
+
+    fn show_highlight(
+        &mut self,
+        ui: &imgui::Ui,
+        message_uuid: UUID,
+        start: [f32; 2],
+        end: [f32; 2],
+    ) {
+        let alpha = match &self.highlighted {
+            Some((uuid, timer)) if *uuid == message_uuid => 1.0 - (timer.elapsed().get_seconds() / 2.0) as f32,
+            _ => return,
+        };
+
+        if alpha <= 0.0 {
+            self.highlighted = None;
+            return;
+        }
+
+        let mut color = self.app_state.theme.sign_up_btn_color;
+        color[3] = 0.3 * alpha;
+
+        ui.get_window_draw_list()
+            .add_rect(start, end, color)
+            .filled(true)
+            .rounding(BORDER_RADIUS)
+            .build();
+    }
+
+    fn show_selection_highlight(&self, ui: &imgui::Ui, start: [f32; 2], end: [f32; 2]) {
+        let mut color = self.app_state.theme.accent_color;
+        color[3] = 0.35;
+
+        ui.get_window_draw_list()
+            .add_rect(start, end, color)
+            .filled(true)
+            .rounding(BORDER_RADIUS)
+            .build();
+    }
+
+    fn show_context_menu(
+        &mut self,
+        ui: &imgui::Ui,
+        current_user: &User,
+        chat: &Chat,
+        chat_state: Option<&ChatState>,
+    ) {
+        let message = if let Some(message) = self.context_message.and_then(|uuid| chat.messages().iter().find(|m| m.uuid() == uuid)) { message }
+        else {
+            ui.close_current_popup();
+            return;
+        };
+        let message_uuid = message.uuid();
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let text = match message.content() {
+            MessageType::TEXT(text) => Some(mentions::display_text(&shared_mut, text)),
+            MessageType::FILE(_) => None,
+        };
+        let bookmarked = shared_mut.bookmarks.iter().any(|bookmark| bookmark.message_uuid == message_uuid);
+        let can_copy = can_keep_copy(&shared_mut, chat.uuid(), message);
+        let offline = shared_mut.offline;
+        drop(shared_mut);
+
+        if let Some(text) = &text {
+            if ui.selectable("Copy text") {
+                ui.set_clipboard_text(text);
+            }
+        }
+        if ui.selectable("Copy link") {
+            ui.set_clipboard_text(message_link(chat.uuid(), message_uuid));
+        }
+        if ui.selectable("Copy message ID") {
+            ui.set_clipboard_text(message_uuid.to_string());
+        }
+
+        ui.separator();
+        if can_copy && ui.selectable("Forward...") {
+            self.forward.open(vec![message_uuid]);
+        }
+        // Pins are shared with the other members, so they need the Server.
+        let pinned = chat_state.map_or(false, |state| state.is_pinned(message_uuid));
+        if !offline && ui.selectable(if pinned { "Unpin" } else { "Pin to chat" }) {
+            self.menu_action = Some((MenuAction::PIN, vec![message_uuid]));
+        }
+        // A bookmark made before the Chat turned on encryption can still be removed.
+        if text.is_some() && (can_copy || bookmarked) && ui.selectable(if bookmarked { "Remove bookmark" } else { "Bookmark" }) {
+            self.menu_action = Some((MenuAction::BOOKMARK, vec![message_uuid]));
+        }
+        if ui.selectable("Select") {
+            self.selection = Some(HashSet::from([message_uuid]));
+        }
+
+        // Other users' messages are theirs to delete.
+        if message.sender() == current_user.uuid() && !offline {
+            ui.separator();
+            let _color = ui.push_style_color(imgui::StyleColor::Text, self.app_state.theme.negative_btn_color);
+            if ui.selectable("Delete") {
+                self.menu_action = Some((MenuAction::DELETE, vec![message_uuid]));
+            }
+        }
+    }
+
+    fn show_typing_indicator(
+        &self,
+        ui: &imgui::Ui,
+        current_user: &User,
+        chat_state: Option<&ChatState>,
+    ) {
+        let cursor_pos = ui.cursor_pos();
+
+        let typers = chat_state
+            .map(|state| state.typing_users())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|u| *u != current_user.uuid())
+            .map(|u| user_tag(&self.app_state.shared_mut.borrow(), u))
+            .collect::<Vec<_>>();
+
+        let text = match typers.as_slice() {
+            [] => None,
+            [user] => Some(std::format!("{user} is typing...")),
+            [first, second] => Some(std::format!("{first} and {second} are typing...")),
+            [first, second, third] => Some(std::format!("{first}, {second} and {third} are typing...")),
+            _ => Some(std::format!("{} and {} others are typing...", typers[..TYPING_NAMES].join(", "), typers.len() - TYPING_NAMES)),
+        };
+
+        if let Some(text) = text {
+            let _font = use_font(ui, super::FontType::BOLD15);
+            ui.text_colored([1.0, 1.0, 1.0, 0.6], text);
+        }
+
+        ui.set_cursor_pos([cursor_pos[0], cursor_pos[1] + 20.0]);
+    }
+
+    // Line between the messages and the composer.
+    fn show_composer_bar(
+        &mut self,
+        ui: &imgui::Ui,
+        chat: &Chat,
+    ) {
+        let cursor_pos = ui.cursor_pos();
+
+        if self.selection.is_some() {
+            self.show_selection_bar(ui, chat);
+        }
+        else {
+            if self.app_state.shared_mut.borrow().offline {
+                let _font = use_font(ui, super::FontType::BOLD15);
+                ui.set_cursor_pos([cursor_pos[0], cursor_pos[1] + 8.0]);
+                ui.text_colored([1.0, 1.0, 1.0, 0.6], "Offline, keep writing and send once reconnected.");
+            }
+            else if !self.show_command_palette(ui) && !self.show_mention_suggestions(ui) && !self.show_emoji_suggestions(ui) && !self.show_editing_scheduled(ui) {
+                self.show_reply_preview(ui, chat);
+            }
+
+            self.show_scheduled_list(ui, chat, cursor_pos);
+        }
+
+        ui.set_cursor_pos([cursor_pos[0], cursor_pos[1] + 35.0]);
+    }
+
+    // Takes the place of the composer bar on multi-select mode.
+    fn show_selection_bar(&mut self, ui: &imgui::Ui, chat: &Chat) {
+        if ui.is_key_pressed(imgui::Key::Escape) {
+            self.selection = None;
+            return;
+        }
+
+        let selected = if let Some(selection) = &self.selection {
+            chat.messages()
+                .iter()
+                .filter(|m| selection.contains(&m.uuid()))
+                .collect::<Vec<_>>()
+        }
+        else { return; };
+
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+        let can_delete = !shared_mut.offline && !selected.is_empty() && selected.iter().all(|m| Some(m.sender()) == current_user);
+        let can_forward = !selected.is_empty() && selected.iter().all(|m| can_keep_copy(&shared_mut, chat.uuid(), m));
+        drop(shared_mut);
+
+        let cursor_pos = ui.cursor_pos();
+        {
+            let _font = use_font(ui, super::FontType::BOLD15);
+            ui.set_cursor_pos([cursor_pos[0], cursor_pos[1] + 8.0]);
+            ui.text_colored([1.0, 1.0, 1.0, 0.6], std::format!("{} selected, click messages to add or remove them", selected.len()));
+        }
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        ui.set_cursor_pos([ui.content_region_max()[0] - 275.0, cursor_pos[1] + 5.0]);
+        if button(
+            ui,
+            "Copy##selection_copy",
+            [60.0, 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) && !selected.is_empty() {
+            ui.set_clipboard_text(self.selection_text(&selected));
+        }
+
+        ui.same_line();
+        if button(
+            ui,
+            "Forward##selection_forward",
+            [75.0, 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) && can_forward {
+            self.forward.open(selected.iter().map(|m| m.uuid()).collect());
+        }
+        if ui.is_item_hovered() && !can_forward && !selected.is_empty() {
+            ui.tooltip_text("Disappearing and encrypted messages can't be forwarded");
+        }
+
+        ui.same_line();
+        let delete_color = if can_delete { self.app_state.theme.negative_btn_color } else { self.app_state.theme.accent_color };
+        if button(
+            ui,
+            "Delete##selection_delete",
+            [60.0, 25.0],
+            BORDER_RADIUS,
+            delete_color,
+            self.app_state.theme.negative_actv_btn_color,
+            self.app_state.theme.negative_actv_btn_color,
+        ) && can_delete {
+            self.menu_action = Some((MenuAction::DELETE, selected.iter().map(|m| m.uuid()).collect()));
+            self.selection = None;
+        }
+        if ui.is_item_hovered() && !can_delete {
+            ui.tooltip_text("Only your own messages can be deleted, while online");
+        }
+
+        ui.same_line();
+        if button(
+            ui,
+            "Cancel##selection_cancel",
+            [60.0, 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) {
+            self.selection = None;
+        }
+    }
+
+    // One "tag (time): text" line per Message, in the Chat order.
+    fn selection_text(&self, messages: &[&Message]) -> String {
+        let shared_mut = self.app_state.shared_mut.borrow();
+
+        messages
+            .iter()
+            .map(|message| {
+                let text = match message.content() {
+                    MessageType::TEXT(text) => mentions::display_text(&shared_mut, text),
+                    MessageType::FILE(_) => String::from("[File]"),
+                };
+                let time = message.date_time()
+                    .to_local()
+                    .map(|date_time| time_format::full(&date_time, shared_mut.clock_format))
+                    .unwrap_or_default();
+
+                std::format!("{} ({time}): {text}", user_tag(&shared_mut, message.sender()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn show_editing_scheduled(&mut self, ui: &imgui::Ui) -> bool {
+        let send_at = self.editing_scheduled.and_then(|uuid| self.app_state.shared_mut.borrow().scheduled
+            .iter()
+            .find(|scheduled| scheduled.uuid == uuid)
+            .map(|scheduled| scheduled.send_at)
+        );
+        let send_at = if let Some(send_at) = send_at { send_at }
+        else { return false; };
+
+        let _font = use_font(ui, super::FontType::BOLD15);
+        ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 8.0]);
+        let clock = self.app_state.shared_mut.borrow().clock_format;
+        ui.text_colored([1.0, 1.0, 1.0, 0.6], std::format!("Editing the message scheduled for {}, Enter saves it", time_format::full(&send_at, clock)));
+
+        ui.same_line_with_pos(ui.content_region_max()[0] - 20.0);
+        if button(
+            ui,
+            "X##cancel_scheduled_edit",
+            [20.0, 20.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) {
+            self.editing_scheduled = None;
+            self.message_buffer.clear();
+            self.composer_mentions.clear();
+            self.buffer_replacement = Some(String::default());
+        }
+
+        true
+    }
+
+    // Button with the count of scheduled messages of the Chat, opens the list of them.
+    fn show_scheduled_list(&mut self, ui: &imgui::Ui, chat: &Chat, cursor_pos: [f32; 2]) {
+        let mut scheduled = self.app_state.shared_mut.borrow().scheduled
+            .iter()
+            .filter(|scheduled| scheduled.chat_uuid == chat.uuid())
+            .cloned()
+            .collect::<Vec<_>>();
+        if scheduled.is_empty() { return; }
+        scheduled.sort_by_key(|scheduled| scheduled.send_at);
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        // Left of the close button of the reply preview.
+        ui.set_cursor_pos([ui.content_region_max()[0] - 140.0, cursor_pos[1] + 5.0]);
+        if button(
+            ui,
+            &std::format!("⏰ {} scheduled##scheduled_list", scheduled.len()),
+            [110.0, 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.mini_pop_up_color,
+            self.app_state.theme.sign_up_btn_color,
+            self.app_state.theme.sign_up_btn_color,
+        ) {
+            ui.open_popup("##scheduled_list_popup");
+        }
+
+        let _popup = if let Some(popup) = ui.begin_popup("##scheduled_list_popup") { popup }
+        else { return; };
+
+        let clock = self.app_state.shared_mut.borrow().clock_format;
+        for scheduled in scheduled {
+            let text = mentions::display_text(&self.app_state.shared_mut.borrow(), &scheduled.text);
+            let snippet = match text.char_indices().nth(QUOTE_SNIPPET_LEN) {
+                Some((end, _)) => std::format!("{}...", &text[..end]),
+                None => text,
+            };
+
+            ui.text_colored([1.0, 1.0, 1.0, 0.6], time_format::full(&scheduled.send_at, clock));
+            ui.text(snippet);
+
+            let actions = [("Edit", ScheduledAction::EDIT), ("Send now", ScheduledAction::SEND_NOW), ("Cancel", ScheduledAction::CANCEL)];
+            for (i, (label, action)) in actions.into_iter().enumerate() {
+                if i > 0 { ui.same_line(); }
+
+                let color = match action {
+                    ScheduledAction::CANCEL => self.app_state.theme.negative_btn_color,
+                    _ => self.app_state.theme.accent_color,
+                };
+                if button(
+                    ui,
+                    &std::format!("{label}##scheduled_{}", scheduled.uuid.to_string()),
+                    [80.0, 22.0],
+                    BORDER_RADIUS,
+                    color,
+                    self.app_state.theme.sign_up_btn_color,
+                    self.app_state.theme.sign_up_btn_color,
+                ) {
+                    self.scheduled_action = Some((scheduled.uuid, action));
+                    ui.close_current_popup();
+                }
+            }
+            ui.separator();
+        }
+    }
+
+    // Quick choices and a custom time for the composer text.
+    fn show_schedule_popup(&mut self, ui: &imgui::Ui) {
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        if self.message_buffer.trim().is_empty() {
+            ui.text_colored([1.0, 1.0, 1.0, 0.6], "Write the message first, then pick when to send it.");
+            return;
+        }
+
+        {
+            let _font = use_font(ui, super::FontType::BOLD17);
+            ui.text("Send later");
+        }
+
+        let clock = self.app_state.shared_mut.borrow().clock_format;
+        let now = chrono::Local::now();
+        for (label, send_at) in scheduled::presets(&now) {
+            if button(
+                ui,
+                &std::format!("{label}, {}##schedule_preset", time_format::time(&send_at, clock)),
+                [260.0, 25.0],
+                BORDER_RADIUS,
+                self.app_state.theme.accent_color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                self.schedule_at = Some(send_at);
+                ui.close_current_popup();
+            }
+        }
+
+        spacing(ui, 2);
+        ui.set_next_item_width(260.0);
+        let entered = text_input(
+            ui,
+            scheduled::SEND_AT_HINT,
+            &mut self.schedule_input,
+            "##schedule_input",
+            self.app_state.theme.input_text_bg_light,
+            [0.0, 0.0, 0.0, 1.0],
+            BORDER_RADIUS,
+            imgui::InputTextFlags::CALLBACK_RESIZE
+            | imgui::InputTextFlags::ENTER_RETURNS_TRUE
+        );
+
+        let schedule = button(
+            ui,
+            "Schedule##schedule_custom",
+            [260.0, 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.positive_btn_color,
+            self.app_state.theme.positive_actv_btn_color,
+            self.app_state.theme.positive_actv_btn_color,
+        ) || entered;
+
+        if schedule {
+            match scheduled::parse_send_at(&self.schedule_input, &now) {
+                Ok(send_at) => {
+                    self.schedule_at = Some(send_at);
+                    self.schedule_input.clear();
+                    self.schedule_error = None;
+                    ui.close_current_popup();
+                },
+                Err(e) => self.schedule_error = Some(e),
+            }
+        }
+
+        if let Some(error) = &self.schedule_error {
+            ui.text_colored(self.app_state.theme.negative_btn_color, error);
+        }
+    }
+
+    fn show_reply_preview(
+        &mut self,
+        ui: &imgui::Ui,
+        chat: &Chat,
+    ) {
+        let cursor_pos = ui.cursor_pos();
+        let preview = self.reply_to.and_then(|reply_to| chat.messages()
+            .iter()
+            .find(|m| m.uuid() == reply_to)
+            .map(|m| std::format!("Replying to {}: {}", user_tag(&self.app_state.shared_mut.borrow(), m.sender()), message_snippet(&self.app_state.shared_mut.borrow(), m, QUOTE_SNIPPET_LEN)))
+        );
+
+        if let Some(preview) = preview {
+            let _font = use_font(ui, super::FontType::BOLD15);
+            ui.set_cursor_pos([cursor_pos[0], cursor_pos[1] + 8.0]);
+            ui.text_colored([1.0, 1.0, 1.0, 0.6], preview);
+
+            ui.same_line_with_pos(ui.content_region_max()[0] - 20.0);
+            if button(
+                ui,
+                "X##cancel_reply",
+                [20.0, 20.0],
+                BORDER_RADIUS,
+                self.app_state.theme.accent_color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                self.reply_to = None;
+            }
+        }
+    }
+
+    // Commands matching the name being typed, or the arguments of the chosen one.
+    fn show_command_palette(&mut self, ui: &imgui::Ui) -> bool {
+        if self.command_error.as_ref().map_or(false, |(_, buffer)| *buffer != self.message_buffer) {
+            self.command_error = None;
+        }
+        if let Some((error, _)) = &self.command_error {
+            let _font = use_font(ui, super::FontType::BOLD15);
+            ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 8.0]);
+            ui.text_colored(self.app_state.theme.negative_btn_color, error);
+
+            return true;
+        }
+
+        let palette = if let Some(palette) = commands::palette(&self.message_buffer) { palette }
+        else { return false; };
+
+        match palette {
+            Palette::NAMES(specs) => {
+                let _font = use_font(ui, super::FontType::REGULAR17);
+                ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 4.0]);
+
+                for (i, spec) in specs.into_iter().enumerate() {
+                    if i > 0 { ui.same_line(); }
+
+                    let color = if i == 0 { self.app_state.theme.accent_color } else { self.app_state.theme.mini_pop_up_color };
+                    if button(
+                        ui,
+                        &std::format!("/{}##command_suggestion_{i}", spec.name),
+                        [0.0, 25.0],
+                        BORDER_RADIUS,
+                        color,
+                        self.app_state.theme.sign_up_btn_color,
+                        self.app_state.theme.sign_up_btn_color,
+                    ) {
+                        self.complete_command(spec);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(std::format!("/{} {}\n{}", spec.name, spec.args, spec.description));
+                    }
+                }
+            },
+            Palette::HINT(spec) => {
+                let _font = use_font(ui, super::FontType::BOLD15);
+                ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 8.0]);
+                ui.text_colored([1.0, 1.0, 1.0, 0.6], std::format!("/{} {}", spec.name, spec.args));
+                ui.same_line();
+                ui.text_colored([1.0, 1.0, 1.0, 0.4], spec.description);
+            },
+        }
+
+        true
+    }
+
+    fn complete_command(&mut self, spec: &CommandSpec) {
+        self.message_buffer = std::format!("/{} ", spec.name);
+        self.buffer_replacement = Some(self.message_buffer.clone());
+    }
+
+    // Commands that only touch the open Chat.
+    fn run_command(&mut self, server_coms: &mut ServerCommunication, chat_uuid: UUID, command: Command, composer_mentions: &[(String, UUID)]) -> Result<(), StdError> {
+        let (user_uuid, user_tag) = match &self.app_state.shared_mut.borrow().user {
+            Some(user) => (user.uuid(), user.tag().to_string()),
+            None => return Ok(()),
+        };
+
+        let text = match command {
+            Command::ME(action) => std::format!("*{user_tag} {action}*"),
+            Command::SHRUG(text) => if text.is_empty() { SHRUG.to_string() } else { std::format!("{text} {SHRUG}") },
+            Command::LEAVE => {
+                let is_group = self.app_state.shared_mut.borrow().chats.get(&chat_uuid).map_or(false, is_group);
+                if !is_group {
+                    self.command_error = Some((String::from("Only groups can be left"), String::default()));
+                    return Ok(());
+                }
+
+                let can_leave = self.app_state.shared_mut.borrow().chats.get(&chat_uuid).map_or(false, |chat| chat_info_gui::can_leave(chat, user_uuid));
+                if !can_leave {
+                    self.command_error = Some((String::from("Make another member owner to leave the group"), String::default()));
+                    return Ok(());
+                }
+
+                server_coms.send(ServerMessage::from(ServerMessageContent::MODIFICATION(Modification::CHAT_LEAVE(chat_uuid, user_uuid))))?;
+                self.leave = true;
+
+                return Ok(());
+            },
+            Command::CLEAR => {
+                let _ = self.app_state.shared_mut.borrow_mut().cleared.insert(chat_uuid, chrono::Local::now());
+                return Ok(());
+            },
+            _ => return Err(std::format!("In ChatGuiManager::run_command: {:?} is not run by the chat page!", command).into()),
+        };
+
+        let text = emoji::replace_shortcodes(&mentions::encode_all(&text, composer_mentions));
+        let message = Message::new(user_uuid, MessageType::TEXT(text), DateTime::from_utc(&chrono::Utc::now()));
+
+        self.send_chat_message(server_coms, chat_uuid, message)
+    }
+
+    // Members of the open Chat matching the "@" being typed, (Tag, User UUID).
+    fn mention_suggestions(&self) -> Vec<(String, UUID)> {
+        let query = if let Some((_, query)) = mentions::mention_query(&self.message_buffer) { query.to_lowercase() }
+        else { return Vec::default(); };
+
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+        let chat = if let Some(chat) = self.chat_uuid.and_then(|chat_uuid| shared_mut.chats.get(&chat_uuid)) { chat }
+        else { return Vec::default(); };
+
+        chat.users()
+            .iter()
+            .filter(|member| Some(**member) != current_user)
+            .map(|member| (user_tag(&shared_mut, *member), *member))
+            .filter(|(tag, _)| tag.to_lowercase().contains(&query))
+            .take(MAX_MENTION_SUGGESTIONS)
+            .collect()
+    }
+
+    fn show_mention_suggestions(&mut self, ui: &imgui::Ui) -> bool {
+        let suggestions = self.mention_suggestions();
+        if suggestions.is_empty() { return false; }
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 4.0]);
+
+        for (i, (tag, user_uuid)) in suggestions.into_iter().enumerate() {
+            if i > 0 { ui.same_line(); }
+
+            let color = if i == 0 { self.app_state.theme.accent_color } else { self.app_state.theme.mini_pop_up_color };
+            if button(
+                ui,
+                &std::format!("@{tag}##mention_suggestion_{i}"),
+                [0.0, 25.0],
+                BORDER_RADIUS,
+                color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                self.complete_mention(tag, user_uuid);
+            }
+        }
+
+        true
+    }
+
+    // The composer shows "@tag", it's encoded by UUID when sent.
+    fn complete_mention(&mut self, tag: String, user_uuid: UUID) {
+        if let Some((start, _)) = mentions::mention_query(&self.message_buffer) {
+            self.message_buffer.truncate(start);
+            self.message_buffer.push_str(&std::format!("@{tag} "));
+            self.buffer_replacement = Some(self.message_buffer.clone());
+            self.composer_mentions.push((tag, user_uuid));
+        }
+    }
+
+    fn emoji_suggestions(&self) -> Vec<&'static emoji::Emoji> {
+        emoji::shortcode_query(&self.message_buffer)
+            .map(|(_, query)| emoji::search(query).take(MAX_EMOJI_SUGGESTIONS).collect())
+            .unwrap_or_default()
+    }
+
+    fn show_emoji_suggestions(&mut self, ui: &imgui::Ui) -> bool {
+        let suggestions = self.emoji_suggestions();
+        if suggestions.is_empty() { return false; }
+
+        let _font = use_font(ui, super::FontType::REGULAR17);
+        ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 4.0]);
+
+        for (i, suggestion) in suggestions.iter().enumerate() {
+            if i > 0 { ui.same_line(); }
+
+            let color = if i == 0 { self.app_state.theme.accent_color } else { self.app_state.theme.mini_pop_up_color };
+            if button(
+                ui,
+                &std::format!("{} :{}:##emoji_suggestion_{i}", suggestion.emoji, suggestion.shortcode),
+                [0.0, 25.0],
+                BORDER_RADIUS,
+                color,
+                self.app_state.theme.sign_up_btn_color,
+                self.app_state.theme.sign_up_btn_color,
+            ) {
+                self.complete_shortcode(suggestion.emoji);
+            }
+        }
+
+        true
+    }
+
+    fn complete_shortcode(&mut self, emoji: &str) {
+        if let Some((start, _)) = emoji::shortcode_query(&self.message_buffer) {
+            self.message_buffer.truncate(start);
+            self.message_buffer.push_str(emoji);
+            self.message_buffer.push(' ');
+            self.buffer_replacement = Some(self.message_buffer.clone());
+        }
+    }
+
+    // Shows the message right away and sends it, the delivery state follows the server answers.
+    fn send_chat_message(&mut self, server_coms: &mut ServerCommunication, chat_uuid: UUID, mut message: Message) -> Result<(), StdError> {
+        let message_uuid = message.uuid();
+        if let Some(chat) = self.app_state.shared_mut.borrow_mut().chats.get_mut(&chat_uuid) {
+            // Stamped once, a retry keeps the expiry the Chat had when it was first sent.
+            if let (None, Some(expiry)) = (message.expiry(), chat.expiry()) {
+                message = message.with_expiry(expiry);
+            }
+            if !chat.messages().iter().any(|m| m.uuid() == message_uuid) {
+                chat.push_message(message.clone());
+            }
+        }
+        self.set_delivery(chat_uuid, message_uuid, DeliveryState::SENDING);
+
+        // Only the copy for the server is encrypted, ours keeps the plaintext.
+        if let MessageType::TEXT(text) = message.content() {
+            let encrypted = self.app_state.shared_mut.borrow_mut().e2e.encrypt(chat_uuid, message.sender(), message_uuid, text);
+            match encrypted {
+                Ok(Some(encrypted)) => message.set_content(MessageType::TEXT(encrypted)),
+                Ok(None) => (),
+                Err(e) => {
+                    self.set_delivery(chat_uuid, message_uuid, DeliveryState::FAILED);
+                    return Err(e);
+                },
+            }
+        }
+
+        let msg_uuid = UUID::generate();
+        let _ = self.sending.insert(msg_uuid, (chat_uuid, message_uuid, LgTimer::new(), message));
+
+        self.resend_chat_message(server_coms, msg_uuid)
+    }
+
+    // Sends a Message in `sending` with its original server message UUID, like ComsManager's own retries.
+    // The Server gets the same message however many copies arrive, and any of their answers marks it as sent.
+    fn resend_chat_message(&mut self, server_coms: &mut ServerCommunication, msg_uuid: UUID) -> Result<(), StdError> {
+        let (chat_uuid, message_uuid, message) = match self.sending.get_mut(&msg_uuid) {
+            Some((chat_uuid, message_uuid, timer, message)) => {
+                timer.restart();
+                (*chat_uuid, *message_uuid, message.clone())
+            },
+            None => return Err(std::format!("In ChatGuiManager::resend_chat_message: {} is not being sent!", msg_uuid.to_string()).into()),
+        };
+        self.set_delivery(chat_uuid, message_uuid, DeliveryState::SENDING);
+
+        if let Err(e) = server_coms.send(ServerMessage::new(msg_uuid, ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::NEW_MESSAGE(chat_uuid, message))))) {
+            self.set_delivery(chat_uuid, message_uuid, DeliveryState::FAILED);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    // Sends our key to the contact, encryption is on once theirs comes back.
+    fn send_e2e_key(&mut self, server_coms: &mut ServerCommunication, chat_uuid: UUID) -> Result<(), StdError> {
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+        let user_uuid = shared_mut.user.as_ref().map(|user| user.uuid()).ok_or("In ChatGuiManager::send_e2e_key: No user!")?;
+        let peer = shared_mut.chats
+            .get(&chat_uuid)
+            .and_then(|chat| chat.users().iter().find(|member| **member != user_uuid).copied())
+            .ok_or("In ChatGuiManager::send_e2e_key: Chat has no contact!")?;
+
+        let key = shared_mut.e2e.enable(chat_uuid, peer).ok_or("In ChatGuiManager::send_e2e_key: This device has no identity key!")?;
+        // Not shown right away, the echo is revealed as a notice by ClientManager.
+        let key_message = Message::new(user_uuid, MessageType::TEXT(key), DateTime::from_utc(&chrono::Utc::now()));
+        server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::NEW_MESSAGE(chat_uuid, key_message)))))
+    }
+
+    // Removes the Message from this client only.
+    fn delete_local_message(&mut self, chat_uuid: UUID, message_uuid: UUID) {
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+        if let Some(chat) = shared_mut.chats.get_mut(&chat_uuid) {
+            let mut messages = chat.messages()
+                .iter()
+                .filter(|m| m.uuid() != message_uuid)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            chat.clear_messages();
+            chat.append_messages(&mut messages);
+        }
+        if let Some(chat_state) = shared_mut.chat_states.get_mut(&chat_uuid) {
+            chat_state.forget(message_uuid);
+        }
+        shared_mut.e2e.forget(message_uuid);
+        let _ = self.markdown_cache.remove(&message_uuid);
+        // Whatever copy still reaches the Server is no longer followed.
+        self.sending.retain(|_, (_, sent_uuid, _, _)| *sent_uuid != message_uuid);
+    }
+
+    // Shown right away, like reactions, the echo from the Server changes nothing.
+    fn toggle_pins(&mut self, server_coms: &mut ServerCommunication, chat_uuid: UUID, message_uuids: &[UUID]) -> Result<(), StdError> {
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+        let user_uuid = shared_mut.user.as_ref().map(|user| user.uuid()).ok_or("In ChatGuiManager::toggle_pins: No user!")?;
+        let chat_state = shared_mut.chat_states.entry(chat_uuid).or_default();
+
+        for message_uuid in message_uuids.iter().copied() {
+            let notification = if chat_state.is_pinned(message_uuid) {
+                chat_state.unpin(message_uuid);
+                NotificationType::MESSAGE_UNPINNED(chat_uuid, message_uuid, user_uuid)
+            }
+            else {
+                chat_state.pin(message_uuid, user_uuid);
+                NotificationType::MESSAGE_PINNED(chat_uuid, message_uuid, user_uuid)
+            };
+
+            server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(notification))))?;
+        }
+
+        Ok(())
+    }
+
+    fn toggle_bookmarks(&mut self, chat_uuid: UUID, message_uuids: &[UUID]) {
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+        let chat = if let Some(chat) = shared_mut.chats.get(&chat_uuid) { chat }
+        else { return; };
+
+        for message in chat.messages().iter().filter(|m| message_uuids.contains(&m.uuid())) {
+            let bookmarked = shared_mut.bookmarks.iter().any(|bookmark| bookmark.message_uuid == message.uuid());
+            if !bookmarked && !can_keep_copy(shared_mut, chat_uuid, message) { continue; }
+
+            if let MessageType::TEXT(text) = message.content() {
+                let _ = bookmarks::toggle(&mut shared_mut.bookmarks, Bookmark::new(chat_uuid, message.uuid(), message.sender(), text.clone()));
+            }
+        }
+    }
+
+    // Copies of the messages of the open Chat, sent as the current user and crediting the original sender.
+    fn forwarded_messages(&self, message_uuids: &[UUID]) -> Vec<Message> {
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let (user, chat) = match (&shared_mut.user, self.chat_uuid.and_then(|chat_uuid| shared_mut.chats.get(&chat_uuid))) {
+            (Some(user), Some(chat)) => (user, chat),
+            _ => return Vec::default(),
+        };
+
+        chat.messages()
+            .iter()
+            .filter(|m| message_uuids.contains(&m.uuid()) && can_keep_copy(&shared_mut, chat.uuid(), m))
+            .filter_map(|m| match m.content() {
+                // The tag instead of a mention, the sender may not be on the other Chat.
+                MessageType::TEXT(text) => Some(Message::new(
+                    user.uuid(),
+                    MessageType::TEXT(std::format!("Forwarded from {}:\n{text}", user_tag(&shared_mut, m.sender()))),
+                    DateTime::from_utc(&chrono::Utc::now())
+                )),
+                MessageType::FILE(_) => None,
+            })
+            .collect()
+    }
+
+    fn delivery(&self, chat_uuid: UUID, message_uuid: UUID) -> Option<DeliveryState> {
+        self.app_state.shared_mut.borrow().chat_states
+            .get(&chat_uuid)
+            .and_then(|state| state.delivery(message_uuid))
+    }
+
+    fn set_delivery(&self, chat_uuid: UUID, message_uuid: UUID, state: DeliveryState) {
+        self.app_state.shared_mut
+            .borrow_mut()
+            .chat_states
+            .entry(chat_uuid)
+            .or_default()
+            .set_delivery(message_uuid, state);
+    }
+
+    // Schedules the composer text, or applies what was picked on the list of scheduled messages.
+    fn update_scheduled(&mut self) {
+        // Enter while editing a scheduled Message saves it with the same time.
+        if self.send_message { if let Some(editing) = self.editing_scheduled {
+            let send_at = self.app_state.shared_mut.borrow().scheduled
+                .iter()
+                .find(|scheduled| scheduled.uuid == editing)
+                .map(|scheduled| scheduled.send_at);
+
+            match send_at {
+                Some(send_at) if !commands::is_command(&self.message_buffer) => {
+                    self.send_message = false;
+                    self.schedule_at = Some(send_at);
+                },
+                _ => self.editing_scheduled = None,
+            }
+        }}
+
+        if let (Some(send_at), Some(chat_uuid)) = (self.schedule_at.take(), self.chat_uuid) {
+            if commands::is_command(&self.message_buffer) {
+                self.command_error = Some((String::from("Commands can't be scheduled"), self.message_buffer.clone()));
+            }
+            else if !self.message_buffer.trim().is_empty() {
+                let text = mentions::encode_all(commands::unescape(&std::mem::take(&mut self.message_buffer)), &std::mem::take(&mut self.composer_mentions));
+                let text = emoji::replace_shortcodes(&text);
+
+                let scheduled = &mut self.app_state.shared_mut.borrow_mut().scheduled;
+                match self.editing_scheduled.take().and_then(|editing| scheduled.iter_mut().find(|scheduled| scheduled.uuid == editing)) {
+                    Some(editing) => {
+                        editing.text = text;
+                        editing.send_at = send_at;
+                    },
+                    None => scheduled.push(ScheduledMessage::new(chat_uuid, text, send_at)),
+                }
+            }
+        }
+
+        let (uuid, action) = if let Some(scheduled_action) = self.scheduled_action.take() { scheduled_action }
+        else { return; };
+
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+        match action {
+            ScheduledAction::EDIT => if let Some(scheduled) = shared_mut.scheduled.iter().find(|scheduled| scheduled.uuid == uuid) {
+                (self.message_buffer, self.composer_mentions) = mentions::decode(shared_mut, &scheduled.text);
+                self.buffer_replacement = Some(self.message_buffer.clone());
+                self.editing_scheduled = Some(uuid);
+            },
+            // Goes out with the next due check, once connected.
+            ScheduledAction::SEND_NOW => if let Some(scheduled) = shared_mut.scheduled.iter_mut().find(|scheduled| scheduled.uuid == uuid) {
+                scheduled.send_at = chrono::Local::now();
+            },
+            ScheduledAction::CANCEL => {
+                shared_mut.scheduled.retain(|scheduled| scheduled.uuid != uuid);
+                if self.editing_scheduled == Some(uuid) {
+                    self.editing_scheduled = None;
+                }
+            },
+        }
+    }
+
+    // Keeps the composer text of the open Chat, so it's there when coming back.
+    fn save_draft(&mut self) {
+        let chat_uuid = if let Some(chat_uuid) = self.chat_uuid { chat_uuid }
+        else { return; };
+
+        let drafts = &mut self.app_state.shared_mut.borrow_mut().drafts;
+        if self.message_buffer.trim().is_empty() {
+            let _ = drafts.remove(&chat_uuid);
+            return;
+        }
+
+        let draft = mentions::encode_all(&self.message_buffer, &self.composer_mentions);
+        if drafts.get(&chat_uuid) != Some(&draft) {
+            let _ = drafts.insert(chat_uuid, draft);
+        }
+    }
+
+    fn restore_draft(&mut self) {
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let draft = self.chat_uuid
+            .and_then(|chat_uuid| shared_mut.drafts.get(&chat_uuid))
+            .map(|draft| mentions::decode(&shared_mut, draft))
+            .unwrap_or_default();
+
+        (self.message_buffer, self.composer_mentions) = draft;
+        self.buffer_replacement = Some(self.message_buffer.clone());
+        // Not typed by the user, so no typing notification.
+        self.typed_buffer = self.message_buffer.clone();
+    }
+
+    // Tells the server the current user read the open Chat up to its latest message.
+    fn update_read(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+
+        let (user, chat_uuid) = match (&shared_mut.user, self.chat_uuid) {
+            (Some(user), Some(chat_uuid)) => (user, chat_uuid),
+            _ => return Ok(()),
+        };
+        let chat_state = shared_mut.chat_states.entry(chat_uuid).or_default();
+        chat_state.clear_unread();
+
+        // The server doesn't know about the messages that are still being sent.
+        let last_message = match shared_mut.chats.get(&chat_uuid).and_then(|chat| chat.messages().iter().rev().find(|m| !chat_state.is_unconfirmed(m.uuid()))) {
+            Some(message) => message.uuid(),
+            None => return Ok(()),
+        };
+
+        if chat_state.last_read(user.uuid()) == Some(last_message) { return Ok(()); }
+        chat_state.set_read(user.uuid(), last_message);
+
+        // MESSAGE_READ keeps its original shape, so Servers that don't know about receipts still mark the Chat as read.
+        // The receipt goes on its own message, so one being rejected doesn't affect the other.
+        server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::MESSAGE_READ(chat_uuid)))))?;
+        server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::MESSAGE_READ_UP_TO(chat_uuid, user.uuid(), last_message)))))
+    }
+
+    // Sends TYPING_START at most once every TYPING_THROTTLE_SECONDS while the composer changes.
+    fn update_typing(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        let chat_uuid = if let Some(chat_uuid) = self.chat_uuid { chat_uuid }
+        else { return Ok(()); };
+
+        let user_uuid = if let Some(user) = &self.app_state.shared_mut.borrow().user { user.uuid() }
+        else { return Ok(()); };
+
+        if self.message_buffer != self.typed_buffer {
+            self.typed_buffer = self.message_buffer.clone();
+            self.last_keystroke = Some(LgTimer::new());
+
+            let throttled = self.typing_sent
+                .as_ref()
+                .map(|timer| timer.elapsed() < TYPING_THROTTLE_SECONDS.s())
+                .unwrap_or(false);
+
+            // Commands are not messages, nobody needs to see them being typed.
+            if !self.message_buffer.is_empty() && !commands::is_command(&self.message_buffer) && !throttled {
+                server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::TYPING_START(chat_uuid, user_uuid)))))?;
+                self.typing_sent = Some(LgTimer::new());
+            }
+        }
+
+        let idle = self.last_keystroke
+            .as_ref()
+            .map(|timer| timer.elapsed() >= TYPING_IDLE_SECONDS.s())
+            .unwrap_or(true);
+
+        if self.message_buffer.is_empty() || commands::is_command(&self.message_buffer) || idle {
+            self.stop_typing(server_coms)?;
+        }
+
+        Ok(())
+    }
+
+    fn stop_typing(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if self.typing_sent.take().is_none() { return Ok(()); }
+
+        let user_uuid = self.app_state.shared_mut.borrow().user.as_ref().map(|user| user.uuid());
+        if let (Some(chat_uuid), Some(user_uuid)) = (self.chat_uuid, user_uuid) {
+            server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::TYPING_STOP(chat_uuid, user_uuid)))))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn thread_summaries(chat: &Chat) -> HashMap<UUID, ThreadSummary> {
+    let mut summaries: HashMap<UUID, ThreadSummary> = HashMap::default();
+
+    for message in chat.messages() {
+        if let Some(root) = message.thread_root() {
+            let summary = summaries.entry(root).or_insert(ThreadSummary { replies: 0, last_repliers: Vec::default() });
+            summary.replies += 1;
+
+            summary.last_repliers.retain(|u| *u != message.sender());
+            summary.last_repliers.push(message.sender());
+            if summary.last_repliers.len() > THREAD_AVATARS {
+                summary.last_repliers.remove(0);
+            }
+        }
+    }
+
+    summaries
+}
+
+/// Link to a Message, to be shared outside of the app.
+fn message_link(chat_uuid: UUID, message_uuid: UUID) -> String {
+    std::format!("{MESSAGE_LINK_SCHEME}{}/{}", chat_uuid.to_string(), message_uuid.to_string())
+}
+
+// Disappearing and encrypted messages must not outlive their Chat, so they are never bookmarked or forwarded.
+fn can_keep_copy(shared_mut: &SharedMut, chat_uuid: UUID, message: &Message) -> bool {
+    message.expiry().is_none() && shared_mut.e2e.status(chat_uuid) == E2eStatus::OFF
+}
+
+fn message_actions_x(ui: &imgui::Ui) -> f32 {
+    ui.content_region_max()[0] - MESSAGE_ACTIONS.iter().map(|(_, width, _)| width + 5.0).sum::<f32>()
+}
+
+fn is_grouped(previous: &Message, message: &Message) -> bool {
+    if previous.sender() != message.sender() { return false; }
+
+    match (previous.date_time().to_local(), message.date_time().to_local()) {
+        // A timestamp going backwards, like clock skew between senders, starts a new group.
+        (Ok(previous), Ok(current)) => (0..GROUP_WINDOW_MINUTES).contains(&current.signed_duration_since(previous).num_minutes()),
+        _ => false,
+    }
+}
+
+// Index of the latest Message another member has read.
+fn last_read_by_others(chat: &Chat, chat_state: &ChatState, current_user: UUID) -> Option<usize> {
+    chat.messages()
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| chat_state.read_up_to()
+            .iter()
+            .any(|(reader, read)| *reader != current_user && *read == message.uuid())
+        )
+        .map(|(i, _)| i)
+        .last()
+}
+
+// Readers of each message shown on the message list, thread replies count for their root.
+fn seen_by(chat: &Chat, chat_state: &ChatState, current_user: UUID) -> HashMap<UUID, Vec<UUID>> {
+    let mut seen_by: HashMap<UUID, Vec<UUID>> = HashMap::default();
+    let mut last_shown = None;
+
+    for message in chat.messages() {
+        if message.thread_root().is_none() {
+            last_shown = Some(message.uuid());
+        }
+
+        let shown = if let Some(shown) = last_shown { shown }
+        else { continue; };
+
+        for (reader, read) in chat_state.read_up_to() {
+            if *read == message.uuid() && *reader != current_user && *reader != message.sender() {
+                seen_by.entry(shown).or_default().push(*reader);
+            }
+        }
+    }
+
+    seen_by
+}
@@ -0,0 +1,193 @@
+use crate::export::ExportFormat;
+
+/// Composer commands, typed as "/name arguments".
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    ME(String),
+    SHRUG(String),
+    NICK(String),
+    MUTE,
+    LEAVE,
+    SEARCH(String),
+    CLEAR,
+    EXPORT(ExportFormat),
+}
+
+/// Who runs a Command once the chat page parsed it.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CommandTarget {
+    CHAT_PAGE,
+    SIDEBAR,
+    SEARCH,
+    SERVER,
+    EXPORT,
+}
+
+impl Command {
+    pub(crate) fn target(&self) -> CommandTarget {
+        match self {
+            Command::ME(_) | Command::SHRUG(_) | Command::LEAVE | Command::CLEAR => CommandTarget::CHAT_PAGE,
+            Command::MUTE => CommandTarget::SIDEBAR,
+            Command::SEARCH(_) => CommandTarget::SEARCH,
+            Command::NICK(_) => CommandTarget::SERVER,
+            Command::EXPORT(_) => CommandTarget::EXPORT,
+        }
+    }
+}
+
+pub(crate) struct CommandSpec {
+    pub(crate) name: &'static str,
+    pub(crate) args: &'static str,
+    pub(crate) description: &'static str,
+    parse: fn(&str) -> Result<Command, String>,
+}
+
+/// Every command the composer knows, new ones only need an entry here and a match arm where they run.
+pub(crate) static COMMANDS: [CommandSpec; 8] = [
+    CommandSpec { name: "me", args: "<action>", description: "Describe what you are doing", parse: parse_me },
+    CommandSpec { name: "shrug", args: "[message]", description: "Append a shrug to the message", parse: parse_shrug },
+    CommandSpec { name: "nick", args: "<new tag>", description: "Change your tag", parse: parse_nick },
+    CommandSpec { name: "mute", args: "", description: "Hide or show the unread badge of this chat", parse: parse_mute },
+    CommandSpec { name: "leave", args: "", description: "Leave this group", parse: parse_leave },
+    CommandSpec { name: "search", args: "<text>", description: "Search the messages of this chat", parse: parse_search },
+    CommandSpec { name: "clear", args: "", description: "Hide the current history on this device", parse: parse_clear },
+    CommandSpec { name: "export", args: "[json|html|txt]", description: "Save the whole history of this chat to a file", parse: parse_export },
+];
+
+/// What the command palette shows for the composer text.
+pub(crate) enum Palette {
+    // The name is still being typed.
+    NAMES(Vec<&'static CommandSpec>),
+    // The name is complete, the arguments are being typed.
+    HINT(&'static CommandSpec),
+}
+
+/// None when the text is not a command, "//" sends a message starting with "/".
+pub(crate) fn parse(text: &str) -> Option<Result<Command, String>> {
+    let (name, args) = split(text)?;
+
+    match find(name) {
+        Some(spec) => Some((spec.parse)(args.trim())),
+        None => Some(Err(std::format!("Unknown command /{name}, start with // to send it as a message"))),
+    }
+}
+
+pub(crate) fn palette(text: &str) -> Option<Palette> {
+    let (name, args) = split(text)?;
+
+    if args.is_empty() && !text.ends_with(char::is_whitespace) {
+        let matches = COMMANDS
+            .iter()
+            .filter(|spec| spec.name.starts_with(&name.to_lowercase()))
+            .collect::<Vec<_>>();
+
+        if matches.is_empty() { None } else { Some(Palette::NAMES(matches)) }
+    }
+    else { find(name).map(Palette::HINT) }
+}
+
+pub(crate) fn is_command(text: &str) -> bool {
+    split(text).is_some()
+}
+
+/// Removes the escape of a message starting with "//".
+pub(crate) fn unescape(text: &str) -> &str {
+    if text.starts_with("//") { &text[1..] } else { text }
+}
+
+fn split(text: &str) -> Option<(&str, &str)> {
+    let command = text.strip_prefix('/')?;
+    if command.starts_with('/') { return None; }
+
+    Some(command.split_once(char::is_whitespace).unwrap_or((command, "")))
+}
+
+fn find(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+fn required<'a>(args: &'a str, what: &str) -> Result<&'a str, String> {
+    if args.is_empty() { Err(std::format!("Missing {what}")) } else { Ok(args) }
+}
+
+fn parse_me(args: &str) -> Result<Command, String> {
+    Ok(Command::ME(required(args, "action")?.to_string()))
+}
+
+fn parse_shrug(args: &str) -> Result<Command, String> {
+    Ok(Command::SHRUG(args.to_string()))
+}
+
+fn parse_nick(args: &str) -> Result<Command, String> {
+    let tag = required(args, "new tag")?;
+    if tag.contains(char::is_whitespace) { return Err(String::from("Tags can't have spaces")); }
+
+    Ok(Command::NICK(tag.to_string()))
+}
+
+fn parse_mute(_args: &str) -> Result<Command, String> {
+    Ok(Command::MUTE)
+}
+
+fn parse_leave(_args: &str) -> Result<Command, String> {
+    Ok(Command::LEAVE)
+}
+
+fn parse_search(args: &str) -> Result<Command, String> {
+    Ok(Command::SEARCH(required(args, "search text")?.to_string()))
+}
+
+fn parse_clear(_args: &str) -> Result<Command, String> {
+    Ok(Command::CLEAR)
+}
+
+fn parse_export(args: &str) -> Result<Command, String> {
+    if args.is_empty() { return Ok(Command::EXPORT(ExportFormat::JSON)); }
+
+    ExportFormat::from_name(args)
+        .map(Command::EXPORT)
+        .ok_or_else(|| std::format!("Unknown format {args}, use json, html or txt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_commands() {
+        assert_eq!(split("/me waves  hi"), Some(("me", "waves  hi")));
+        assert_eq!(split("/mute"), Some(("mute", "")));
+        assert_eq!(split("//not a command"), None);
+        assert_eq!(split("hi /me"), None);
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parse("hello"), None);
+        assert_eq!(parse("/ME  waves "), Some(Ok(Command::ME(String::from("waves")))));
+        assert_eq!(parse("/shrug"), Some(Ok(Command::SHRUG(String::new()))));
+        assert_eq!(parse("/nick new_tag"), Some(Ok(Command::NICK(String::from("new_tag")))));
+        assert!(matches!(parse("/nick two words"), Some(Err(_))));
+        assert!(matches!(parse("/me"), Some(Err(_))));
+        assert!(matches!(parse("/export pdf"), Some(Err(_))));
+        assert!(matches!(parse("/nope"), Some(Err(_))));
+        assert_eq!(parse("/export"), Some(Ok(Command::EXPORT(ExportFormat::JSON))));
+        assert_eq!(unescape("//me"), "/me");
+    }
+
+    #[test]
+    fn palette_filtering() {
+        let names = |text| match palette(text) {
+            Some(Palette::NAMES(specs)) => specs.iter().map(|spec| spec.name).collect::<Vec<_>>(),
+            _ => Vec::new(),
+        };
+
+        assert_eq!(names("/"), COMMANDS.iter().map(|spec| spec.name).collect::<Vec<_>>());
+        assert_eq!(names("/S"), vec!["shrug", "search"]);
+        assert!(palette("/x").is_none());
+        assert!(matches!(palette("/search "), Some(Palette::HINT(spec)) if spec.name == "search"));
+        assert!(palette("hi").is_none());
+    }
+}
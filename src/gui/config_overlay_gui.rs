@@ -1,6 +1,6 @@
 use yapping_core::{client_server_coms::{Modification, ServerMessage, ServerMessageContent}, l3gion_rust::{imgui::{self, TableColumnSetup}, lg_core::renderer::Renderer, sllog::info, AsLgTime, LgTimer, StdError}};
 use crate::{client_manager::AppState, server_coms::ServerCommunication};
-use super::{button, gui_manager::GuiMannager, no_resize_window, spacing, text_input, use_font, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+use super::{button, gui_manager::GuiMannager, no_resize_window, spacing, text_input, time_format::ClockFormat, use_font, BORDER_RADIUS, NEXT_WINDOW_SPECS};
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -18,6 +18,7 @@ pub(crate) struct ConfigOverlayGuiManager {
     timer: LgTimer,
     timer_init: bool,
     new_tag: String,
+    toggle_clock: bool,
 }
 impl ConfigOverlayGuiManager {
     pub(crate) fn new(app_state: AppState) -> Self {
@@ -29,6 +30,7 @@ impl ConfigOverlayGuiManager {
             timer: LgTimer::new(),
             timer_init: false,
             new_tag: String::default(),
+            toggle_clock: false,
         }
     }
     
@@ -94,6 +96,14 @@ impl GuiMannager for ConfigOverlayGuiManager {
     }
 
     fn on_update(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if std::mem::take(&mut self.toggle_clock) {
+            let shared_mut = &mut self.app_state.shared_mut.borrow_mut();
+            shared_mut.clock_format = match shared_mut.clock_format {
+                ClockFormat::H12 => ClockFormat::H24,
+                ClockFormat::H24 => ClockFormat::H12,
+            };
+        }
+
         match self.state {
             ConfigState::CHANGE_TAG => {
                 if !self.new_tag.is_empty() {
@@ -199,6 +209,23 @@ impl ConfigOverlayGuiManager {
             self.app_state.theme.sign_up_btn_color, 
             self.app_state.theme.sign_up_btn_color, 
         ); 
+
+        ui.spacing();
+        let clock_label = match self.app_state.shared_mut.borrow().clock_format {
+            ClockFormat::H12 => "Clock: 12 hours",
+            ClockFormat::H24 => "Clock: 24 hours",
+        };
+        if button(
+            ui, 
+            clock_label, 
+            [200.0, 25.0], 
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color, 
+            self.app_state.theme.sign_up_btn_color, 
+            self.app_state.theme.sign_up_btn_color, 
+        ) {
+            self.toggle_clock = true;
+        }
     }
     
     fn show_change_tag(&mut self, ui: &imgui::Ui, renderer: &Renderer) {
@@ -0,0 +1,332 @@
+use yapping_core::l3gion_rust::imgui;
+
+use super::{button, text_input, theme::Theme, use_font, BORDER_RADIUS};
+
+const PICKER_COLUMNS: usize = 8;
+const MIN_SHORTCODE_QUERY: usize = 2;
+
+/// Glyphs merged from the emoji font into the Roboto fonts, zero terminated.
+pub(crate) static EMOJI_GLYPH_RANGES: [u32; 27] = [
+    0x2000, 0x206F, // General Punctuation
+    0x2190, 0x21FF, // Arrows
+    0x2300, 0x23FF, // Miscellaneous Technical
+    0x2460, 0x24FF, // Enclosed Alphanumerics
+    0x25A0, 0x27BF, // Geometric Shapes, Miscellaneous Symbols and Dingbats
+    0x2900, 0x297F, // Supplemental Arrows-B
+    0x2B00, 0x2BFF, // Miscellaneous Symbols and Arrows
+    0x3030, 0x303D, // Wavy Dash, Part Alternation Mark
+    0x3297, 0x3299, // Circled Ideographs
+    0xFE00, 0xFE0F, // Variation Selectors
+    0x1F000, 0x1FAFF, // Mahjong Tiles up to Symbols and Pictographs Extended-A
+    0x2122, 0x2139, // Letterlike Symbols
+    0x20E3, 0x20E3, // Combining Enclosing Keycap
+    0,
+];
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum EmojiCategory {
+    #[default]
+    SMILEYS,
+    PEOPLE,
+    NATURE,
+    FOOD,
+    ACTIVITIES,
+    TRAVEL,
+    OBJECTS,
+    SYMBOLS,
+}
+impl EmojiCategory {
+    const ALL: [EmojiCategory; 8] = [
+        EmojiCategory::SMILEYS,
+        EmojiCategory::PEOPLE,
+        EmojiCategory::NATURE,
+        EmojiCategory::FOOD,
+        EmojiCategory::ACTIVITIES,
+        EmojiCategory::TRAVEL,
+        EmojiCategory::OBJECTS,
+        EmojiCategory::SYMBOLS,
+    ];
+
+    fn icon(&self) -> &'static str {
+        match self {
+            EmojiCategory::SMILEYS => "😀",
+            EmojiCategory::PEOPLE => "👋",
+            EmojiCategory::NATURE => "🌿",
+            EmojiCategory::FOOD => "🍕",
+            EmojiCategory::ACTIVITIES => "⚽",
+            EmojiCategory::TRAVEL => "🚗",
+            EmojiCategory::OBJECTS => "💡",
+            EmojiCategory::SYMBOLS => "❤",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Emoji {
+    pub(crate) shortcode: &'static str,
+    pub(crate) emoji: &'static str,
+    pub(crate) category: EmojiCategory,
+}
+
+macro_rules! emojis {
+    ($($category:ident: [$(($shortcode:literal, $emoji:literal)),* $(,)?]),* $(,)?) => {
+        &[$($(Emoji { shortcode: $shortcode, emoji: $emoji, category: EmojiCategory::$category }),*),*]
+    };
+}
+
+pub(crate) static EMOJIS: &[Emoji] = emojis! {
+    SMILEYS: [
+        ("grinning", "😀"), ("smiley", "😃"), ("smile", "😄"), ("grin", "😁"), ("laughing", "😆"),
+        ("sweat_smile", "😅"), ("joy", "😂"), ("rofl", "🤣"), ("slight_smile", "🙂"), ("upside_down", "🙃"),
+        ("wink", "😉"), ("blush", "😊"), ("innocent", "😇"), ("heart_eyes", "😍"), ("star_struck", "🤩"),
+        ("kissing_heart", "😘"), ("yum", "😋"), ("stuck_out_tongue", "😛"), ("zany", "🤪"), ("hugging", "🤗"),
+        ("thinking", "🤔"), ("shush", "🤫"), ("neutral_face", "😐"), ("expressionless", "😑"), ("no_mouth", "😶"),
+        ("smirk", "😏"), ("unamused", "😒"), ("rolling_eyes", "🙄"), ("grimacing", "😬"), ("relieved", "😌"),
+        ("pensive", "😔"), ("sleepy", "😪"), ("sleeping", "😴"), ("mask", "😷"), ("nerd", "🤓"),
+        ("sunglasses", "😎"), ("confused", "😕"), ("worried", "😟"), ("open_mouth", "😮"), ("astonished", "😲"),
+        ("flushed", "😳"), ("pleading", "🥺"), ("cry", "😢"), ("sob", "😭"), ("scream", "😱"),
+        ("angry", "😠"), ("rage", "😡"), ("skull", "💀"), ("poop", "💩"), ("clown", "🤡"),
+        ("ghost", "👻"), ("alien", "👽"), ("robot", "🤖"), ("smiley_cat", "😺"),
+    ],
+    PEOPLE: [
+        ("wave", "👋"), ("raised_hand", "✋"), ("ok_hand", "👌"), ("pinched_fingers", "🤌"), ("v", "✌"),
+        ("crossed_fingers", "🤞"), ("metal", "🤘"), ("call_me", "🤙"), ("point_left", "👈"), ("point_right", "👉"),
+        ("point_up", "👆"), ("point_down", "👇"), ("thumbsup", "👍"), ("+1", "👍"), ("thumbsdown", "👎"),
+        ("-1", "👎"), ("fist", "✊"), ("punch", "👊"), ("clap", "👏"), ("raised_hands", "🙌"),
+        ("open_hands", "👐"), ("handshake", "🤝"), ("pray", "🙏"), ("muscle", "💪"), ("eyes", "👀"),
+        ("brain", "🧠"), ("facepalm", "🤦"), ("shrug", "🤷"), ("dancer", "💃"), ("ninja", "🥷"),
+    ],
+    NATURE: [
+        ("dog", "🐶"), ("cat", "🐱"), ("mouse", "🐭"), ("fox", "🦊"), ("bear", "🐻"),
+        ("panda", "🐼"), ("koala", "🐨"), ("tiger", "🐯"), ("lion", "🦁"), ("frog", "🐸"),
+        ("monkey", "🐵"), ("chicken", "🐔"), ("penguin", "🐧"), ("bird", "🐦"), ("owl", "🦉"),
+        ("unicorn", "🦄"), ("bee", "🐝"), ("bug", "🐛"), ("butterfly", "🦋"), ("snail", "🐌"),
+        ("turtle", "🐢"), ("snake", "🐍"), ("octopus", "🐙"), ("crab", "🦀"), ("whale", "🐳"),
+        ("seedling", "🌱"), ("herb", "🌿"), ("four_leaf_clover", "🍀"), ("cactus", "🌵"), ("rose", "🌹"),
+        ("sunflower", "🌻"), ("sun", "☀"), ("cloud", "☁"), ("rainbow", "🌈"), ("snowflake", "❄"),
+        ("zap", "⚡"), ("fire", "🔥"), ("droplet", "💧"), ("star", "⭐"), ("crescent_moon", "🌙"),
+    ],
+    FOOD: [
+        ("apple", "🍎"), ("banana", "🍌"), ("grapes", "🍇"), ("watermelon", "🍉"), ("strawberry", "🍓"),
+        ("peach", "🍑"), ("avocado", "🥑"), ("eggplant", "🍆"), ("carrot", "🥕"), ("corn", "🌽"),
+        ("bread", "🍞"), ("cheese", "🧀"), ("egg", "🥚"), ("bacon", "🥓"), ("hamburger", "🍔"),
+        ("fries", "🍟"), ("pizza", "🍕"), ("hotdog", "🌭"), ("taco", "🌮"), ("sushi", "🍣"),
+        ("ramen", "🍜"), ("popcorn", "🍿"), ("cake", "🍰"), ("birthday", "🎂"), ("cookie", "🍪"),
+        ("chocolate_bar", "🍫"), ("doughnut", "🍩"), ("coffee", "☕"), ("tea", "🍵"), ("beer", "🍺"),
+        ("beers", "🍻"), ("wine_glass", "🍷"),
+    ],
+    ACTIVITIES: [
+        ("soccer", "⚽"), ("basketball", "🏀"), ("football", "🏈"), ("baseball", "⚾"), ("tennis", "🎾"),
+        ("volleyball", "🏐"), ("8ball", "🎱"), ("ping_pong", "🏓"), ("trophy", "🏆"), ("medal", "🏅"),
+        ("video_game", "🎮"), ("joystick", "🕹"), ("game_die", "🎲"), ("dart", "🎯"), ("chess_pawn", "♟"),
+        ("art", "🎨"), ("guitar", "🎸"), ("musical_note", "🎵"), ("microphone", "🎤"), ("headphones", "🎧"),
+        ("tada", "🎉"), ("confetti_ball", "🎊"), ("balloon", "🎈"), ("gift", "🎁"), ("christmas_tree", "🎄"),
+    ],
+    TRAVEL: [
+        ("car", "🚗"), ("taxi", "🚕"), ("bus", "🚌"), ("ambulance", "🚑"), ("bike", "🚲"),
+        ("train", "🚆"), ("airplane", "✈"), ("rocket", "🚀"), ("ship", "🚢"), ("anchor", "⚓"),
+        ("house", "🏠"), ("office", "🏢"), ("hospital", "🏥"), ("school", "🏫"), ("tent", "⛺"),
+        ("earth_americas", "🌎"), ("earth_africa", "🌍"), ("mountain", "⛰"), ("volcano", "🌋"), ("beach", "🏖"),
+        ("statue_of_liberty", "🗽"), ("moyai", "🗿"), ("world_map", "🗺"), ("construction", "🚧"), ("vertical_traffic_light", "🚦"),
+    ],
+    OBJECTS: [
+        ("watch", "⌚"), ("iphone", "📱"), ("computer", "💻"), ("keyboard", "⌨"), ("desktop", "🖥"),
+        ("printer", "🖨"), ("floppy_disk", "💾"), ("cd", "💿"), ("camera", "📷"), ("tv", "📺"),
+        ("bulb", "💡"), ("flashlight", "🔦"), ("battery", "🔋"), ("electric_plug", "🔌"), ("moneybag", "💰"),
+        ("gem", "💎"), ("wrench", "🔧"), ("hammer", "🔨"), ("gear", "⚙"), ("link", "🔗"),
+        ("lock", "🔒"), ("unlock", "🔓"), ("key", "🔑"), ("bell", "🔔"), ("pushpin", "📌"),
+        ("paperclip", "📎"), ("scissors", "✂"), ("pencil", "✏"), ("memo", "📝"), ("book", "📖"),
+        ("calendar", "📅"), ("chart", "📈"), ("clipboard", "📋"), ("email", "📧"), ("package", "📦"),
+        ("hourglass", "⌛"), ("alarm_clock", "⏰"), ("bookmark", "🔖"), ("bomb", "💣"), ("pill", "💊"),
+    ],
+    SYMBOLS: [
+        ("heart", "❤"), ("orange_heart", "🧡"), ("yellow_heart", "💛"), ("green_heart", "💚"), ("blue_heart", "💙"),
+        ("purple_heart", "💜"), ("black_heart", "🖤"), ("broken_heart", "💔"), ("sparkling_heart", "💖"), ("two_hearts", "💕"),
+        ("100", "💯"), ("anger", "💢"), ("boom", "💥"), ("dizzy", "💫"), ("sweat_drops", "💦"),
+        ("speech_balloon", "💬"), ("zzz", "💤"), ("sparkles", "✨"), ("white_check_mark", "✅"), ("heavy_check_mark", "✔"),
+        ("x", "❌"), ("warning", "⚠"), ("no_entry", "⛔"), ("question", "❓"), ("exclamation", "❗"),
+        ("recycle", "♻"), ("infinity", "♾"), ("copyright", "©"), ("registered", "®"), ("tm", "™"),
+        ("arrow_right", "➡"), ("arrow_left", "⬅"), ("arrow_up", "⬆"), ("arrow_down", "⬇"), ("arrows_counterclockwise", "🔄"),
+        ("red_circle", "🔴"), ("green_circle", "🟢"), ("blue_circle", "🔵"), ("white_circle", "⚪"), ("black_circle", "⚫"),
+    ],
+};
+
+pub(crate) fn find(shortcode: &str) -> Option<&'static Emoji> {
+    EMOJIS.iter().find(|e| e.shortcode == shortcode)
+}
+
+pub(crate) fn search(query: &str) -> impl Iterator<Item = &'static Emoji> + '_ {
+    let prefix = query.to_lowercase();
+    let contains = prefix.clone();
+
+    // Prefix matches first, then anything containing the query.
+    EMOJIS.iter()
+        .filter(move |e| e.shortcode.starts_with(&prefix))
+        .chain(EMOJIS.iter().filter(move |e| !e.shortcode.starts_with(&contains) && e.shortcode.contains(&contains)))
+}
+
+/// The ":partial" shortcode being typed at the end of the text, with the byte index of the ':'.
+pub(crate) fn shortcode_query(text: &str) -> Option<(usize, &str)> {
+    let start = text.rfind(':')?;
+    let query = &text[start + 1..];
+
+    let valid_start = text[..start].chars().last().map_or(true, char::is_whitespace);
+    let valid_query = query.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'));
+
+    if valid_start && valid_query && query.len() >= MIN_SHORTCODE_QUERY { Some((start, query)) }
+    else { None }
+}
+
+/// Replaces every known ":shortcode:" by its emoji, code spans and fenced code blocks are left as written.
+pub(crate) fn replace_shortcodes(text: &str) -> String {
+    let mut in_fence = false;
+
+    text.split('\n')
+        .map(|line| {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+                return line.to_string();
+            }
+            if in_fence { return line.to_string(); }
+
+            // Odd parts are inside backticks, unless the last backtick is never closed.
+            let parts = line.split('`').collect::<Vec<_>>();
+            let unclosed = parts.len() % 2 == 0;
+            parts
+                .iter()
+                .enumerate()
+                .map(|(i, part)| if i % 2 == 1 && !(unclosed && i == parts.len() - 1) { part.to_string() }
+                    else { replace_in_text(part) })
+                .collect::<Vec<_>>()
+                .join("`")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn replace_in_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(':') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match rest[1..].find(':').and_then(|end| find(&rest[1..1 + end]).map(|e| (end, e))) {
+            Some((end, emoji)) => {
+                result.push_str(emoji.emoji);
+                rest = &rest[end + 2..];
+            },
+            None => {
+                result.push(':');
+                rest = &rest[1..];
+            },
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Searchable emoji picker, meant to be drawn inside a popup.
+#[derive(Debug, Default)]
+pub(crate) struct EmojiPicker {
+    search: String,
+    category: EmojiCategory,
+}
+impl EmojiPicker {
+    pub(crate) fn show(&mut self, ui: &imgui::Ui, theme: &Theme, id: &str) -> Option<&'static str> {
+        let mut selected = None;
+
+        {
+            let _font = use_font(ui, super::FontType::REGULAR17);
+            ui.set_next_item_width(PICKER_COLUMNS as f32 * 40.0);
+            text_input(
+                ui,
+                "Search emoji",
+                &mut self.search,
+                &std::format!("##emoji_search_{id}"),
+                theme.input_text_bg_light,
+                [0.0, 0.0, 0.0, 1.0],
+                BORDER_RADIUS,
+                imgui::InputTextFlags::CALLBACK_RESIZE
+            );
+        }
+
+        let _font = use_font(ui, super::FontType::REGULAR24);
+        if self.search.is_empty() {
+            for (i, category) in EmojiCategory::ALL.iter().enumerate() {
+                if i > 0 { ui.same_line(); }
+
+                let color = if *category == self.category { theme.accent_color } else { theme.mini_pop_up_color };
+                if button(
+                    ui,
+                    &std::format!("{}##emoji_category_{id}_{i}", category.icon()),
+                    [35.0, 35.0],
+                    BORDER_RADIUS,
+                    color,
+                    theme.sign_up_btn_color,
+                    theme.sign_up_btn_color,
+                ) {
+                    self.category = *category;
+                }
+            }
+            ui.separator();
+        }
+
+        ui.child_window(std::format!("##emoji_grid_{id}"))
+            .size([PICKER_COLUMNS as f32 * 40.0 + 10.0, 200.0])
+            .build(|| {
+                let emojis: Vec<&Emoji> = if self.search.is_empty() {
+                    EMOJIS.iter().filter(|e| e.category == self.category).collect()
+                }
+                else { search(&self.search).collect() };
+
+                for (i, emoji) in emojis.iter().enumerate() {
+                    if i % PICKER_COLUMNS != 0 { ui.same_line(); }
+
+                    if button(
+                        ui,
+                        &std::format!("{}##emoji_{id}_{i}", emoji.emoji),
+                        [35.0, 35.0],
+                        BORDER_RADIUS,
+                        theme.mini_pop_up_color,
+                        theme.accent_color,
+                        theme.accent_color,
+                    ) {
+                        selected = Some(emoji.emoji);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(std::format!(":{}:", emoji.shortcode));
+                    }
+                }
+            });
+
+        if selected.is_some() {
+            self.search.clear();
+        }
+
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_known_shortcodes() {
+        assert_eq!(replace_shortcodes("nice :thumbsup: :nope: a:b"), std::format!("nice {} :nope: a:b", find("thumbsup").unwrap().emoji));
+    }
+
+    #[test]
+    fn leaves_code_alone() {
+        let emoji = find("thumbsup").unwrap().emoji;
+
+        assert_eq!(replace_shortcodes("`a :thumbsup: b` :thumbsup:"), std::format!("`a :thumbsup: b` {emoji}"));
+        assert_eq!(replace_shortcodes("```\nlet x = y::z::w;\n:thumbsup:\n```\n:thumbsup:"), std::format!("```\nlet x = y::z::w;\n:thumbsup:\n```\n{emoji}"));
+        // An unclosed backtick is just a backtick.
+        assert_eq!(replace_shortcodes("it`s :thumbsup:"), std::format!("it`s {emoji}"));
+    }
+}
@@ -0,0 +1,207 @@
+use std::path::PathBuf;
+use yapping_core::{client_server_coms::{Query, Response, ServerMessage, ServerMessageContent}, l3gion_rust::{imgui, lg_core::renderer::Renderer, AsLgTime, LgTimer, StdError, UUID}, message::Message};
+
+use crate::{client_manager::AppState, export::{self, ChatExporter, ExportFormat, EXPORT_PAGE_SIZE, PAGE_TIMEOUT_SECONDS}, server_coms::ServerCommunication};
+
+use super::{button, chat_name, gui_manager::GuiMannager, no_resize_window, use_font, user_tag, BORDER_RADIUS};
+
+/// Exports the history of a Chat to a file, asking the Server for every page of it.
+pub(crate) struct ExportGuiManager {
+    app_state: AppState,
+    requested: Option<(UUID, ExportFormat)>,
+    // Chat UUID, Chat name, the file being written.
+    exporter: Option<(UUID, String, ChatExporter)>,
+    path: PathBuf,
+    // Result of the last export, shown until closed.
+    status: Option<String>,
+    waiting_response: UUID,
+    // Since the last page was asked.
+    page_timer: LgTimer,
+}
+impl ExportGuiManager {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            requested: None,
+            exporter: None,
+            path: PathBuf::default(),
+            status: None,
+            waiting_response: UUID::default(),
+            page_timer: LgTimer::new(),
+        }
+    }
+
+    /// Starts once the running export, if any, is done.
+    pub(crate) fn start(&mut self, chat_uuid: UUID, format: ExportFormat) {
+        self.requested = Some((chat_uuid, format));
+    }
+}
+
+impl GuiMannager for ExportGuiManager {
+    fn on_imgui(&mut self, ui: &imgui::Ui, _renderer: &Renderer) {
+        let text = match (&self.exporter, &self.status) {
+            (Some((_, name, exporter)), _) => std::format!("Exporting {name}... {} messages", exporter.written()),
+            (None, Some(status)) => status.clone(),
+            (None, None) => return,
+        };
+
+        let theme = &self.app_state.theme;
+        let size = [460.0, 90.0];
+        let position = [(ui.io().display_size[0] - size[0]) / 2.0, ui.io().display_size[1] - size[1] - 10.0];
+
+        no_resize_window(
+            ui,
+            "export_window",
+            None,
+            position,
+            size,
+            [10.0, 10.0],
+            size,
+            theme.mini_pop_up_color,
+            |ui| {
+                let _font = use_font(ui, super::FontType::BOLD17);
+                ui.text_wrapped(text);
+                if self.exporter.is_some() { return; }
+
+                ui.set_cursor_pos([ui.cursor_pos()[0], size[1] - 40.0]);
+                if button(
+                    ui,
+                    "Copy path",
+                    [100.0, 30.0],
+                    BORDER_RADIUS,
+                    theme.accent_color,
+                    theme.sign_up_btn_color,
+                    theme.sign_up_btn_color,
+                ) {
+                    ui.set_clipboard_text(self.path.display().to_string());
+                }
+
+                ui.same_line();
+                if button(
+                    ui,
+                    "Close##export_close",
+                    [100.0, 30.0],
+                    BORDER_RADIUS,
+                    theme.negative_btn_color,
+                    theme.negative_actv_btn_color,
+                    theme.negative_actv_btn_color,
+                ) {
+                    self.status = None;
+                }
+            });
+    }
+
+    fn on_update(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if self.exporter.is_some() {
+            // The page won't arrive, what was written so far is kept.
+            let reason = if !server_coms.connected() { Some("the connection to the Server was lost") }
+            else if self.page_timer.elapsed() >= PAGE_TIMEOUT_SECONDS.s() { Some("the Server stopped answering") }
+            else { None };
+
+            if let Some(reason) = reason {
+                if let Some((_, _, exporter)) = self.exporter.take() {
+                    self.waiting_response = UUID::default();
+                    return self.finish(exporter, &std::format!(" (incomplete, {reason})"));
+                }
+            }
+
+            return Ok(());
+        }
+
+        let (chat_uuid, format) = if let Some(requested) = self.requested.take() { requested }
+        else { return Ok(()); };
+
+        let shared = self.app_state.shared_mut.clone();
+        let shared_mut = shared.borrow();
+        let chat = shared_mut.chats.get(&chat_uuid).ok_or("In ExportGuiManager::on_update: Chat doesn't exist!")?;
+
+        let name = chat_name(&shared_mut, chat);
+        let tags = chat.users().iter().map(|member| (*member, user_tag(&shared_mut, *member)));
+        self.path = export::default_path(&name, format);
+
+        let mut exporter = match ChatExporter::create(&self.path, format, chat, &name, tags) {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                self.status = Some(std::format!("Export failed: {e}"));
+                return Err(e);
+            },
+        };
+
+        // Offline, the cached messages are all we have.
+        if !server_coms.connected() {
+            exporter.write_messages(chat.messages())?;
+            return self.finish(exporter, " (cached messages only)");
+        }
+
+        self.request_page(server_coms, chat_uuid, None)?;
+        self.exporter = Some((chat_uuid, name, exporter));
+        self.status = None;
+
+        Ok(())
+    }
+
+    fn on_responded_messages(&mut self, message: &(ServerMessage, Response), server_coms: &mut ServerCommunication) -> Result<bool, StdError> {
+        if message.0.uuid == self.waiting_response
+        {
+            self.waiting_response = UUID::default();
+
+            if let Err(e) = self.continue_export(&message.1, server_coms) {
+                self.exporter = None;
+                self.status = Some(std::format!("Export failed: {e}"));
+                return Err(e);
+            }
+
+            Ok(true)
+        }
+        else { Ok(false) }
+    }
+}
+impl ExportGuiManager {
+    // Writes the page that arrived and asks for the next one, pages are oldest first.
+    fn continue_export(&mut self, response: &Response, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        let (chat_uuid, name, mut exporter) = if let Some(exporter) = self.exporter.take() { exporter }
+        else { return Ok(()); };
+
+        let mut page: Vec<Message> = match response {
+            Response::OK_QUERY(Query::RESULT_CHAT_MESSAGES_PAGE(page)) => page.clone(),
+            Response::Err(e) => return Err(e.clone().into()),
+            _ => return Err(String::from("In ExportGuiManager::continue_export: Wrong response from Server!").into()),
+        };
+        // Encrypted messages are written as they are shown on the chat page.
+        {
+            let shared_mut = &mut *self.app_state.shared_mut.borrow_mut();
+            if let Some(user) = &shared_mut.user {
+                for message in page.iter_mut() {
+                    let _ = shared_mut.e2e.reveal(chat_uuid, user.uuid(), message);
+                }
+            }
+        }
+        exporter.write_messages(&page)?;
+
+        if page.len() < EXPORT_PAGE_SIZE as usize {
+            return self.finish(exporter, "");
+        }
+
+        self.request_page(server_coms, chat_uuid, page.last().map(|m| m.uuid()))?;
+        self.exporter = Some((chat_uuid, name, exporter));
+
+        Ok(())
+    }
+
+    fn request_page(&mut self, server_coms: &mut ServerCommunication, chat_uuid: UUID, after: Option<UUID>) -> Result<(), StdError> {
+        let msg_uuid = UUID::generate();
+        server_coms.send(ServerMessage::new(msg_uuid, ServerMessageContent::QUERY(Query::CHAT_MESSAGES_PAGE(chat_uuid, after, EXPORT_PAGE_SIZE))))?;
+        self.waiting_response = msg_uuid;
+        self.page_timer.restart();
+
+        Ok(())
+    }
+
+    fn finish(&mut self, exporter: ChatExporter, note: &str) -> Result<(), StdError> {
+        let written = exporter.written();
+        exporter.finish()?;
+        self.status = Some(std::format!("Exported {written} messages{note} to {}", self.path.display()));
+
+        Ok(())
+    }
+}
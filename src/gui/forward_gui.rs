@@ -0,0 +1,116 @@
+use yapping_core::l3gion_rust::{imgui, UUID};
+
+use crate::client_manager::AppState;
+
+use super::{button, chat_name, no_resize_child_window, spacing, text_input, use_font, BORDER_RADIUS};
+
+const DIALOG_ID: &str = "Forward##forward_dialog";
+
+/// Modal where the user picks the Chat that gets a copy of the messages.
+pub(crate) struct ForwardDialog {
+    app_state: AppState,
+    open: bool,
+    filter: String,
+    messages: Vec<UUID>,
+    target: Option<UUID>,
+}
+impl ForwardDialog {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            open: false,
+            filter: String::default(),
+            messages: Vec::default(),
+            target: None,
+        }
+    }
+
+    pub(crate) fn open(&mut self, messages: Vec<UUID>) {
+        self.open = true;
+        self.filter.clear();
+        self.messages = messages;
+    }
+
+    /// The Chat picked and the messages to forward to it.
+    pub(crate) fn take_forward(&mut self) -> Option<(UUID, Vec<UUID>)> {
+        let target = self.target.take()?;
+
+        Some((target, std::mem::take(&mut self.messages)))
+    }
+
+    pub(crate) fn show(&mut self, ui: &imgui::Ui) {
+        if std::mem::take(&mut self.open) {
+            ui.open_popup(DIALOG_ID);
+        }
+
+        let _popup = if let Some(popup) = ui.modal_popup_config(DIALOG_ID)
+            .always_auto_resize(true)
+            .begin_popup() { popup }
+        else { return; };
+
+        let theme = &self.app_state.theme;
+        let mut _fonts = vec![use_font(ui, super::FontType::BOLD17)];
+        if self.messages.len() == 1 { ui.text("Forward message to"); }
+        else { ui.text(std::format!("Forward {} messages to", self.messages.len())); }
+
+        _fonts.push(use_font(ui, super::FontType::REGULAR17));
+        ui.set_next_item_width(350.0);
+        text_input(
+            ui,
+            "Search chats",
+            &mut self.filter,
+            "##forward_filter",
+            theme.input_text_bg_light,
+            [0.0, 0.0, 0.0, 1.0],
+            BORDER_RADIUS,
+            imgui::InputTextFlags::CALLBACK_RESIZE
+        );
+
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let filter = self.filter.trim().to_lowercase();
+        let mut chats = shared_mut.chats
+            .values()
+            .map(|chat| (chat.uuid(), chat_name(&shared_mut, chat)))
+            .filter(|(_, name)| name.to_lowercase().contains(&filter))
+            .collect::<Vec<_>>();
+        chats.sort_by(|a, b| a.1.cmp(&b.1));
+
+        no_resize_child_window(
+            ui,
+            "forward_chats",
+            imgui::WindowFlags::empty(),
+            [350.0, 200.0],
+            [5.0, 5.0],
+            theme.left_panel_bg_color,
+            |ui| {
+                if chats.is_empty() {
+                    ui.text_colored([1.0, 1.0, 1.0, 0.5], "No chat matches");
+                }
+
+                for (chat_uuid, name) in &chats {
+                    if ui.selectable(std::format!("{name}##forward_chat_{}", chat_uuid.to_string())) {
+                        self.target = Some(*chat_uuid);
+                    }
+                }
+            });
+        drop(shared_mut);
+
+        if self.target.is_some() {
+            ui.close_current_popup();
+        }
+
+        spacing(ui, 3);
+        if button(
+            ui,
+            "Cancel##forward_cancel",
+            [350.0, 30.0],
+            BORDER_RADIUS,
+            theme.negative_btn_color,
+            theme.negative_actv_btn_color,
+            theme.negative_actv_btn_color,
+        ) {
+            self.messages.clear();
+            ui.close_current_popup();
+        }
+    }
+}
@@ -0,0 +1,605 @@
+use yapping_core::l3gion_rust::{imgui, sllog::error};
+
+use super::{mentions::{self, MentionNames}, syntax::{self, Language, TokenKind}, theme::Theme, use_font, FontType, BORDER_RADIUS};
+
+const INLINE_DELIMITERS: [(&str, Style); 5] = [
+    ("**", Style::BOLD),
+    ("__", Style::BOLD),
+    ("~~", Style::STRIKE),
+    ("*", Style::ITALIC),
+    ("_", Style::ITALIC),
+];
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    BOLD,
+    ITALIC,
+    STRIKE,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SpanStyle {
+    pub(crate) bold: bool,
+    pub(crate) italic: bool,
+    pub(crate) strike: bool,
+    pub(crate) code: bool,
+}
+impl SpanStyle {
+    fn with(mut self, style: Style) -> Self {
+        match style {
+            Style::BOLD => self.bold = true,
+            Style::ITALIC => self.italic = true,
+            Style::STRIKE => self.strike = true,
+        }
+
+        self
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Span {
+    TEXT(String, SpanStyle),
+    LINK(String),
+    // Id of the mentioned user, see mentions::encode.
+    MENTION(String),
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Block {
+    PARAGRAPH(Vec<Span>),
+    QUOTE(Vec<Span>),
+    LIST_ITEM(String, Vec<Span>),
+    // Language, code and its tokens by line, highlighted once when parsed.
+    CODE_BLOCK(String, String, Vec<Vec<(TokenKind, String)>>),
+}
+
+/// Parses a chat message, every line is its own block except for fenced code blocks.
+pub(crate) fn parse(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+
+        if let Some(language) = trimmed.strip_prefix("```") {
+            let mut code = Vec::new();
+            for code_line in lines.by_ref() {
+                if code_line.trim_start().starts_with("```") { break; }
+                code.push(code_line);
+            }
+
+            blocks.push(code_block(language.trim(), code.join("\n")));
+        }
+        else if let Some(quote) = trimmed.strip_prefix('>') {
+            blocks.push(Block::QUOTE(parse_inline(quote.strip_prefix(' ').unwrap_or(quote))));
+        }
+        else if let Some(item) = ["- ", "* ", "+ "].iter().find_map(|m| trimmed.strip_prefix(m)) {
+            blocks.push(Block::LIST_ITEM(String::from("•"), parse_inline(item)));
+        }
+        else if let Some((number, item)) = ordered_item(trimmed) {
+            blocks.push(Block::LIST_ITEM(std::format!("{number}."), parse_inline(item)));
+        }
+        else {
+            blocks.push(Block::PARAGRAPH(parse_inline(line)));
+        }
+    }
+
+    blocks
+}
+
+fn code_block(language: &str, code: String) -> Block {
+    let lines = syntax::highlight(Language::from_name(language), &code)
+        .into_iter()
+        .map(|tokens| tokens.into_iter().map(|(kind, text)| (kind, text.to_string())).collect())
+        .collect();
+
+    Block::CODE_BLOCK(language.to_string(), code, lines)
+}
+
+pub(crate) fn parse_inline(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    parse_inline_styled(text, SpanStyle::default(), &mut spans);
+
+    spans
+}
+
+/// Renders the blocks wrapping the text to the available width.
+pub(crate) fn show_markdown(ui: &imgui::Ui, theme: &Theme, blocks: &[Block], mentions: &MentionNames) {
+    let start_x = ui.cursor_pos()[0];
+    let wrap_width = ui.content_region_avail()[0];
+
+    for (i, block) in blocks.iter().enumerate() {
+        match block {
+            Block::PARAGRAPH(spans) => show_spans(ui, theme, spans, mentions, start_x, wrap_width),
+            Block::QUOTE(spans) => {
+                let bar_start = ui.cursor_screen_pos();
+                show_spans(ui, theme, spans, mentions, start_x + 12.0, wrap_width - 12.0);
+
+                ui.get_window_draw_list()
+                    .add_rect(bar_start, [bar_start[0] + 4.0, ui.cursor_screen_pos()[1] - 2.0], theme.accent_color)
+                    .filled(true)
+                    .build();
+            },
+            Block::LIST_ITEM(marker, spans) => {
+                ui.set_cursor_pos([start_x + 8.0, ui.cursor_pos()[1]]);
+                ui.text(marker);
+                ui.same_line();
+
+                let item_x = ui.cursor_pos()[0];
+                show_spans(ui, theme, spans, mentions, item_x, wrap_width - (item_x - start_x));
+            },
+            Block::CODE_BLOCK(_, code, lines) => {
+                ui.set_cursor_pos([start_x, ui.cursor_pos()[1]]);
+                show_code_block(ui, theme, code, lines, wrap_width, i);
+            },
+        }
+    }
+
+    ui.set_cursor_pos([start_x, ui.cursor_pos()[1]]);
+}
+
+fn ordered_item(line: &str) -> Option<(&str, &str)> {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 { return None; }
+
+    line[digits..].strip_prefix(". ").map(|item| (&line[..digits], item))
+}
+
+fn parse_inline_styled(text: &str, style: SpanStyle, spans: &mut Vec<Span>) {
+    let mut plain = String::new();
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let previous = text[..i].chars().last();
+        let c = rest.chars().next().unwrap_or_default();
+
+        // Escaped characters are always literal.
+        if c == '\\' {
+            if let Some(escaped) = rest[1..].chars().next().filter(|e| e.is_ascii_punctuation()) {
+                plain.push(escaped);
+                i += 1 + escaped.len_utf8();
+                continue;
+            }
+        }
+
+        // Inline code, the content is never styled.
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`').filter(|end| *end > 0) {
+                push_text(spans, &mut plain, style);
+                spans.push(Span::TEXT(rest[1..1 + end].to_string(), SpanStyle { code: true, ..style }));
+                i += end + 2;
+                continue;
+            }
+        }
+
+        // Mentions, by user id.
+        if let Some((id, len)) = mentions::parse(rest) {
+            push_text(spans, &mut plain, style);
+            spans.push(Span::MENTION(id.to_string()));
+            i += len;
+            continue;
+        }
+
+        // Auto links.
+        if (rest.starts_with("https://") || rest.starts_with("http://")) && previous.map_or(true, |p| p.is_whitespace() || p == '(') {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let url = rest[..end].trim_end_matches(|c: char| matches!(c, '.' | ',' | ')' | '!' | '?' | ';' | ':'));
+
+            push_text(spans, &mut plain, style);
+            spans.push(Span::LINK(url.to_string()));
+            i += url.len();
+            continue;
+        }
+
+        // Emphasis, only when there is a matching closing delimiter.
+        let left_flanking = previous.map_or(true, |p| !p.is_alphanumeric());
+        if let Some((delimiter, delimiter_style)) = INLINE_DELIMITERS.iter().find(|(d, _)| rest.starts_with(d)) {
+            let content = &rest[delimiter.len()..];
+            let intraword = delimiter.starts_with('_') && !left_flanking;
+
+            if !intraword && !content.starts_with(char::is_whitespace) {
+                if let Some(end) = closing_delimiter(content, delimiter) {
+                    push_text(spans, &mut plain, style);
+                    parse_inline_styled(&content[..end], style.with(*delimiter_style), spans);
+                    i += delimiter.len() * 2 + end;
+                    continue;
+                }
+            }
+        }
+
+        plain.push(c);
+        i += c.len_utf8();
+    }
+
+    push_text(spans, &mut plain, style);
+}
+
+fn closing_delimiter(content: &str, delimiter: &str) -> Option<usize> {
+    let mut search_from = 0;
+
+    while let Some(found) = content[search_from..].find(delimiter) {
+        let mut end = search_from + found;
+
+        // On runs like "***" the strong delimiter closes with the last two characters.
+        let run = content[end..].chars().take_while(|c| delimiter.starts_with(*c)).count();
+        if delimiter.len() == 2 && run > 2 {
+            end += run - 2;
+        }
+
+        let after = content[end + delimiter.len()..].chars().next();
+        let before = content[..end].chars().last();
+
+        // "**" inside "*" emphasis must not close it, and "_" must end a word.
+        let doubled = delimiter.len() == 1 && content[end + 1..].starts_with(delimiter);
+        let intraword = delimiter.starts_with('_') && after.map_or(false, |a| a.is_alphanumeric());
+
+        if end > 0 && !before.map_or(false, char::is_whitespace) && !doubled && !intraword {
+            return Some(end);
+        }
+
+        search_from = end + delimiter.len() * if doubled { 2 } else { 1 };
+    }
+
+    None
+}
+
+fn push_text(spans: &mut Vec<Span>, plain: &mut String, style: SpanStyle) {
+    if plain.is_empty() { return; }
+
+    match spans.last_mut() {
+        Some(Span::TEXT(text, last_style)) if *last_style == style => text.push_str(plain),
+        _ => spans.push(Span::TEXT(plain.clone(), style)),
+    }
+    plain.clear();
+}
+
+fn font_for(style: SpanStyle) -> FontType {
+    match (style.code, style.bold, style.italic) {
+        (true, _, _) => FontType::MONO20,
+        (_, true, true) => FontType::BOLD_ITALIC24,
+        (_, true, false) => FontType::BOLD24,
+        (_, false, true) => FontType::ITALIC24,
+        _ => FontType::REGULAR24,
+    }
+}
+
+fn show_spans(ui: &imgui::Ui, theme: &Theme, spans: &[Span], mentions: &MentionNames, start_x: f32, wrap_width: f32) {
+    ui.set_cursor_pos([start_x, ui.cursor_pos()[1]]);
+    if spans.is_empty() {
+        ui.new_line();
+        return;
+    }
+
+    let mut line_width = 0.0;
+    for span in spans {
+        let (text, style, url) = match span {
+            Span::TEXT(text, style) => (text.as_str(), *style, None),
+            Span::LINK(url) => (url.as_str(), SpanStyle::default(), Some(url.as_str())),
+            Span::MENTION(id) => {
+                let (tag, current_user) = mentions
+                    .get(id)
+                    .map(|(tag, current_user)| (tag.as_str(), *current_user))
+                    .unwrap_or(("Unknown User", false));
+
+                let _font = use_font(ui, FontType::BOLD24);
+                let chip = std::format!("@{tag}");
+                let chip_size = ui.calc_text_size(&chip);
+                if line_width > 0.0 && line_width + chip_size[0] > wrap_width {
+                    ui.new_line();
+                    ui.set_cursor_pos([start_x, ui.cursor_pos()[1]]);
+                    line_width = 0.0;
+                }
+
+                show_mention(ui, theme, &chip, chip_size, current_user);
+                line_width += chip_size[0];
+                continue;
+            },
+        };
+
+        let _font = use_font(ui, font_for(style));
+        for word in split_words(text) {
+            let mut word_size = ui.calc_text_size(word);
+
+            if line_width > 0.0 && line_width + word_size[0] > wrap_width {
+                ui.new_line();
+                ui.set_cursor_pos([start_x, ui.cursor_pos()[1]]);
+                line_width = 0.0;
+
+                // Leading spaces are dropped when wrapping.
+                if word.trim().is_empty() { continue; }
+            }
+
+            // Words wider than the whole line are broken by characters.
+            for chunk in break_word(ui, word, wrap_width) {
+                if chunk.len() != word.len() {
+                    word_size = ui.calc_text_size(chunk);
+                    if line_width > 0.0 {
+                        ui.new_line();
+                        ui.set_cursor_pos([start_x, ui.cursor_pos()[1]]);
+                        line_width = 0.0;
+                    }
+                }
+
+                show_word(ui, theme, chunk, word_size, style, url);
+                line_width += word_size[0];
+            }
+        }
+    }
+
+    ui.new_line();
+}
+
+fn show_word(ui: &imgui::Ui, theme: &Theme, word: &str, size: [f32; 2], style: SpanStyle, url: Option<&str>) {
+    let pos = ui.cursor_screen_pos();
+
+    if style.code {
+        ui.get_window_draw_list()
+            .add_rect(pos, [pos[0] + size[0], pos[1] + size[1]], theme.mini_pop_up_color)
+            .filled(true)
+            .build();
+    }
+
+    match url {
+        Some(_) => ui.text_colored(theme.sign_up_actv_btn_color, word),
+        None => ui.text(word),
+    }
+
+    if style.strike || url.is_some() {
+        let y = if style.strike { pos[1] + size[1] / 2.0 } else { pos[1] + size[1] - 1.0 };
+        let color = if url.is_some() { theme.sign_up_actv_btn_color } else { theme.font_color };
+
+        ui.get_window_draw_list()
+            .add_line([pos[0], y], [pos[0] + size[0], y], color)
+            .thickness(1.5)
+            .build();
+    }
+
+    if let Some(url) = url {
+        if ui.is_item_hovered() {
+            ui.set_mouse_cursor(Some(imgui::MouseCursor::Hand));
+            ui.tooltip_text(url);
+        }
+        if ui.is_item_clicked() {
+            open_url(url);
+        }
+    }
+
+    ui.same_line_with_spacing(0.0, 0.0);
+}
+
+fn show_mention(ui: &imgui::Ui, theme: &Theme, chip: &str, size: [f32; 2], current_user: bool) {
+    let pos = ui.cursor_screen_pos();
+    let color = if current_user { theme.sign_up_btn_color } else { theme.accent_color };
+
+    ui.get_window_draw_list()
+        .add_rect(pos, [pos[0] + size[0], pos[1] + size[1]], color)
+        .filled(true)
+        .rounding(BORDER_RADIUS)
+        .build();
+    ui.text(chip);
+    ui.same_line_with_spacing(0.0, 0.0);
+}
+
+// Long lines scroll horizontally instead of wrapping, the copy button stays on the top right corner.
+fn show_code_block(ui: &imgui::Ui, theme: &Theme, code: &str, lines: &[Vec<(TokenKind, String)>], width: f32, index: usize) {
+    let _font = use_font(ui, FontType::MONO20);
+    let line_height = ui.text_line_height_with_spacing();
+
+    let padding = 5.0;
+    let code_width = code.lines().map(|line| ui.calc_text_size(line)[0]).fold(0.0, f32::max);
+    let scrollbar = if code_width + padding * 2.0 > width { ui.clone_style().scrollbar_size } else { 0.0 };
+    let height = line_height * lines.len().max(1) as f32 + padding * 2.0 + scrollbar;
+
+    let _rounding = ui.push_style_var(imgui::StyleVar::ChildRounding(BORDER_RADIUS));
+    super::no_resize_child_window(
+        ui,
+        &std::format!("##code_block_{index}"),
+        imgui::WindowFlags::HORIZONTAL_SCROLLBAR | imgui::WindowFlags::NO_SCROLL_WITH_MOUSE,
+        [width, height],
+        [padding; 2],
+        theme.mini_pop_up_color,
+        |ui| {
+            for tokens in lines {
+                if tokens.is_empty() {
+                    ui.text("");
+                    continue;
+                }
+
+                for (i, (kind, text)) in tokens.iter().enumerate() {
+                    if i > 0 { ui.same_line_with_spacing(0.0, 0.0); }
+                    ui.text_colored(token_color(theme, *kind), text);
+                }
+            }
+
+            let resume_pos = ui.cursor_pos();
+            let _font = use_font(ui, FontType::BOLD15);
+            ui.set_cursor_pos([ui.scroll_x() + ui.window_size()[0] - 55.0, padding]);
+            if super::button(
+                ui,
+                &std::format!("Copy##code_copy_{index}"),
+                [50.0, 20.0],
+                BORDER_RADIUS,
+                theme.accent_color,
+                theme.sign_up_btn_color,
+                theme.sign_up_btn_color,
+            ) {
+                ui.set_clipboard_text(code);
+            }
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Copy to clipboard");
+            }
+            ui.set_cursor_pos(resume_pos);
+        });
+}
+
+fn token_color(theme: &Theme, kind: TokenKind) -> [f32; 4] {
+    match kind {
+        TokenKind::PLAIN => theme.font_color,
+        TokenKind::KEYWORD => theme.code_keyword_color,
+        TokenKind::TYPE => theme.code_type_color,
+        TokenKind::STRING => theme.code_string_color,
+        TokenKind::NUMBER => theme.code_number_color,
+        TokenKind::COMMENT => theme.code_comment_color,
+    }
+}
+
+/// Splits keeping the whitespace attached to the start of each word.
+fn split_words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() && i > start && !text[start..i].trim().is_empty() {
+            words.push(&text[start..i]);
+            start = i;
+        }
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+
+    words
+}
+
+fn break_word<'a>(ui: &imgui::Ui, word: &'a str, wrap_width: f32) -> Vec<&'a str> {
+    if ui.calc_text_size(word)[0] <= wrap_width { return vec![word]; }
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (i, c) in word.char_indices() {
+        let end = i + c.len_utf8();
+        if ui.calc_text_size(&word[start..end])[0] > wrap_width && i > start {
+            chunks.push(&word[start..i]);
+            start = i;
+        }
+    }
+    chunks.push(&word[start..]);
+
+    chunks
+}
+
+fn open_url(url: &str) {
+    let result = if cfg!(target_os = "windows") {
+        std::process::Command::new("cmd").args(["/C", "start", "", url]).spawn()
+    }
+    else if cfg!(target_os = "macos") {
+        std::process::Command::new("open").arg(url).spawn()
+    }
+    else {
+        std::process::Command::new("xdg-open").arg(url).spawn()
+    };
+
+    if let Err(e) = result {
+        error!("In markdown::open_url: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Span {
+        Span::TEXT(text.to_string(), SpanStyle::default())
+    }
+
+    fn styled(text: &str, style: SpanStyle) -> Span {
+        Span::TEXT(text.to_string(), style)
+    }
+
+    const BOLD: SpanStyle = SpanStyle { bold: true, italic: false, strike: false, code: false };
+    const ITALIC: SpanStyle = SpanStyle { bold: false, italic: true, strike: false, code: false };
+    const STRIKE: SpanStyle = SpanStyle { bold: false, italic: false, strike: true, code: false };
+    const CODE: SpanStyle = SpanStyle { bold: false, italic: false, strike: false, code: true };
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(parse_inline("just yapping"), vec![text("just yapping")]);
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(parse_inline("a **b** c"), vec![text("a "), styled("b", BOLD), text(" c")]);
+        assert_eq!(parse_inline("*a* _b_"), vec![styled("a", ITALIC), text(" "), styled("b", ITALIC)]);
+        assert_eq!(parse_inline("~~gone~~"), vec![styled("gone", STRIKE)]);
+    }
+
+    #[test]
+    fn nested_emphasis() {
+        assert_eq!(
+            parse_inline("**bold *both***"),
+            vec![styled("bold ", BOLD), styled("both", SpanStyle { bold: true, italic: true, ..Default::default() })]
+        );
+    }
+
+    #[test]
+    fn unmatched_delimiters_are_literal() {
+        assert_eq!(parse_inline("2 * 3 = 6"), vec![text("2 * 3 = 6")]);
+        assert_eq!(parse_inline("**open"), vec![text("**open")]);
+        assert_eq!(parse_inline("snake_case_name"), vec![text("snake_case_name")]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(parse_inline(r"\*not italic\*"), vec![text("*not italic*")]);
+    }
+
+    #[test]
+    fn inline_code_is_not_styled() {
+        assert_eq!(parse_inline("run `cargo **build**`"), vec![text("run "), styled("cargo **build**", CODE)]);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            parse_inline("see https://example.com/a_b, ok"),
+            vec![text("see "), Span::LINK(String::from("https://example.com/a_b")), text(", ok")]
+        );
+        assert_eq!(parse_inline("nohttps://x"), vec![text("nohttps://x")]);
+    }
+
+    #[test]
+    fn mention_spans() {
+        assert_eq!(
+            parse_inline("hey <@1234-abcd>!"),
+            vec![text("hey "), Span::MENTION(String::from("1234-abcd")), text("!")]
+        );
+        assert_eq!(parse_inline("a <@ b> <@>"), vec![text("a <@ b> <@>")]);
+    }
+
+    #[test]
+    fn blocks() {
+        let blocks = parse("> quoted\n- item\n2. second\n\nline");
+
+        assert_eq!(blocks, vec![
+            Block::QUOTE(vec![text("quoted")]),
+            Block::LIST_ITEM(String::from("•"), vec![text("item")]),
+            Block::LIST_ITEM(String::from("2."), vec![text("second")]),
+            Block::PARAGRAPH(vec![]),
+            Block::PARAGRAPH(vec![text("line")]),
+        ]);
+    }
+
+    #[test]
+    fn fenced_code_block() {
+        let blocks = parse("before\n```rust\nfn main() {\n    *x*\n}\n```\nafter");
+
+        assert_eq!(blocks, vec![
+            Block::PARAGRAPH(vec![text("before")]),
+            Block::CODE_BLOCK(String::from("rust"), String::from("fn main() {\n    *x*\n}"), vec![
+                vec![(TokenKind::KEYWORD, String::from("fn")), (TokenKind::PLAIN, String::from(" main() {"))],
+                vec![(TokenKind::PLAIN, String::from("    *x*"))],
+                vec![(TokenKind::PLAIN, String::from("}"))],
+            ]),
+            Block::PARAGRAPH(vec![text("after")]),
+        ]);
+    }
+
+    #[test]
+    fn unterminated_code_block_takes_the_rest() {
+        assert_eq!(parse("```\ncode"), vec![Block::CODE_BLOCK(String::new(), String::from("code"), vec![vec![(TokenKind::PLAIN, String::from("code"))]])]);
+    }
+}
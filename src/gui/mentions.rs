@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+use yapping_core::{chat::Chat, l3gion_rust::UUID, user::User};
+
+use crate::client_manager::SharedMut;
+
+use super::user_tag;
+
+/// Display name by the id written on the message, and whether it's the current user.
+pub(crate) type MentionNames = HashMap<String, (String, bool)>;
+
+/// Mentions are stored by UUID, so they survive tag changes.
+pub(crate) fn encode(user_uuid: UUID) -> String {
+    std::format!("<@{}>", user_uuid.to_string())
+}
+
+/// When the text starts with a mention, returns the id and the length of the whole mention.
+pub(crate) fn parse(text: &str) -> Option<(&str, usize)> {
+    let rest = text.strip_prefix("<@")?;
+    let end = rest.find('>')?;
+    let id = &rest[..end];
+
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') { return None; }
+
+    Some((id, end + 3))
+}
+
+pub(crate) fn mentions(text: &str, user_uuid: UUID) -> bool {
+    text.contains(&encode(user_uuid))
+}
+
+/// The "@" being typed at the end of the text, with its position.
+pub(crate) fn mention_query(text: &str) -> Option<(usize, &str)> {
+    let start = text.rfind('@')?;
+    let query = &text[start + 1..];
+
+    let valid_start = text[..start].chars().last().map_or(true, char::is_whitespace);
+    let valid_query = !query.contains(char::is_whitespace);
+
+    if valid_start && valid_query { Some((start, query)) }
+    else { None }
+}
+
+/// Members of the Chat that can be mentioned.
+pub(crate) fn names(shared_mut: &SharedMut, chat: &Chat) -> MentionNames {
+    let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+
+    chat.users()
+        .iter()
+        .map(|member| (member.to_string(), (user_tag(shared_mut, *member), current_user == Some(*member))))
+        .collect()
+}
+
+/// Replaces the "@tag" of the chosen mentions with their encoded form.
+/// Only whole tokens are replaced, so "@bobby" or "x@bob.com" are left alone when "bob" was chosen.
+pub(crate) fn encode_all(text: &str, chosen: &[(String, UUID)]) -> String {
+    // Longer tags first, so prefixes don't clash.
+    let mut chosen = chosen.iter().collect::<Vec<_>>();
+    chosen.sort_by_key(|(tag, _)| std::cmp::Reverse(tag.len()));
+
+    let mut result = String::with_capacity(text.len());
+    let mut previous = None;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        let token = (c == '@' && previous.map_or(true, char::is_whitespace))
+            .then(|| chosen.iter().find(|(tag, _)| {
+                rest[1..].strip_prefix(tag.as_str()).is_some_and(|after| !after.starts_with(is_tag_char))
+            }))
+            .flatten();
+
+        match token {
+            Some((tag, user_uuid)) => {
+                result.push_str(&encode(*user_uuid));
+                rest = &rest[tag.len() + 1..];
+                previous = tag.chars().last();
+            },
+            None => {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+                previous = Some(c);
+            },
+        }
+    }
+
+    result
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The text with every mention written as "@tag", for previews.
+pub(crate) fn display_text(shared_mut: &SharedMut, text: &str) -> String {
+    decode(shared_mut, text).0
+}
+
+/// Reverse of encode_all, returns the text as typed on the composer and the mentions in it.
+pub(crate) fn decode(shared_mut: &SharedMut, text: &str) -> (String, Vec<(String, UUID)>) {
+    decode_by(text, |id| user_by_id(shared_mut, id).map(|user| (user.tag().to_string(), user.uuid())))
+}
+
+/// decode with the tag and UUID of each User given by `user_by_id`, for when there's no SharedMut like on the CLI export.
+pub(crate) fn decode_by(text: &str, user_by_id: impl Fn(&str) -> Option<(String, UUID)>) -> (String, Vec<(String, UUID)>) {
+    let mut result = String::with_capacity(text.len());
+    let mut chosen = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("<@") {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        match parse(rest) {
+            Some((id, len)) => {
+                match user_by_id(id) {
+                    Some((tag, user_uuid)) => {
+                        result.push('@');
+                        result.push_str(&tag);
+                        chosen.push((tag, user_uuid));
+                    },
+                    None => result.push_str("@Unknown User"),
+                }
+                rest = &rest[len..];
+            },
+            None => {
+                result.push_str("<@");
+                rest = &rest[2..];
+            },
+        }
+    }
+    result.push_str(rest);
+
+    (result, chosen)
+}
+
+fn user_by_id<'a>(shared_mut: &'a SharedMut, id: &str) -> Option<&'a User> {
+    let current_user = shared_mut.user.as_ref()?;
+
+    std::iter::once(current_user)
+        .chain(current_user.friends().iter())
+        .chain(shared_mut.users.values())
+        .find(|user| user.uuid().to_string() == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mentions() {
+        assert_eq!(parse("<@abc-123> hi"), Some(("abc-123", 10)));
+        assert_eq!(parse("<@> hi"), None);
+        assert_eq!(parse("<@a b>"), None);
+        assert_eq!(parse("<@abc"), None);
+        assert_eq!(parse("hi <@abc>"), None);
+    }
+
+    #[test]
+    fn query_at_the_end() {
+        assert_eq!(mention_query("hi @bo"), Some((3, "bo")));
+        assert_eq!(mention_query("@"), Some((0, "")));
+        assert_eq!(mention_query("x@bo"), None);
+        assert_eq!(mention_query("@bob hi"), None);
+    }
+
+    #[test]
+    fn encode_whole_tokens() {
+        let bob = UUID::generate();
+        let bobby = UUID::generate();
+        let chosen = [(String::from("bob"), bob)];
+
+        assert_eq!(encode_all("@bob, hi", &chosen), std::format!("{}, hi", encode(bob)));
+        assert_eq!(encode_all("@bobby x@bob.com", &chosen), "@bobby x@bob.com");
+        assert_eq!(
+            encode_all("@bob @bobby", &[(String::from("bob"), bob), (String::from("bobby"), bobby)]),
+            std::format!("{} {}", encode(bob), encode(bobby)),
+        );
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let bob = UUID::generate();
+        let chosen = vec![(String::from("bob"), bob)];
+        let encoded = encode_all("hi @bob <@", &chosen);
+        let user_by_id = |id: &str| (id == bob.to_string()).then(|| (String::from("bob"), bob));
+
+        assert_eq!(decode_by(&encoded, user_by_id), (String::from("hi @bob <@"), chosen));
+        assert_eq!(decode_by(&encode(UUID::generate()), user_by_id).0, "@Unknown User");
+    }
+}
@@ -1,5 +1,7 @@
 use std::cell::OnceCell;
-use yapping_core::l3gion_rust::{imgui, lg_core::{renderer::{texture::{TextureFilter, TextureFormat, TextureSpecs}, Renderer}, window::LgWindow}, StdError, UUID};
+use yapping_core::{chat::Chat, chrono::{self, Datelike}, date_time::DateTime, l3gion_rust::{imgui, lg_core::{renderer::{texture::{TextureFilter, TextureFormat, TextureSpecs}, Renderer}, window::LgWindow}, StdError, UUID}, message::{Message, MessageType}};
+
+use crate::{client_manager::SharedMut, gui::theme::Theme};
 
 pub(crate) mod theme;
 pub(crate) mod validation_gui;
@@ -9,9 +11,24 @@ pub(crate) mod gui_manager;
 pub(crate) mod sidebar_gui;
 pub(crate) mod chat_page_gui;
 pub(crate) mod config_overlay_gui;
+pub(crate) mod thread_panel_gui;
+pub(crate) mod markdown;
+pub(crate) mod mentions;
+pub(crate) mod emoji;
+pub(crate) mod new_group_gui;
+pub(crate) mod chat_info_gui;
+pub(crate) mod search_gui;
+pub(crate) mod time_format;
+pub(crate) mod commands;
+pub(crate) mod export_gui;
+pub(crate) mod safety_number_gui;
+pub(crate) mod syntax;
+pub(crate) mod forward_gui;
+pub(crate) mod bookmarks_gui;
 
 const BORDER_RADIUS: f32 = 5.0;
 
+#[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum FontType {
     REGULAR17,
@@ -19,6 +36,9 @@ pub(crate) enum FontType {
     BOLD17,
     BOLD24,
     BOLD15,
+    ITALIC24,
+    BOLD_ITALIC24,
+    MONO20,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -28,6 +48,9 @@ struct Fonts {
     bold_17: imgui::FontId,
     bold_24: imgui::FontId,
     bold_15: imgui::FontId,
+    italic_24: imgui::FontId,
+    bold_italic_24: imgui::FontId,
+    mono_20: imgui::FontId,
 }
 
 static LOGO_PATH: &str = "assets/textures/logo.png";
@@ -62,6 +85,7 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
                 config: None 
             }
         ),
+        (String::from("Emoji-Regular17"), emoji_font(17.0)),
         (
             String::from("Roboto-Regular24"),
             imgui::FontSource::TtfData { 
@@ -70,6 +94,7 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
                 config: None 
             }
         ),
+        (String::from("Emoji-Regular24"), emoji_font(24.0)),
         (
             String::from("Roboto-Bold17"),
             imgui::FontSource::TtfData { 
@@ -78,6 +103,7 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
                 config: None 
             }
         ),
+        (String::from("Emoji-Bold17"), emoji_font(17.0)),
         (
             String::from("Roboto-Bold24"),
             imgui::FontSource::TtfData { 
@@ -86,6 +112,7 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
                 config: None 
             }
         ),
+        (String::from("Emoji-Bold24"), emoji_font(24.0)),
         (
             String::from("Roboto-Bold15"),
             imgui::FontSource::TtfData { 
@@ -94,6 +121,34 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
                 config: None 
             }
         ),
+        (String::from("Emoji-Bold15"), emoji_font(15.0)),
+        (
+            String::from("Roboto-Italic24"),
+            imgui::FontSource::TtfData {
+                data: include_bytes!("../../resources/fonts/roboto/Roboto-Italic.ttf"),
+                size_pixels: 24.0,
+                config: None
+            }
+        ),
+        (String::from("Emoji-Italic24"), emoji_font(24.0)),
+        (
+            String::from("Roboto-BoldItalic24"),
+            imgui::FontSource::TtfData {
+                data: include_bytes!("../../resources/fonts/roboto/Roboto-BoldItalic.ttf"),
+                size_pixels: 24.0,
+                config: None
+            }
+        ),
+        (String::from("Emoji-BoldItalic24"), emoji_font(24.0)),
+        (
+            String::from("Hack-Regular20"),
+            imgui::FontSource::TtfData {
+                data: include_bytes!("../../resources/fonts/hack/Hack-Regular.ttf"),
+                size_pixels: 20.0,
+                config: None
+            }
+        ),
+        (String::from("Emoji-Mono20"), emoji_font(20.0)),
     ]);
 
     renderer.set_fonts();
@@ -105,6 +160,9 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
         bold_17: imgui_core.get_font_id("Roboto-Bold17").unwrap(),
         bold_24: imgui_core.get_font_id("Roboto-Bold24").unwrap(),
         bold_15: imgui_core.get_font_id("Roboto-Bold15").unwrap(),
+        italic_24: imgui_core.get_font_id("Roboto-Italic24").unwrap(),
+        bold_italic_24: imgui_core.get_font_id("Roboto-BoldItalic24").unwrap(),
+        mono_20: imgui_core.get_font_id("Hack-Regular20").unwrap(),
     };
 
     FONTS.with(|fonts_cell| fonts_cell.set(fonts).unwrap());
@@ -112,6 +170,21 @@ pub(crate) fn init_gui(renderer: &mut Renderer, window: &LgWindow) -> Result<(),
     Ok(())
 }
 
+// Merged into the previously inserted font, so emoji render inside regular text and inputs.
+fn emoji_font(size_pixels: f32) -> imgui::FontSource<'static> {
+    imgui::FontSource::TtfData {
+        data: include_bytes!("../../resources/fonts/noto_emoji/NotoEmoji-Regular.ttf"),
+        size_pixels,
+        config: Some(imgui::FontConfig {
+            merge_mode: true,
+            glyph_ranges: imgui::FontGlyphRanges::from_slice(&emoji::EMOJI_GLYPH_RANGES),
+            oversample_h: 1,
+            oversample_v: 1,
+            ..Default::default()
+        }),
+    }
+}
+
 fn window<F, R>(
     ui: &imgui::Ui,
     title: &str,
@@ -264,6 +337,9 @@ pub(crate) fn use_font(ui: &imgui::Ui, font_type: FontType) -> imgui::FontStackT
             FontType::BOLD17 => font.bold_17,
             FontType::BOLD24 => font.bold_24,
             FontType::BOLD15 => font.bold_15,
+            FontType::ITALIC24 => font.italic_24,
+            FontType::BOLD_ITALIC24 => font.bold_italic_24,
+            FontType::MONO20 => font.mono_20,
         };
         
         ui.push_font(to_use)
@@ -309,6 +385,39 @@ fn multiline_text_input(
         .build()
 }
 
+struct BufferReplacement(Option<String>);
+impl imgui::InputTextCallbackHandler for BufferReplacement {
+    fn on_always(&mut self, mut data: imgui::TextCallbackData) {
+        if let Some(text) = self.0.take() {
+            data.clear();
+            data.push_str(&text);
+            data.set_cursor_pos(text.len());
+        }
+    }
+}
+
+/// Like multiline_text_input, but the replacement is applied even while the input is active.
+fn multiline_text_input_with_replacement(
+    ui: &imgui::Ui,
+    size: [f32; 2],
+    buffer: &mut String,
+    label: &str,
+    bg_color: [f32; 4],
+    text_color: [f32; 4],
+    border_radius: f32,
+    flags: imgui::InputTextFlags,
+    replacement: Option<String>,
+) -> bool {
+    let _bg_color_token = ui.push_style_color(imgui::StyleColor::FrameBg, bg_color);
+    let _text_color_token = ui.push_style_color(imgui::StyleColor::Text, text_color);
+    let _frame_rounding = ui.push_style_var(imgui::StyleVar::FrameRounding(border_radius));
+
+    ui.input_text_multiline(label, buffer, size)
+        .flags(flags)
+        .callback(imgui::InputTextMultilineCallback::ALWAYS, BufferReplacement(replacement))
+        .build()
+}
+
 fn button(
     ui: &imgui::Ui,
     label: &str,
@@ -334,6 +443,64 @@ fn button(
     return clicked;
 }
 
+fn user_tag(shared_mut: &SharedMut, user_uuid: UUID) -> String {
+    let current_user = if let Some(user) = &shared_mut.user { user }
+    else { return String::from("Unknown User"); };
+
+    if current_user.uuid() == user_uuid { return current_user.tag().to_string(); }
+
+    current_user.friends()
+        .iter()
+        .chain(shared_mut.users.values())
+        .find(|u| u.uuid() == user_uuid)
+        .map(|u| u.tag().to_string())
+        .unwrap_or_else(|| String::from("Unknown User"))
+}
+
+// Direct chats are created without a tag, older ones use the placeholder.
+pub(crate) fn is_group(chat: &Chat) -> bool {
+    !chat.tag().is_empty() && chat.tag() != "placeholder_chat_tag"
+}
+
+/// Group name, or the other participant's tag for direct chats.
+fn chat_name(shared_mut: &SharedMut, chat: &Chat) -> String {
+    if is_group(chat) { return chat.tag().to_string(); }
+
+    let current_user = shared_mut.user.as_ref().map(|user| user.uuid());
+    chat.users()
+        .iter()
+        .find(|u| Some(**u) != current_user)
+        .map(|u| user_tag(shared_mut, *u))
+        .unwrap_or_else(|| String::from("Empty Chat"))
+}
+
+fn message_snippet(shared_mut: &SharedMut, message: &Message, max_chars: usize) -> String {
+    match message.content() {
+        MessageType::TEXT(text) => {
+            let text = mentions::display_text(shared_mut, text);
+            let first_line = text.lines().next().unwrap_or_default();
+            if first_line.chars().count() > max_chars || text.lines().count() > 1 {
+                std::format!("{}...", first_line.chars().take(max_chars).collect::<String>())
+            }
+            else { first_line.to_string() }
+        },
+        MessageType::FILE(_) => String::from("[File]"),
+    }
+}
+
+// "now", "5m", "3h", "2d" and the date for anything older than a week.
+fn relative_time(date_time: &DateTime) -> String {
+    let date_time = if let Ok(date_time) = date_time.to_local() { date_time }
+    else { return String::default(); };
+
+    let elapsed = chrono::Local::now().signed_duration_since(date_time);
+    if elapsed.num_minutes() < 1 { String::from("now") }
+    else if elapsed.num_hours() < 1 { std::format!("{}m", elapsed.num_minutes()) }
+    else if elapsed.num_days() < 1 { std::format!("{}h", elapsed.num_hours()) }
+    else if elapsed.num_days() < 7 { std::format!("{}d", elapsed.num_days()) }
+    else { std::format!("{}/{}/{}", date_time.day(), date_time.month(), date_time.year()) }
+}
+
 fn spacing(ui: &imgui::Ui, quantity: u32) {
     for _ in 0..quantity {
         ui.spacing();
@@ -354,40 +521,39 @@ fn centered_component<R>(
     component(ui, component_size)
 }
 
-pub(super) fn show_loading_gui(
+/// Non modal banner shown on top of everything while the Server can't be reached. Returns true when "Retry now" is clicked.
+pub(super) fn show_offline_banner(
     ui: &imgui::Ui, 
-    renderer: &Renderer,
-    position: [f32; 2], 
-    size: [f32; 2],
-    bg_color: [f32; 4],
-) {
+    theme: &Theme,
+    reconnect_in: u32,
+) -> bool {
+    let size = [420.0, 40.0];
+    let position = [(ui.io().display_size[0] - size[0]) / 2.0, 10.0];
+
     no_resize_window(
         ui, 
-        "Loading Window", 
+        "offline_banner",
         None,
-        [0.0, 0.0], 
-        ui.io().display_size, 
-        [0.0, 0.0], 
-        [0.0, 0.0], 
-        bg_color, 
+        position,
+        size,
+        [10.0, 5.0],
+        size,
+        theme.negative_btn_color,
         |ui| {
-            let content_size = ui.content_region_avail();
-            let logo_size = content_size[0] / 3.0;
+            let _font = use_font(ui, FontType::BOLD17);
+            ui.set_cursor_pos([ui.cursor_pos()[0], (size[1] - ui.text_line_height()) / 2.0]);
+            ui.text(std::format!("Offline - reconnecting in {reconnect_in}s"));
             
-            show_logo(
+            ui.same_line_with_pos(size[0] - 110.0);
+            ui.set_cursor_pos([ui.cursor_pos()[0], 5.0]);
+            button(
                 ui, 
-                renderer, 
-                [logo_size; 2],
-                [
-                    content_size[0] / 3.0, 
-                    content_size[1] / 4.0 - logo_size / 2.0
-                ],
-            );
-            
-            let _font = use_font(ui, FontType::BOLD24);
-            let text = "LOAGING...";
-            let text_width = ui.calc_text_size(text)[0];
-            ui.set_cursor_pos([ui.content_region_avail()[0] / 2.0 - text_width / 2.0, size[1] / 1.5]);
-            ui.text(text);
-        });
+                "Retry now",
+                [100.0, 30.0],
+                BORDER_RADIUS,
+                theme.negative_actv_btn_color,
+                theme.accent_color,
+                theme.accent_color,
+            )
+        }).unwrap_or(false)
 }
\ No newline at end of file
@@ -0,0 +1,156 @@
+use yapping_core::{chat::Chat, l3gion_rust::{imgui, UUID}, user::User};
+
+use crate::client_manager::AppState;
+
+use super::{button, emoji::EmojiPicker, no_resize_child_window, spacing, text_input, use_font, BORDER_RADIUS};
+
+const DIALOG_ID: &str = "New group##new_group_dialog";
+
+/// Modal where the user picks friends and a name to create a group Chat.
+pub(crate) struct NewGroupDialog {
+    app_state: AppState,
+    open: bool,
+    name: String,
+    icon: Option<&'static str>,
+    members: Vec<UUID>,
+    emoji_picker: EmojiPicker,
+    create: bool,
+}
+impl NewGroupDialog {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            open: false,
+            name: String::default(),
+            icon: None,
+            members: Vec::default(),
+            emoji_picker: EmojiPicker::default(),
+            create: false,
+        }
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Returns the group to be created, with the current user as one of the members.
+    pub(crate) fn take_chat(&mut self, current_user: &User) -> Option<Chat> {
+        if !self.create { return None; }
+        self.create = false;
+
+        let mut members = std::mem::take(&mut self.members);
+        members.insert(0, current_user.uuid());
+
+        let mut chat = Chat::new(std::mem::take(&mut self.name).trim(), members);
+        if let Some(icon) = self.icon.take() {
+            chat = chat.with_icon(icon);
+        }
+
+        Some(chat)
+    }
+
+    pub(crate) fn show(&mut self, ui: &imgui::Ui, current_user: &User) {
+        if std::mem::take(&mut self.open) {
+            ui.open_popup(DIALOG_ID);
+        }
+
+        let _popup = if let Some(popup) = ui.modal_popup_config(DIALOG_ID)
+            .always_auto_resize(true)
+            .begin_popup() { popup }
+        else { return; };
+
+        let theme = &self.app_state.theme;
+        let mut _fonts = vec![use_font(ui, super::FontType::REGULAR24)];
+
+        // Icon and name
+        if button(
+            ui,
+            &std::format!("{}##new_group_icon", self.icon.unwrap_or("+")),
+            [40.0, 40.0],
+            BORDER_RADIUS,
+            theme.accent_color,
+            theme.sign_up_btn_color,
+            theme.sign_up_btn_color,
+        ) {
+            ui.open_popup("##new_group_icon_picker");
+        }
+        if let Some(_picker) = ui.begin_popup("##new_group_icon_picker") {
+            if let Some(emoji) = self.emoji_picker.show(ui, theme, "new_group") {
+                self.icon = Some(emoji);
+                ui.close_current_popup();
+            }
+        }
+
+        ui.same_line();
+        ui.set_next_item_width(300.0);
+        text_input(
+            ui,
+            "Group name",
+            &mut self.name,
+            "##new_group_name",
+            theme.input_text_bg_light,
+            [0.0, 0.0, 0.0, 1.0],
+            BORDER_RADIUS,
+            imgui::InputTextFlags::CALLBACK_RESIZE
+        );
+
+        // Members
+        spacing(ui, 3);
+        _fonts.push(use_font(ui, super::FontType::BOLD17));
+        ui.text(std::format!("Members ({} selected)", self.members.len()));
+
+        _fonts.push(use_font(ui, super::FontType::REGULAR17));
+        no_resize_child_window(
+            ui,
+            "new_group_members",
+            imgui::WindowFlags::empty(),
+            [350.0, 200.0],
+            [5.0, 5.0],
+            theme.left_panel_bg_color,
+            |ui| {
+                if current_user.friends().is_empty() {
+                    ui.text_colored([1.0, 1.0, 1.0, 0.5], "Add some friends first!");
+                }
+
+                for friend in current_user.friends() {
+                    let mut selected = self.members.contains(&friend.uuid());
+                    if ui.checkbox(std::format!("{}##new_group_member_{}", friend.tag(), friend.uuid().to_string()), &mut selected) {
+                        if selected { self.members.push(friend.uuid()); }
+                        else { self.members.retain(|u| *u != friend.uuid()); }
+                    }
+                }
+            });
+
+        spacing(ui, 3);
+        let can_create = !self.name.trim().is_empty() && !self.members.is_empty();
+        let create_color = if can_create { theme.positive_btn_color } else { theme.accent_color };
+        if button(
+            ui,
+            "Create",
+            [170.0, 30.0],
+            BORDER_RADIUS,
+            create_color,
+            theme.positive_actv_btn_color,
+            theme.positive_actv_btn_color,
+        ) && can_create {
+            self.create = true;
+            ui.close_current_popup();
+        }
+
+        ui.same_line();
+        if button(
+            ui,
+            "Cancel",
+            [170.0, 30.0],
+            BORDER_RADIUS,
+            theme.negative_btn_color,
+            theme.negative_actv_btn_color,
+            theme.negative_actv_btn_color,
+        ) {
+            self.name.clear();
+            self.icon = None;
+            self.members.clear();
+            ui.close_current_popup();
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use yapping_core::l3gion_rust::imgui;
+
+use crate::{client_manager::AppState, e2e::E2eStatus};
+
+use super::{button, spacing, use_font, BORDER_RADIUS};
+
+const DIALOG_ID: &str = "Safety number##safety_number_dialog";
+const GROUPS_PER_ROW: usize = 4;
+
+/// Modal with the safety number of an encrypted Chat, to be compared with the contact's.
+pub(crate) struct SafetyNumberDialog {
+    app_state: AppState,
+    open: bool,
+    verify: Option<bool>,
+}
+impl SafetyNumberDialog {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            open: false,
+            verify: None,
+        }
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// What the user decided about the contact, if anything.
+    pub(crate) fn take_verify(&mut self) -> Option<bool> {
+        self.verify.take()
+    }
+
+    pub(crate) fn show(&mut self, ui: &imgui::Ui, contact: &str, safety_number: Option<&str>, status: E2eStatus) {
+        if std::mem::take(&mut self.open) {
+            ui.open_popup(DIALOG_ID);
+        }
+
+        let _popup = if let Some(popup) = ui.modal_popup_config(DIALOG_ID)
+            .always_auto_resize(true)
+            .begin_popup() { popup }
+        else { return; };
+
+        let theme = &self.app_state.theme;
+        let mut _fonts = vec![use_font(ui, super::FontType::BOLD17)];
+
+        if status == E2eStatus::KEY_CHANGED {
+            ui.text_colored(theme.negative_btn_color, std::format!("The safety number with {contact} changed."));
+            ui.text_colored(theme.negative_btn_color, "This happens when they reinstall or use another device.");
+            spacing(ui, 2);
+        }
+
+        _fonts.push(use_font(ui, super::FontType::REGULAR17));
+        ui.text(std::format!("Compare these numbers with {contact}, in person or over a call."));
+        ui.text("If they match, nobody can read your messages in between.");
+        spacing(ui, 3);
+
+        _fonts.push(use_font(ui, super::FontType::BOLD24));
+        match safety_number {
+            Some(safety_number) => {
+                let groups = safety_number
+                    .as_bytes()
+                    .chunks(5)
+                    .map(|group| String::from_utf8_lossy(group).to_string())
+                    .collect::<Vec<_>>();
+
+                for row in groups.chunks(GROUPS_PER_ROW) {
+                    ui.text(row.join("  "));
+                }
+            },
+            None => ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("Waiting for the key of {contact}")),
+        }
+
+        spacing(ui, 3);
+        _fonts.push(use_font(ui, super::FontType::REGULAR17));
+        let verified = status == E2eStatus::ON { verified: true };
+        if safety_number.is_some() {
+            let (label, color) = if verified { ("Unverify", theme.accent_color) } else { ("Mark as verified", theme.positive_btn_color) };
+            if button(
+                ui,
+                label,
+                [170.0, 30.0],
+                BORDER_RADIUS,
+                color,
+                theme.positive_actv_btn_color,
+                theme.positive_actv_btn_color,
+            ) {
+                self.verify = Some(!verified);
+                ui.close_current_popup();
+            }
+            ui.same_line();
+        }
+
+        if button(
+            ui,
+            "Close##safety_number_close",
+            [170.0, 30.0],
+            BORDER_RADIUS,
+            theme.negative_btn_color,
+            theme.negative_actv_btn_color,
+            theme.negative_actv_btn_color,
+        ) {
+            ui.close_current_popup();
+        }
+    }
+}
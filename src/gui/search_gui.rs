@@ -0,0 +1,376 @@
+use yapping_core::{client_server_coms::{Query, Response, ServerMessage, ServerMessageContent}, l3gion_rust::{imgui, lg_core::renderer::Renderer, AsLgTime, LgTimer, StdError, UUID}, message::{Message, MessageType}};
+
+use crate::{client_manager::{AppState, ForegroundState}, server_coms::ServerCommunication};
+
+use super::{chat_name, gui_manager::GuiMannager, mentions, no_resize_child_window, spacing, text_input, time_format, use_font, user_tag, window, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+
+const MAX_RESULTS: usize = 200;
+// Characters of context shown around the match.
+const SNIPPET_CONTEXT: usize = 30;
+// The server is only asked once the query stops changing for this long.
+const SERVER_SEARCH_DELAY_SECONDS: u32 = 1;
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SearchScope {
+    CHAT(UUID),
+    ALL,
+}
+
+struct SearchResult {
+    chat_uuid: UUID,
+    message: Message,
+}
+
+/// Search bar over the message history, Ctrl+F for the open Chat and Ctrl+Shift+F for every Chat.
+pub(crate) struct SearchGuiManager {
+    app_state: AppState,
+    scope: Option<SearchScope>,
+    focus_input: bool,
+    query: String,
+    searched_query: String,
+    search_server: bool,
+    // Set while a server search waits for the query to settle.
+    server_search_pending: bool,
+    server_search_timer: LgTimer,
+    results: Vec<SearchResult>,
+    selected: Option<(UUID, UUID)>,
+    waiting_response: UUID,
+}
+impl SearchGuiManager {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            scope: None,
+            focus_input: false,
+            query: String::default(),
+            searched_query: String::default(),
+            search_server: false,
+            server_search_pending: false,
+            server_search_timer: LgTimer::new(),
+            results: Vec::default(),
+            selected: None,
+            waiting_response: UUID::default(),
+        }
+    }
+
+    pub(crate) fn open(&mut self, scope: SearchScope) {
+        if self.scope != Some(scope) {
+            self.results.clear();
+            self.searched_query.clear();
+        }
+        self.scope = Some(scope);
+        self.focus_input = true;
+    }
+
+    pub(crate) fn open_with_query(&mut self, scope: SearchScope, query: String) {
+        self.open(scope);
+        self.query = query;
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.scope = None;
+    }
+}
+
+impl GuiMannager for SearchGuiManager {
+    fn on_imgui(&mut self, ui: &imgui::Ui, _renderer: &Renderer) {
+        let scope = if let Some(scope) = self.scope { scope }
+        else { return; };
+
+        if ui.is_key_pressed(imgui::Key::Escape) {
+            self.close();
+            return;
+        }
+
+        let (page_pos, page_size) = unsafe { NEXT_WINDOW_SPECS };
+        let size = [(page_size[0] - 40.0).min(600.0), (page_size[1] - 80.0).min(500.0)];
+        let position = [page_pos[0] + (page_size[0] - size[0]) / 2.0, page_pos[1] + 40.0];
+
+        window(
+            ui,
+            "search_window",
+            None,
+            position,
+            size,
+            [10.0, 10.0],
+            size,
+            self.app_state.theme.mini_pop_up_color,
+            |ui| {
+                let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
+                let title = match scope {
+                    SearchScope::CHAT(chat_uuid) => {
+                        let shared_mut = self.app_state.shared_mut.borrow();
+                        let name = shared_mut.chats
+                            .get(&chat_uuid)
+                            .map(|chat| chat_name(&shared_mut, chat))
+                            .unwrap_or_default();
+
+                        std::format!("Search in {name}")
+                    },
+                    SearchScope::ALL => String::from("Search all chats"),
+                };
+                ui.text(title);
+
+                _fonts.push(use_font(ui, super::FontType::REGULAR17));
+                if std::mem::take(&mut self.focus_input) {
+                    ui.set_keyboard_focus_here();
+                }
+                ui.set_next_item_width(ui.content_region_avail()[0] - 150.0);
+                text_input(
+                    ui,
+                    "Search messages",
+                    &mut self.query,
+                    "##search_query",
+                    self.app_state.theme.input_text_bg_light,
+                    [0.0, 0.0, 0.0, 1.0],
+                    BORDER_RADIUS,
+                    imgui::InputTextFlags::CALLBACK_RESIZE
+                );
+                ui.same_line();
+                if ui.checkbox("On server", &mut self.search_server) {
+                    // Searched again, with or without the server.
+                    self.searched_query.clear();
+                }
+
+                spacing(ui, 2);
+                if !self.query.trim().is_empty() {
+                    let waiting = if self.waiting_response.is_valid() || self.server_search_pending { " (searching server...)" } else { "" };
+                    ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("{} results{waiting}", self.results.len()));
+                }
+                ui.separator();
+
+                no_resize_child_window(
+                    ui,
+                    "search_results",
+                    imgui::WindowFlags::empty(),
+                    ui.content_region_avail(),
+                    [0.0; 2],
+                    self.app_state.theme.mini_pop_up_color,
+                    |ui| self.show_results(ui, scope));
+            });
+    }
+
+    fn on_update(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if let Some((chat_uuid, message_uuid)) = self.selected.take() {
+            let mut shared_mut = self.app_state.shared_mut.borrow_mut();
+            shared_mut.foreground_state = ForegroundState::CHAT_PAGE(chat_uuid);
+            shared_mut.jump_to = Some((chat_uuid, message_uuid));
+            self.close();
+        }
+
+        let scope = if let Some(scope) = self.scope { scope }
+        else { return Ok(()); };
+
+        let query = self.query.trim().to_string();
+        if query != self.searched_query {
+            self.searched_query = query.clone();
+            self.results = self.search_local(scope, &query);
+
+            // Results for the previous query are no longer wanted.
+            self.waiting_response = UUID::default();
+            self.server_search_pending = self.search_server && !query.is_empty();
+            self.server_search_timer.restart();
+        }
+
+        if self.server_search_pending && self.server_search_timer.elapsed() >= SERVER_SEARCH_DELAY_SECONDS.s() {
+            self.server_search_pending = false;
+            let chat_uuid = match scope {
+                SearchScope::CHAT(chat_uuid) => Some(chat_uuid),
+                SearchScope::ALL => None,
+            };
+
+            let msg_uuid = UUID::generate();
+            server_coms.send(ServerMessage::new(msg_uuid, ServerMessageContent::QUERY(Query::SEARCH_MESSAGES(chat_uuid, query))))?;
+            self.waiting_response = msg_uuid;
+        }
+
+        Ok(())
+    }
+
+    fn on_responded_messages(&mut self, message: &(ServerMessage, Response), _server_coms: &mut ServerCommunication) -> Result<bool, StdError> {
+        if message.0.uuid == self.waiting_response
+        {
+            self.waiting_response = UUID::default();
+            match &message.1 {
+                Response::OK_QUERY(Query::RESULT_SEARCH(found)) => {
+                    for (chat_uuid, found_message) in found {
+                        if self.results.len() >= MAX_RESULTS { break; }
+                        if self.results.iter().any(|r| r.message.uuid() == found_message.uuid()) { continue; }
+
+                        self.results.push(SearchResult { chat_uuid: *chat_uuid, message: found_message.clone() });
+                    }
+                    self.results.sort_by(|a, b| b.message.date_time().to_local().ok().cmp(&a.message.date_time().to_local().ok()));
+                },
+                Response::Err(e) => return Err(e.clone().into()),
+                _ => return Err(String::from("In SearchGuiManager::on_responded_messages: Wrong response from Server!").into()),
+            }
+
+            Ok(true)
+        }
+        else { Ok(false) }
+    }
+}
+impl SearchGuiManager {
+    // Newest messages first.
+    fn search_local(&self, scope: SearchScope, query: &str) -> Vec<SearchResult> {
+        if query.is_empty() { return Vec::default(); }
+
+        let shared_mut = self.app_state.shared_mut.borrow();
+        let mut results = shared_mut.chats
+            .values()
+            .filter(|chat| match scope {
+                SearchScope::CHAT(chat_uuid) => chat.uuid() == chat_uuid,
+                SearchScope::ALL => true,
+            })
+            .flat_map(|chat| chat.messages().iter().map(move |message| (chat.uuid(), message)))
+            .filter(|(_, message)| match message.content() {
+                MessageType::TEXT(text) => find_match(&mentions::display_text(&shared_mut, text), query).is_some(),
+                MessageType::FILE(_) => false,
+            })
+            .map(|(chat_uuid, message)| SearchResult { chat_uuid, message: message.clone() })
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| b.message.date_time().to_local().ok().cmp(&a.message.date_time().to_local().ok()));
+        results.truncate(MAX_RESULTS);
+
+        results
+    }
+
+    fn show_results(&mut self, ui: &imgui::Ui, scope: SearchScope) {
+        let shared_mut = self.app_state.shared_mut.borrow();
+
+        for result in &self.results {
+            let start = ui.cursor_screen_pos();
+
+            let mut header = user_tag(&shared_mut, result.message.sender());
+            if scope == SearchScope::ALL {
+                if let Some(chat) = shared_mut.chats.get(&result.chat_uuid) {
+                    header = std::format!("{header} in {}", chat_name(&shared_mut, chat));
+                }
+            }
+
+            let mut _fonts = vec![use_font(ui, super::FontType::BOLD17)];
+            ui.text(header);
+            if let Ok(date_time) = result.message.date_time().to_local() {
+                _fonts.push(use_font(ui, super::FontType::BOLD15));
+                ui.same_line();
+                ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("{} {}", time_format::date(date_time.date_naive()), time_format::time(&date_time, shared_mut.clock_format)));
+            }
+
+            _fonts.push(use_font(ui, super::FontType::REGULAR17));
+            if let MessageType::TEXT(text) = result.message.content() {
+                self.show_snippet(ui, &mentions::display_text(&shared_mut, text));
+            }
+
+            let end = [ui.window_pos()[0] + ui.content_region_max()[0], ui.cursor_screen_pos()[1]];
+            if ui.is_window_hovered() && ui.is_mouse_hovering_rect(start, end) {
+                ui.get_window_draw_list()
+                    .add_rect(start, end, [1.0, 1.0, 1.0, 0.05])
+                    .filled(true)
+                    .rounding(BORDER_RADIUS)
+                    .build();
+                ui.set_mouse_cursor(Some(imgui::MouseCursor::Hand));
+
+                if ui.is_mouse_clicked(imgui::MouseButton::Left) {
+                    self.selected = Some((result.chat_uuid, result.message.uuid()));
+                }
+            }
+
+            ui.separator();
+        }
+    }
+
+    // Text around the match, with the match highlighted.
+    fn show_snippet(&self, ui: &imgui::Ui, text: &str) {
+        let text = text.replace('\n', " ");
+        let (start, end) = if let Some(found) = find_match(&text, self.searched_query.as_str()) { found }
+        else {
+            ui.text(&text);
+            return;
+        };
+
+        let before = &text[..start];
+        let before_skip = before.chars().count().saturating_sub(SNIPPET_CONTEXT);
+        let before = before.chars().skip(before_skip).collect::<String>();
+        let after = text[end..].chars().take(SNIPPET_CONTEXT).collect::<String>();
+
+        let mut prefix = if before_skip > 0 { String::from("...") } else { String::default() };
+        prefix.push_str(&before);
+        let suffix = if text[end..].chars().count() > SNIPPET_CONTEXT { std::format!("{after}...") } else { after };
+
+        ui.text(prefix);
+        ui.same_line_with_spacing(0.0, 0.0);
+
+        let matched = &text[start..end];
+        let match_pos = ui.cursor_screen_pos();
+        let match_size = ui.calc_text_size(matched);
+        ui.get_window_draw_list()
+            .add_rect(match_pos, [match_pos[0] + match_size[0], match_pos[1] + match_size[1]], self.app_state.theme.sign_up_btn_color)
+            .filled(true)
+            .rounding(2.0)
+            .build();
+        ui.text(matched);
+
+        ui.same_line_with_spacing(0.0, 0.0);
+        ui.text(suffix);
+    }
+}
+
+/// Byte range of the first case insensitive match of the query in the text, always on char boundaries.
+fn find_match(text: &str, query: &str) -> Option<(usize, usize)> {
+    // Some chars lowercase to more than one, so both sides are compared as lowercase chars.
+    let query = query.chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+    if query.is_empty() { return None; }
+
+    for (start, _) in text.char_indices() {
+        let mut matched = 0;
+
+        for (i, c) in text[start..].char_indices() {
+            let lower = c.to_lowercase().collect::<Vec<_>>();
+            if query.len() - matched < lower.len() || query[matched..matched + lower.len()] != lower[..] { break; }
+
+            matched += lower.len();
+            if matched == query.len() {
+                return Some((start, start + i + c.len_utf8()));
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ignoring_case() {
+        assert_eq!(find_match("Hello World", "world"), Some((6, 11)));
+        assert_eq!(find_match("Hello World", "HELLO"), Some((0, 5)));
+        assert_eq!(find_match("Hello World", "worlds"), None);
+        assert_eq!(find_match("Hello", ""), None);
+    }
+
+    #[test]
+    fn non_ascii() {
+        let text = "Olá, ÉCOLE e café ☕ ok";
+        let (start, end) = find_match(text, "école").unwrap();
+        assert_eq!(&text[start..end], "ÉCOLE");
+
+        let (start, end) = find_match(text, "FÉ ☕").unwrap();
+        assert_eq!(&text[start..end], "fé ☕");
+    }
+
+    #[test]
+    fn multi_char_lowercase_stays_on_boundaries() {
+        // 'İ' lowercases to two chars.
+        let text = "xİstanbul";
+        let (start, end) = find_match(text, "i\u{307}stan").unwrap();
+        assert!(text.is_char_boundary(start) && text.is_char_boundary(end));
+        assert_eq!(&text[start..end], "İstan");
+
+        // Half of the lowercase of 'İ' is not a match of the whole char.
+        assert_eq!(find_match("İ", "i"), None);
+    }
+}
@@ -1,8 +1,10 @@
 use std::{borrow::BorrowMut, collections::HashMap};
 
-use yapping_core::{chat::Chat, client_server_coms::{Notification, NotificationType, ServerMessage, ServerMessageContent}, l3gion_rust::{imgui, lg_core::renderer::Renderer, sllog::warn, Rfc, StdError, UUID}, user::User};
-use crate::{client_manager::{AppState, ForegroundState}, server_coms::{self, ServerCommunication}};
-use super::{button, centered_component, gui_manager::GuiMannager, no_resize_child_window, no_resize_window, spacing, text_input, use_font, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+use yapping_core::{chat::Chat, client_server_coms::{Notification, NotificationType, ServerMessage, ServerMessageContent}, l3gion_rust::{imgui, lg_core::renderer::Renderer, sllog::warn, Rfc, StdError, UUID}, message::Message, user::User};
+use crate::{chat_state::ChatState, client_manager::{AppState, ForegroundState, SharedMut}, export::ExportFormat, server_coms::{self, ServerCommunication}};
+use super::{button, centered_component, chat_name, gui_manager::GuiMannager, is_group, mentions, message_snippet, new_group_gui::NewGroupDialog, no_resize_child_window, no_resize_window, relative_time, spacing, text_input, use_font, user_tag, BORDER_RADIUS, NEXT_WINDOW_SPECS};
+
+const PREVIEW_SNIPPET_LEN: usize = 14;
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone)]
@@ -25,17 +27,44 @@ pub(crate) struct SidebarGuiManager {
     sidebar_action: Option<SidebarAction>,
     search_buffer: String,
     begin_chat: Option<UUID>,
+    new_group: NewGroupDialog,
+    // Chat picked on the context menu, muted or unmuted on on_update.
+    toggle_mute: Option<UUID>,
+    export: Option<(UUID, ExportFormat)>,
+    open_bookmarks: bool,
 }
 impl SidebarGuiManager {
     pub(crate) fn new(app_state: AppState) -> Self {
         Self {
-            app_state,
+            app_state: app_state.clone(),
             sidebar_state: SidebarState::FRIENDS,
             sidebar_action: None,
             search_buffer: String::default(),
             begin_chat: None,
+            new_group: NewGroupDialog::new(app_state),
+            toggle_mute: None,
+            export: None,
+            open_bookmarks: false,
+        }
+    }
+
+    /// Muting only hides the unread badge, there are no other notifications to silence.
+    pub(crate) fn toggle_mute(&mut self, chat_uuid: UUID) {
+        let muted = &mut self.app_state.shared_mut.borrow_mut().muted;
+        if !muted.remove(&chat_uuid) {
+            muted.insert(chat_uuid);
         }
     }
+
+    /// Chat the user chose to export, from its context menu.
+    pub(crate) fn take_export(&mut self) -> Option<(UUID, ExportFormat)> {
+        self.export.take()
+    }
+
+    /// The user asked for the bookmarks window.
+    pub(crate) fn take_open_bookmarks(&mut self) -> bool {
+        std::mem::take(&mut self.open_bookmarks)
+    }
 }
 
 impl GuiMannager for SidebarGuiManager {
@@ -58,10 +87,11 @@ impl GuiMannager for SidebarGuiManager {
                 let user = if let Some(user) = &app_state_borrow.user { user }
                 else { return; };
                 let chats = &app_state_borrow.chats;
+                let chat_states = &app_state_borrow.chat_states;
 
                 if let Some(action) = match self.sidebar_state {
                     SidebarState::FRIENDS => self.show_friends_sidebar(ui, renderer, user),
-                    SidebarState::CHATS => self.show_chats_sidebar(ui, renderer, user, &chats),
+                    SidebarState::CHATS => self.show_chats_sidebar(ui, renderer, user, &chats, &chat_states),
                 } {
                     self.sidebar_action = Some(action);
                 }
@@ -71,6 +101,10 @@ impl GuiMannager for SidebarGuiManager {
     }
 
     fn on_update(&mut self, server_coms: &mut ServerCommunication) -> Result<(), StdError> {
+        if let Some(chat_uuid) = self.toggle_mute.take() {
+            self.toggle_mute(chat_uuid);
+        }
+
         if let Some(action) = self.sidebar_action.take() {
             self.app_state.shared_mut.borrow_mut().foreground_state = match action {
                 SidebarAction::FIND_NEW_FRIEND(mut user_tag) => ForegroundState::FIND_USERS(std::mem::take(&mut user_tag)),
@@ -85,11 +119,18 @@ impl GuiMannager for SidebarGuiManager {
         
         if let (Some(chat_with), Some(current_user)) = (std::mem::take(&mut self.begin_chat), &self.app_state.shared_mut.borrow().user) {
             self.begin_chat = None;
+            // Direct chats have no tag, they are named after the other user.
             server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::NEW_CHAT(Chat::new(
-                "placeholder_chat_tag", 
+                "", 
                 vec![current_user.uuid(), chat_with]
             ))))))?;
         }
+
+        if let Some(current_user) = &self.app_state.shared_mut.borrow().user {
+            if let Some(group) = self.new_group.take_chat(current_user) {
+                server_coms.send(ServerMessage::from(ServerMessageContent::NOTIFICATION(Notification::new(NotificationType::NEW_CHAT(group)))))?;
+            }
+        }
         
         Ok(())
     }
@@ -141,6 +182,7 @@ impl SidebarGuiManager {
         renderer: &Renderer, 
         user: &User,
         chats: &HashMap<UUID, Chat>,
+        chat_states: &HashMap<UUID, ChatState>,
     ) -> Option<SidebarAction>
     {
         let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
@@ -161,8 +203,35 @@ impl SidebarGuiManager {
         _fonts.push(use_font(ui, super::FontType::REGULAR17));
         self.show_search("Chat Tag", ui);
         
+        spacing(ui, 2);
+        if button(
+            ui,
+            "New group",
+            [ui.content_region_avail()[0], 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.main_bg_color,
+            self.app_state.theme.main_bg_color,
+        ) {
+            self.new_group.open();
+        }
+        self.new_group.show(ui, user);
+
+        spacing(ui, 1);
+        if button(
+            ui,
+            "Bookmarks",
+            [ui.content_region_avail()[0], 25.0],
+            BORDER_RADIUS,
+            self.app_state.theme.accent_color,
+            self.app_state.theme.main_bg_color,
+            self.app_state.theme.main_bg_color,
+        ) {
+            self.open_bookmarks = true;
+        }
+        
         // TODO: Get the chats
-        self.show_chat_list(ui, chats);
+        self.show_chat_list(ui, chats, chat_states);
         
         // User
         ui.set_cursor_pos([ui.cursor_pos()[0], ui.cursor_pos()[1] + 40.0]);
@@ -357,11 +426,25 @@ impl SidebarGuiManager {
             });
     }
 
-    fn show_chat_list(&mut self, ui: &imgui::Ui, chats: &HashMap<UUID, Chat>) {
+    fn show_chat_list(
+        &mut self,
+        ui: &imgui::Ui,
+        chats: &HashMap<UUID, Chat>,
+        chat_states: &HashMap<UUID, ChatState>,
+    ) {
         spacing(ui, 5);
         ui.separator();
         spacing(ui, 5);
 
+        // Most recent activity first, chats without messages at the end.
+        let mut chats = chats
+            .values()
+            .map(|chat| (chat, chat_name(&self.app_state.shared_mut.borrow(), chat)))
+            .filter(|(_, name)| name.to_lowercase().contains(&self.search_buffer.to_lowercase()))
+            .map(|(chat, name)| (chat, name, last_message(chat).and_then(|m| m.date_time().to_local().ok())))
+            .collect::<Vec<_>>();
+        chats.sort_by(|(_, a_name, a_time), (_, b_name, b_time)| b_time.cmp(a_time).then_with(|| a_name.cmp(b_name)));
+
         no_resize_child_window(
             ui, 
             "chats_list", 
@@ -371,11 +454,7 @@ impl SidebarGuiManager {
             self.app_state.theme.left_panel_bg_color, 
             |ui| {
                 let _window_rounding = ui.push_style_var(imgui::StyleVar::ChildRounding(BORDER_RADIUS));
-                for (i, (_, chat)) in chats
-                    .iter()
-                    .enumerate() 
-                    .filter(|(_, (_, chat))| (chat).tag().to_lowercase().contains(&self.search_buffer.to_lowercase()))
-                {
+                for (i, (chat, name, _)) in chats.iter().enumerate() {
                     no_resize_child_window(
                         ui, 
                         &std::format!("friend_{}", i), 
@@ -388,13 +467,23 @@ impl SidebarGuiManager {
                                 ui.open_popup("##sidebar_chat_popup");
                             }
                             
+                            let muted = self.app_state.shared_mut.borrow().muted.contains(&chat.uuid());
                             if let Some(_popup) = ui.begin_popup("##sidebar_chat_popup") {
-                                // TODO: 
+                                if ui.selectable(if muted { "Unmute" } else { "Mute" }) {
+                                    self.toggle_mute = Some(chat.uuid());
+                                }
+                                ui.separator();
+                                for (label, format) in [("Export as JSON", ExportFormat::JSON), ("Export as HTML", ExportFormat::HTML), ("Export as text", ExportFormat::TXT)] {
+                                    if ui.selectable(label) {
+                                        self.export = Some((chat.uuid(), format));
+                                    }
+                                }
                             }
 
+                            let pic_pos = ui.cursor_screen_pos();
                             if button(
                                 ui, 
-                                &std::format!("##chat_pic{}", i), 
+                                &std::format!("{}##chat_pic{}", chat.icon().unwrap_or_default(), i), 
                                 [50.0, ui.content_region_avail()[1]], 
                                 0.0,
                                 self.app_state.theme.positive_btn_color, 
@@ -404,9 +493,48 @@ impl SidebarGuiManager {
                                 self.sidebar_action = Some(SidebarAction::CHAT_PAGE(chat.uuid()));
                             };
 
+                            let unread = chat_states.get(&chat.uuid()).map(|state| state.unread()).unwrap_or(0);
+                            if unread > 0 && !muted {
+                                self.show_badge(ui, [pic_pos[0] + 40.0, pic_pos[1] + 10.0], badge_count(unread), self.app_state.theme.negative_btn_color);
+                            }
+                            // Mentions get their own badge, they matter more than the rest.
+                            let unread_mentions = chat_states.get(&chat.uuid()).map(|state| state.unread_mentions()).unwrap_or(0);
+                            if unread_mentions > 0 {
+                                self.show_badge(ui, [pic_pos[0] + 40.0, pic_pos[1] + 35.0], std::format!("@{}", badge_count(unread_mentions)), self.app_state.theme.sign_up_btn_color);
+                            }
+
                             // TODO: Use tables here!
                             ui.same_line();
-                            ui.text(chat.tag());
+                            let text_x = ui.cursor_pos()[0];
+                            if muted { ui.text_colored([1.0, 1.0, 1.0, 0.5], name); }
+                            else { ui.text(name); }
+                            if is_group(chat) {
+                                ui.same_line();
+                                ui.text_colored([1.0, 1.0, 1.0, 0.5], std::format!("({})", chat.users().len()));
+                            }
+
+                            if let Some(last_message) = last_message(chat) {
+                                let time = relative_time(last_message.date_time());
+                                ui.same_line_with_pos(ui.content_region_max()[0] - ui.calc_text_size(&time)[0] - 5.0);
+                                ui.text_colored([1.0, 1.0, 1.0, 0.5], time);
+                            }
+
+                            // An unsent draft is more useful than the last message.
+                            let _font = use_font(ui, super::FontType::BOLD15);
+                            let shared_mut = self.app_state.shared_mut.borrow();
+                            if let Some(draft) = shared_mut.drafts.get(&chat.uuid()) {
+                                ui.set_cursor_pos([text_x, ui.cursor_pos()[1]]);
+                                ui.text_colored(self.app_state.theme.negative_btn_color, "Draft:");
+                                ui.same_line();
+                                ui.text_colored([1.0, 1.0, 1.0, 0.6], draft_snippet(&shared_mut, draft));
+                            }
+                            else if let Some(last_message) = last_message(chat) {
+                                ui.set_cursor_pos([text_x, ui.cursor_pos()[1]]);
+                                ui.text_colored(
+                                    [1.0, 1.0, 1.0, 0.6],
+                                    std::format!("{}: {}", user_tag(&shared_mut, last_message.sender()), message_snippet(&shared_mut, last_message, PREVIEW_SNIPPET_LEN))
+                                );
+                            }
                             
                             if ui.is_window_hovered() && ui.is_mouse_clicked(imgui::MouseButton::Left) {
                                 self.sidebar_action = Some(SidebarAction::CHAT_PAGE(chat.uuid()));
@@ -415,4 +543,40 @@ impl SidebarGuiManager {
                 }
             });
     }
-}
\ No newline at end of file
+
+    fn show_badge(&self, ui: &imgui::Ui, center: [f32; 2], text: String, color: [f32; 4]) {
+        let _font = use_font(ui, super::FontType::BOLD15);
+        let text_size = ui.calc_text_size(&text);
+        let radius = (text_size[0] / 2.0 + 4.0).max(9.0);
+
+        let draw_list = ui.get_window_draw_list();
+        draw_list
+            .add_circle(center, radius, color)
+            .filled(true)
+            .build();
+        draw_list.add_text(
+            [center[0] - text_size[0] / 2.0, center[1] - text_size[1] / 2.0],
+            [1.0, 1.0, 1.0, 1.0],
+            text
+        );
+    }
+}
+
+// Thread replies are not on the timeline, so they don't move the Chat up or show as its preview.
+fn last_message(chat: &Chat) -> Option<&Message> {
+    chat.messages().iter().rev().find(|m| m.thread_root().is_none())
+}
+
+fn badge_count(count: usize) -> String {
+    if count > 99 { String::from("99+") } else { count.to_string() }
+}
+
+fn draft_snippet(shared_mut: &SharedMut, draft: &str) -> String {
+    let draft = mentions::display_text(shared_mut, draft);
+    let first_line = draft.lines().next().unwrap_or_default();
+
+    if first_line.chars().count() > PREVIEW_SNIPPET_LEN || draft.lines().count() > 1 {
+        std::format!("{}...", first_line.chars().take(PREVIEW_SNIPPET_LEN).collect::<String>())
+    }
+    else { first_line.to_string() }
+}
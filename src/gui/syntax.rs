@@ -0,0 +1,362 @@
+/// What a piece of code is, for its color.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TokenKind {
+    PLAIN,
+    KEYWORD,
+    // Types, shell variables and JSON keys.
+    TYPE,
+    STRING,
+    NUMBER,
+    COMMENT,
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Language {
+    RUST,
+    PYTHON,
+    JAVASCRIPT,
+    JSON,
+    SHELL,
+}
+impl Language {
+    /// From the name after the opening fence, e.g. "```rs".
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "rust" | "rs" => Some(Self::RUST),
+            "python" | "py" => Some(Self::PYTHON),
+            "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => Some(Self::JAVASCRIPT),
+            "json" => Some(Self::JSON),
+            "shell" | "sh" | "bash" | "zsh" | "console" => Some(Self::SHELL),
+            _ => None,
+        }
+    }
+
+    fn rules(self) -> &'static Rules {
+        match self {
+            Self::RUST => &RUST,
+            Self::PYTHON => &PYTHON,
+            Self::JAVASCRIPT => &JAVASCRIPT,
+            Self::JSON => &JSON,
+            Self::SHELL => &SHELL,
+        }
+    }
+}
+
+struct Rules {
+    keywords: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    // Longest first, so """ wins over ".
+    quotes: &'static [&'static str],
+    // 'a' is a char but 'a is a lifetime.
+    char_literals: bool,
+    capitalized_types: bool,
+    // $VAR, and comments only at the start of a word.
+    shell_words: bool,
+    // Strings followed by ':' are keys.
+    keys: bool,
+}
+
+static RUST: Rules = Rules {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn", "for", "if", "impl", "in",
+        "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+        "type", "unsafe", "use", "where", "while",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &["\""],
+    char_literals: true,
+    capitalized_types: true,
+    shell_words: false,
+    keys: false,
+};
+
+static PYTHON: Rules = Rules {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "False", "finally",
+        "for", "from", "global", "if", "import", "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "self",
+        "True", "try", "while", "with", "yield",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &["\"\"\"", "'''", "\"", "'"],
+    char_literals: false,
+    capitalized_types: true,
+    shell_words: false,
+    keys: false,
+};
+
+static JAVASCRIPT: Rules = Rules {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do", "else", "export",
+        "extends", "false", "finally", "for", "function", "if", "import", "in", "instanceof", "let", "new", "null", "of", "return", "static",
+        "super", "switch", "this", "throw", "true", "try", "typeof", "undefined", "var", "void", "while", "with", "yield",
+    ],
+    line_comment: Some("//"),
+    block_comment: Some(("/*", "*/")),
+    quotes: &["`", "\"", "'"],
+    char_literals: false,
+    capitalized_types: true,
+    shell_words: false,
+    keys: false,
+};
+
+static JSON: Rules = Rules {
+    keywords: &["true", "false", "null"],
+    line_comment: None,
+    block_comment: None,
+    quotes: &["\""],
+    char_literals: false,
+    capitalized_types: false,
+    shell_words: false,
+    keys: true,
+};
+
+static SHELL: Rules = Rules {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "in", "function", "return", "export",
+        "local", "readonly", "set", "unset", "source", "alias", "echo", "cd", "exit", "shift", "break", "continue", "sudo",
+    ],
+    line_comment: Some("#"),
+    block_comment: None,
+    quotes: &["\"", "'"],
+    char_literals: false,
+    capitalized_types: false,
+    shell_words: true,
+    keys: false,
+};
+
+// What a line leaves open for the next one.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Open {
+    NOTHING,
+    COMMENT,
+    STRING(&'static str),
+}
+
+/// The tokens of every line of the code, unknown languages are a single PLAIN token per line.
+pub(crate) fn highlight(language: Option<Language>, code: &str) -> Vec<Vec<(TokenKind, &str)>> {
+    let mut open = Open::NOTHING;
+
+    code.lines()
+        .map(|line| match language {
+            Some(language) => highlight_line(language.rules(), line, &mut open),
+            None => vec![(TokenKind::PLAIN, line)],
+        })
+        .collect()
+}
+
+fn highlight_line<'a>(rules: &Rules, line: &'a str, open: &mut Open) -> Vec<(TokenKind, &'a str)> {
+    // Kind, start, end. Consecutive plain text is merged.
+    let mut tokens: Vec<(TokenKind, usize, usize)> = Vec::new();
+    let mut i = 0;
+
+    while i < line.len() {
+        let (kind, len) = match *open {
+            Open::COMMENT => {
+                let close = rules.block_comment.map_or("", |(_, close)| close);
+                match line[i..].find(close) {
+                    Some(end) => {
+                        *open = Open::NOTHING;
+                        (TokenKind::COMMENT, end + close.len())
+                    },
+                    None => (TokenKind::COMMENT, line.len() - i),
+                }
+            },
+            Open::STRING(quote) => match string_end(&line[i..], quote) {
+                Some(end) => {
+                    *open = Open::NOTHING;
+                    (TokenKind::STRING, end)
+                },
+                None => (TokenKind::STRING, line.len() - i),
+            },
+            Open::NOTHING => token(rules, line, i, open),
+        };
+
+        let kind = if kind == TokenKind::STRING && rules.keys && line[i + len..].trim_start().starts_with(':') { TokenKind::TYPE } else { kind };
+        match tokens.last_mut() {
+            Some((TokenKind::PLAIN, _, end)) if kind == TokenKind::PLAIN => *end = i + len,
+            _ => tokens.push((kind, i, i + len)),
+        }
+        i += len;
+    }
+
+    tokens.into_iter().map(|(kind, start, end)| (kind, &line[start..end])).collect()
+}
+
+// The token starting at i, anything unknown is a single plain char.
+fn token(rules: &Rules, line: &str, i: usize, open: &mut Open) -> (TokenKind, usize) {
+    let rest = &line[i..];
+    let previous = line[..i].chars().last();
+    let c = rest.chars().next().unwrap_or_default();
+    let word_start = previous.map_or(true, |p| !is_word(p));
+
+    if let Some(comment) = rules.line_comment.filter(|comment| rest.starts_with(comment)) {
+        let at_word_start = previous.map_or(true, char::is_whitespace);
+        if !rules.shell_words || at_word_start || comment != "#" {
+            return (TokenKind::COMMENT, rest.len());
+        }
+    }
+
+    if let Some((start, close)) = rules.block_comment.filter(|(start, _)| rest.starts_with(start)) {
+        return match rest[start.len()..].find(close) {
+            Some(end) => (TokenKind::COMMENT, start.len() + end + close.len()),
+            None => {
+                *open = Open::COMMENT;
+                (TokenKind::COMMENT, rest.len())
+            },
+        };
+    }
+
+    if let Some(quote) = rules.quotes.iter().find(|quote| rest.starts_with(*quote)) {
+        return match string_end(&rest[quote.len()..], quote) {
+            Some(end) => (TokenKind::STRING, quote.len() + end),
+            None => {
+                *open = Open::STRING(quote);
+                (TokenKind::STRING, rest.len())
+            },
+        };
+    }
+
+    if rules.char_literals && c == '\'' {
+        if let Some(len) = char_literal(rest) {
+            return (TokenKind::STRING, len);
+        }
+    }
+
+    if rules.shell_words && c == '$' {
+        let name = &rest[1..];
+        let len = if name.starts_with('{') { name.find('}').map_or(name.len(), |end| end + 1) }
+        else { name.find(|c: char| !is_word(c)).unwrap_or(name.len()).max(name.chars().next().map_or(0, char::len_utf8)) };
+
+        return (TokenKind::TYPE, 1 + len);
+    }
+
+    if c.is_ascii_digit() && word_start {
+        let bytes = rest.as_bytes();
+        let mut len = 0;
+        while len < bytes.len() {
+            let b = bytes[len];
+            // "1..2" is a range, not a number.
+            let decimal_point = b == b'.' && bytes.get(len + 1).map_or(false, u8::is_ascii_digit);
+            if !(b.is_ascii_alphanumeric() || b == b'_' || decimal_point) { break; }
+            len += 1;
+        }
+
+        return (TokenKind::NUMBER, len);
+    }
+
+    if (c.is_alphabetic() || c == '_') && word_start {
+        let len = rest.find(|c: char| !is_word(c)).unwrap_or(rest.len());
+        let word = &rest[..len];
+
+        let kind = if rules.keywords.contains(&word) { TokenKind::KEYWORD }
+        else if rules.capitalized_types && word.starts_with(|c: char| c.is_uppercase()) { TokenKind::TYPE }
+        else { TokenKind::PLAIN };
+
+        return (kind, len);
+    }
+
+    (TokenKind::PLAIN, c.len_utf8())
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Length up to and including the closing quote, skipping escapes.
+fn string_end(text: &str, quote: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            let _ = chars.next();
+        }
+        else if text[i..].starts_with(quote) {
+            return Some(i + quote.len());
+        }
+    }
+
+    None
+}
+
+// 'a', '\n', '\'' or '\u{1F600}', None for lifetimes.
+fn char_literal(text: &str) -> Option<usize> {
+    let inner = &text[1..];
+
+    let len = if let Some(escaped) = inner.strip_prefix('\\') {
+        // The escaped char may be a quote itself.
+        let first = escaped.chars().next()?.len_utf8();
+        1 + first + escaped[first..].find('\'')?
+    }
+    else {
+        let c = inner.chars().next()?;
+        if !inner[c.len_utf8()..].starts_with('\'') { return None; }
+        c.len_utf8()
+    };
+
+    Some(1 + len + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    fn line(language: Language, code: &str) -> Vec<(TokenKind, &str)> {
+        highlight(Some(language), code).remove(0)
+    }
+
+    #[test]
+    fn rust_tokens() {
+        assert_eq!(
+            line(Language::RUST, "let x: Vec<u8> = \"a\\\"b\"; // done"),
+            vec![(KEYWORD, "let"), (PLAIN, " x: "), (TYPE, "Vec"), (PLAIN, "<u8> = "), (STRING, "\"a\\\"b\""), (PLAIN, "; "), (COMMENT, "// done")]
+        );
+        assert_eq!(
+            line(Language::RUST, "fn f<'a>(c: char) { '\\n'; 'x'; 1..2 }"),
+            vec![
+                (KEYWORD, "fn"), (PLAIN, " f<'a>(c: char) { "), (STRING, "'\\n'"), (PLAIN, "; "), (STRING, "'x'"), (PLAIN, "; "),
+                (NUMBER, "1"), (PLAIN, ".."), (NUMBER, "2"), (PLAIN, " }"),
+            ]
+        );
+        assert_eq!(
+            line(Language::RUST, "['\\'', '\\\\', '\\u{1F600}']"),
+            vec![(PLAIN, "["), (STRING, "'\\''"), (PLAIN, ", "), (STRING, "'\\\\'"), (PLAIN, ", "), (STRING, "'\\u{1F600}'"), (PLAIN, "]")]
+        );
+    }
+
+    #[test]
+    fn multi_line_comments_and_strings() {
+        let lines = highlight(Some(Language::RUST), "a /* one\ntwo */ b");
+        assert_eq!(lines[0], vec![(PLAIN, "a "), (COMMENT, "/* one")]);
+        assert_eq!(lines[1], vec![(COMMENT, "two */"), (PLAIN, " b")]);
+
+        let lines = highlight(Some(Language::PYTHON), "x = \"\"\"doc\nstill\"\"\" # end");
+        assert_eq!(lines[0], vec![(PLAIN, "x = "), (STRING, "\"\"\"doc")]);
+        assert_eq!(lines[1], vec![(STRING, "still\"\"\""), (PLAIN, " "), (COMMENT, "# end")]);
+    }
+
+    #[test]
+    fn json_keys_and_shell_variables() {
+        assert_eq!(
+            line(Language::JSON, "{\"id\": 12.5, \"ok\": true}"),
+            vec![(PLAIN, "{"), (TYPE, "\"id\""), (PLAIN, ": "), (NUMBER, "12.5"), (PLAIN, ", "), (TYPE, "\"ok\""), (PLAIN, ": "), (KEYWORD, "true"), (PLAIN, "}")]
+        );
+        assert_eq!(
+            line(Language::SHELL, "echo ${HOME} $1 a#b # note"),
+            vec![(KEYWORD, "echo"), (PLAIN, " "), (TYPE, "${HOME}"), (PLAIN, " "), (TYPE, "$1"), (PLAIN, " a#b "), (COMMENT, "# note")]
+        );
+    }
+
+    #[test]
+    fn unknown_language_is_plain() {
+        assert_eq!(Language::from_name("Rs"), Some(Language::RUST));
+        assert_eq!(Language::from_name("cobol"), None);
+        assert_eq!(highlight(None, "let x\n\"y\""), vec![vec![(PLAIN, "let x")], vec![(PLAIN, "\"y\"")]]);
+    }
+}
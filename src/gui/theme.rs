@@ -15,6 +15,11 @@ pub(crate) struct Theme {
     pub(crate) accent_color: [f32; 4],
     pub(crate) input_text_bg_light: [f32; 4],
     pub(crate) input_text_bg_dark: [f32; 4],
+    pub(crate) code_keyword_color: [f32; 4],
+    pub(crate) code_type_color: [f32; 4],
+    pub(crate) code_string_color: [f32; 4],
+    pub(crate) code_number_color: [f32; 4],
+    pub(crate) code_comment_color: [f32; 4],
 }
 impl Debug for Theme {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -32,6 +37,11 @@ impl Debug for Theme {
         .field("accent_color", &format_args!("{:?}", &self.accent_color))
         .field("input_text_bg_light", &format_args!("{:?}", &self.input_text_bg_light))
         .field("input_text_bg_dark", &format_args!("{:?}", &self.input_text_bg_dark))
+        .field("code_keyword_color", &format_args!("{:?}", &self.code_keyword_color))
+        .field("code_type_color", &format_args!("{:?}", &self.code_type_color))
+        .field("code_string_color", &format_args!("{:?}", &self.code_string_color))
+        .field("code_number_color", &format_args!("{:?}", &self.code_number_color))
+        .field("code_comment_color", &format_args!("{:?}", &self.code_comment_color))
         .finish()
     }
 }
@@ -50,4 +60,9 @@ pub(crate) static MAIN_THEME: Theme = Theme {
     accent_color:               [0.2901, 0.0039, 0.6509, 1.0],
     input_text_bg_light:        [1.0, 1.0, 1.0, 1.0],
     input_text_bg_dark:         [0.8588, 0.8588, 0.8588, 1.0],
+    code_keyword_color:         [0.7764, 0.4705, 0.8666, 1.0],
+    code_type_color:            [0.8980, 0.7529, 0.4823, 1.0],
+    code_string_color:          [0.5960, 0.7647, 0.4745, 1.0],
+    code_number_color:          [0.8196, 0.6039, 0.4000, 1.0],
+    code_comment_color:         [0.5000, 0.5000, 0.5600, 1.0],
 };
\ No newline at end of file
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use yapping_core::{chat::Chat, chrono, date_time::DateTime, l3gion_rust::{imgui, UUID}, message::{Message, MessageType}};
+
+use crate::client_manager::AppState;
+
+use super::{button, emoji, markdown::{self, Block}, mentions::{self, MentionNames}, multiline_text_input, no_resize_child_window, spacing, use_font, user_tag, window, BORDER_RADIUS};
+
+pub(crate) const THREAD_PANEL_WIDTH: f32 = 320.0;
+
+/// Side panel shown to the right of the chat page with the replies of a single thread.
+pub(crate) struct ThreadPanel {
+    app_state: AppState,
+    root: Option<UUID>,
+    message_buffer: String,
+    send_message: bool,
+    // Parsed once per Message, like on the chat page.
+    markdown_cache: HashMap<UUID, Vec<Block>>,
+}
+impl ThreadPanel {
+    pub(crate) fn new(app_state: AppState) -> Self {
+        Self {
+            app_state,
+            root: None,
+            message_buffer: String::default(),
+            send_message: false,
+            markdown_cache: HashMap::default(),
+        }
+    }
+
+    pub(crate) fn is_open(&self) -> bool {
+        self.root.is_some()
+    }
+
+    pub(crate) fn open(&mut self, root: UUID) {
+        if self.root != Some(root) {
+            self.message_buffer.clear();
+            self.markdown_cache.clear();
+        }
+        self.root = Some(root);
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.root = None;
+        self.message_buffer.clear();
+        self.send_message = false;
+        self.markdown_cache.clear();
+    }
+
+    /// Returns the message written on the thread composer, already pointing to the thread root.
+    ///
+    /// The text goes through the same mention and emoji encoding as the main composer.
+    pub(crate) fn take_message(&mut self, sender: UUID, chat: &Chat) -> Option<Message> {
+        let root = self.root?;
+        if !self.send_message || self.message_buffer.is_empty() { return None; }
+        self.send_message = false;
+
+        // There's no mention picker here, so the typed "@tag" of any member counts.
+        let members = chat.users()
+            .iter()
+            .map(|member| (user_tag(&self.app_state.shared_mut.borrow(), *member), *member))
+            .collect::<Vec<_>>();
+        let text = mentions::encode_all(&std::mem::take(&mut self.message_buffer), &members);
+        let text = emoji::replace_shortcodes(&text);
+
+        Some(Message::new(sender, MessageType::TEXT(text), DateTime::from_utc(&chrono::Utc::now()))
+            .with_thread_root(root))
+    }
+
+    pub(crate) fn show(
+        &mut self,
+        ui: &imgui::Ui,
+        position: [f32; 2],
+        size: [f32; 2],
+        chat: &Chat,
+    ) {
+        let root = if let Some(root) = self.root { root }
+        else { return; };
+
+        window(
+            ui,
+            "chat_thread_window",
+            None,
+            position,
+            size,
+            [10.0, 10.0],
+            size,
+            self.app_state.theme.left_panel_bg_color,
+            |ui| {
+                // Header
+                let mut _fonts = vec![use_font(ui, super::FontType::BOLD24)];
+                ui.text("Thread");
+                ui.same_line_with_pos(ui.content_region_max()[0] - 30.0);
+                if button(
+                    ui,
+                    "X##close_thread",
+                    [30.0, 30.0],
+                    BORDER_RADIUS,
+                    self.app_state.theme.accent_color,
+                    self.app_state.theme.sign_up_btn_color,
+                    self.app_state.theme.sign_up_btn_color,
+                ) {
+                    self.close();
+                    return;
+                }
+                ui.separator();
+                spacing(ui, 3);
+
+                no_resize_child_window(
+                    ui,
+                    "thread_messages",
+                    imgui::WindowFlags::empty(),
+                    [ui.content_region_avail()[0], ui.content_region_avail()[1] - 80.0],
+                    [0.0; 2],
+                    self.app_state.theme.left_panel_bg_color,
+                    |ui| {
+                        let mention_names = mentions::names(&self.app_state.shared_mut.borrow(), chat);
+
+                        match chat.messages().iter().find(|m| m.uuid() == root) {
+                            Some(root_message) => self.show_thread_message(ui, 0, root_message, &mention_names),
+                            None => {
+                                let _font = use_font(ui, super::FontType::BOLD15);
+                                ui.text_colored([1.0, 1.0, 1.0, 0.5], "Original message not loaded");
+                            },
+                        }
+
+                        ui.separator();
+                        spacing(ui, 3);
+
+                        for (i, message) in chat.messages().iter().filter(|m| m.thread_root() == Some(root)).enumerate() {
+                            self.show_thread_message(ui, i + 1, message, &mention_names);
+                            spacing(ui, 3);
+                        }
+                    });
+
+                spacing(ui, 2);
+                _fonts.push(use_font(ui, super::FontType::REGULAR17));
+                self.send_message = multiline_text_input(
+                    ui,
+                    [ui.content_region_avail()[0], 60.0],
+                    &mut self.message_buffer,
+                    "##thread_text_input",
+                    [1.0, 1.0, 1.0, 0.3],
+                    [1.0, 1.0, 1.0, 1.0],
+                    BORDER_RADIUS,
+                    imgui::InputTextFlags::CALLBACK_RESIZE
+                    | imgui::InputTextFlags::CTRL_ENTER_FOR_NEW_LINE
+                    | imgui::InputTextFlags::ENTER_RETURNS_TRUE
+                );
+            });
+    }
+}
+impl ThreadPanel {
+    fn show_thread_message(&mut self, ui: &imgui::Ui, id: usize, message: &Message, mention_names: &MentionNames) {
+        let mut _fonts = vec![use_font(ui, super::FontType::BOLD17)];
+        ui.text(user_tag(&self.app_state.shared_mut.borrow(), message.sender()));
+
+        _fonts.push(use_font(ui, super::FontType::REGULAR17));
+        match message.content() {
+            MessageType::TEXT(text) => {
+                let _id = ui.push_id_usize(id);
+                let blocks = self.markdown_cache
+                    .entry(message.uuid())
+                    .or_insert_with(|| markdown::parse(text));
+
+                markdown::show_markdown(ui, &self.app_state.theme, blocks, mention_names);
+            },
+            MessageType::FILE(_) => ui.text("[File]"),
+        }
+    }
+}
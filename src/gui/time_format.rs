@@ -0,0 +1,114 @@
+use yapping_core::chrono::{self, Datelike, Local, NaiveDate, Timelike};
+
+// Regions where the 12 hour clock is the norm.
+const H12_REGIONS: [&str; 9] = ["US", "CA", "AU", "NZ", "IN", "PH", "PK", "EG", "SA"];
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ClockFormat {
+    H12,
+    H24,
+}
+impl ClockFormat {
+    /// Guessed from the locale environment variables, 24h if there is none.
+    pub(crate) fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_TIME", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+
+        Self::from_locale_name(&locale)
+    }
+
+    // e.g. "en_US.UTF-8"
+    fn from_locale_name(locale: &str) -> Self {
+        let region = locale
+            .split(['.', '@'])
+            .next()
+            .and_then(|lang| lang.split('_').nth(1))
+            .unwrap_or_default();
+
+        if H12_REGIONS.contains(&region) { Self::H12 } else { Self::H24 }
+    }
+}
+
+/// "14:05" or "2:05 PM".
+pub(crate) fn time(date_time: &chrono::DateTime<Local>, clock: ClockFormat) -> String {
+    match clock {
+        ClockFormat::H24 => std::format!("{:02}:{:02}", date_time.hour(), date_time.minute()),
+        ClockFormat::H12 => {
+            let (pm, hour) = date_time.hour12();
+            std::format!("{}:{:02} {}", hour, date_time.minute(), if pm { "PM" } else { "AM" })
+        },
+    }
+}
+
+/// "5 March 2024".
+pub(crate) fn date(date: NaiveDate) -> String {
+    std::format!("{} {} {}", date.day(), date.format("%B"), date.year())
+}
+
+/// Exact time, for tooltips.
+pub(crate) fn full(date_time: &chrono::DateTime<Local>, clock: ClockFormat) -> String {
+    let seconds = std::format!(":{:02}", date_time.second());
+    let mut time = time(date_time, clock);
+    match clock {
+        ClockFormat::H24 => time.push_str(&seconds),
+        ClockFormat::H12 => time.insert_str(time.len() - 3, &seconds),
+    }
+
+    std::format!("{}, {} {time}", date_time.format("%A"), date(date_time.date_naive()))
+}
+
+/// "Today", "Yesterday" or the full date.
+pub(crate) fn day_label(day: NaiveDate) -> String {
+    day_label_on(day, Local::now().date_naive())
+}
+
+fn day_label_on(day: NaiveDate, today: NaiveDate) -> String {
+    if day == today { String::from("Today") }
+    else if today.pred_opt() == Some(day) { String::from("Yesterday") }
+    else { std::format!("{}, {}", day.format("%A"), date(day)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yapping_core::chrono::TimeZone;
+
+    fn afternoon() -> chrono::DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, 5, 14, 5, 9).unwrap()
+    }
+
+    #[test]
+    fn clock_from_locale() {
+        assert_eq!(ClockFormat::from_locale_name("en_US.UTF-8"), ClockFormat::H12);
+        assert_eq!(ClockFormat::from_locale_name("en_AU@euro"), ClockFormat::H12);
+        assert_eq!(ClockFormat::from_locale_name("pt_BR.UTF-8"), ClockFormat::H24);
+        assert_eq!(ClockFormat::from_locale_name("C"), ClockFormat::H24);
+        assert_eq!(ClockFormat::from_locale_name(""), ClockFormat::H24);
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(time(&afternoon(), ClockFormat::H24), "14:05");
+        assert_eq!(time(&afternoon(), ClockFormat::H12), "2:05 PM");
+        assert_eq!(time(&Local.with_ymd_and_hms(2024, 3, 5, 0, 30, 0).unwrap(), ClockFormat::H12), "12:30 AM");
+    }
+
+    #[test]
+    fn full_times() {
+        assert_eq!(full(&afternoon(), ClockFormat::H24), "Tuesday, 5 March 2024 14:05:09");
+        assert_eq!(full(&afternoon(), ClockFormat::H12), "Tuesday, 5 March 2024 2:05:09 PM");
+    }
+
+    #[test]
+    fn day_labels() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+
+        assert_eq!(day_label_on(today, today), "Today");
+        assert_eq!(day_label_on(NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(), today), "Yesterday");
+        assert_eq!(day_label_on(NaiveDate::from_ymd_opt(2024, 2, 29).unwrap(), today), "Thursday, 29 February 2024");
+    }
+}
@@ -0,0 +1,481 @@
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}};
+use yapping_core::{chat::Chat, chrono::{DateTime, Local, TimeZone}, l3gion_rust::{sllog::{info, warn}, AsLgTime, LgTimer, StdError, UUID}, serde::{de::DeserializeOwned, Serialize}, user::User};
+
+use crate::{bookmarks::Bookmark, client_manager::SharedMut, e2e::E2e, gui::time_format::ClockFormat, scheduled::ScheduledMessage};
+
+// Time between writes to disk, changes are batched in between.
+const SAVE_INTERVAL_SECONDS: u32 = 2;
+
+/// On disk copy of the users, chats and messages of each account, so the last session shows up before the server answers.
+///
+/// Layout: `<data dir>/yapping/{last_account, clock_24h}` and `<data dir>/yapping/<user uuid>/{account.bin, drafts.bin, e2e.bin, scheduled.bin, bookmarks.bin, muted.bin, pins.bin, reactions.bin, read.bin, cleared.bin, chats/<chat uuid>.bin}`.
+pub(crate) struct LocalStore {
+    root: PathBuf,
+    account: Option<UUID>,
+    // Chats with a file on disk.
+    saved_chats: HashSet<UUID>,
+    dirty_chats: HashSet<UUID>,
+    dirty_account: bool,
+    // Drafts as last written to disk.
+    saved_drafts: HashMap<UUID, String>,
+    // Scheduled messages as last written to disk.
+    saved_scheduled: Vec<ScheduledMessage>,
+    // Bookmarks as last written to disk.
+    saved_bookmarks: Vec<Bookmark>,
+    // Muted chats as last written to disk.
+    saved_muted: HashSet<UUID>,
+    // Chat UUID, (Message UUID, User that pinned it), as last written to disk.
+    saved_pins: HashMap<UUID, Vec<(UUID, UUID)>>,
+    // Chat UUID, Message UUID, (Emoji, Users that reacted), as last written to disk.
+    saved_reactions: HashMap<UUID, HashMap<UUID, Vec<(String, Vec<UUID>)>>>,
+    // Chat UUID, User UUID, last Message UUID the user has read, as last written to disk.
+    saved_read: HashMap<UUID, HashMap<UUID, UUID>>,
+    // Chat UUID, time of the last /clear, as last written to disk.
+    saved_cleared: HashMap<UUID, DateTime<Local>>,
+    // Clock format as last written to disk.
+    saved_clock: Option<ClockFormat>,
+    timer: LgTimer,
+}
+impl LocalStore {
+    pub(crate) fn new() -> Self {
+        Self::with_root(data_dir())
+    }
+
+    fn with_root(root: PathBuf) -> Self {
+        Self {
+            root,
+            account: None,
+            saved_chats: HashSet::default(),
+            dirty_chats: HashSet::default(),
+            dirty_account: false,
+            saved_drafts: HashMap::default(),
+            saved_scheduled: Vec::default(),
+            saved_bookmarks: Vec::default(),
+            saved_muted: HashSet::default(),
+            saved_pins: HashMap::default(),
+            saved_reactions: HashMap::default(),
+            saved_read: HashMap::default(),
+            saved_cleared: HashMap::default(),
+            saved_clock: None,
+            timer: LgTimer::new(),
+        }
+    }
+
+    /// Loads the account that was used last on this machine, including the current user.
+    pub(crate) fn load_last_account(&mut self, shared_mut: &mut SharedMut) -> Result<(), StdError> {
+        let path = self.root.join("last_account");
+        if !path.exists() { return Ok(()); }
+
+        let user_uuid: UUID = yapping_core::bincode::deserialize(&std::fs::read(path)?)?;
+        self.load_account(user_uuid, shared_mut)?;
+
+        if let Some((user, _)) = self.read_account_file(user_uuid)? {
+            shared_mut.user = Some(user);
+        }
+
+        Ok(())
+    }
+
+    /// Settings are shared by every account on this machine.
+    pub(crate) fn load_settings(&mut self, shared_mut: &mut SharedMut) -> Result<(), StdError> {
+        // Until the user picks one, the clock follows the locale.
+        self.saved_clock = Some(shared_mut.clock_format);
+
+        let path = self.root.join("clock_24h");
+        if !path.exists() { return Ok(()); }
+
+        let clock_24h: bool = yapping_core::bincode::deserialize(&std::fs::read(path)?)?;
+        shared_mut.clock_format = if clock_24h { ClockFormat::H24 } else { ClockFormat::H12 };
+        self.saved_clock = Some(shared_mut.clock_format);
+
+        Ok(())
+    }
+
+    pub(crate) fn mark_chat(&mut self, chat_uuid: UUID) {
+        self.dirty_chats.insert(chat_uuid);
+    }
+
+    pub(crate) fn mark_chats<'a>(&mut self, chats: impl Iterator<Item = &'a UUID>) {
+        self.dirty_chats.extend(chats);
+    }
+
+    pub(crate) fn mark_account(&mut self) {
+        self.dirty_account = true;
+    }
+
+    /// Follows the logged in account and writes the pending changes every SAVE_INTERVAL_SECONDS.
+    pub(crate) fn on_update(&mut self, shared_mut: &mut SharedMut) -> Result<(), StdError> {
+        let user_uuid = shared_mut.user.as_ref().map(|user| user.uuid());
+        if let Some(user_uuid) = user_uuid {
+            if self.account != Some(user_uuid) {
+                self.switch_account(user_uuid, shared_mut)?;
+            }
+        }
+
+        if self.timer.elapsed() >= SAVE_INTERVAL_SECONDS.s() {
+            self.timer.restart();
+            self.save_settings(shared_mut)?;
+            self.flush(shared_mut)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self, shared_mut: &mut SharedMut) -> Result<(), StdError> {
+        let account = if let Some(account) = self.account { account }
+        else { return Ok(()); };
+
+        // Chats we left or were removed from.
+        let removed = self.saved_chats
+            .iter()
+            .filter(|chat_uuid| !shared_mut.chats.contains_key(chat_uuid))
+            .copied()
+            .collect::<Vec<_>>();
+        self.dirty_chats.extend(removed);
+
+        let account_dir = self.account_dir(account);
+        save_if_changed(&account_dir.join("drafts.bin"), &shared_mut.drafts, &mut self.saved_drafts, |drafts| Ok(yapping_core::bincode::serialize(drafts)?))?;
+        save_if_changed(&account_dir.join("scheduled.bin"), &shared_mut.scheduled, &mut self.saved_scheduled, |scheduled| list_to_bytes(scheduled))?;
+        save_if_changed(&account_dir.join("bookmarks.bin"), &shared_mut.bookmarks, &mut self.saved_bookmarks, |bookmarks| list_to_bytes(bookmarks))?;
+        save_if_changed(&account_dir.join("muted.bin"), &shared_mut.muted, &mut self.saved_muted, |muted| Ok(yapping_core::bincode::serialize(muted)?))?;
+        save_if_changed(&account_dir.join("pins.bin"), &pins(shared_mut), &mut self.saved_pins, |pins| Ok(yapping_core::bincode::serialize(pins)?))?;
+        save_if_changed(&account_dir.join("reactions.bin"), &reactions(shared_mut), &mut self.saved_reactions, |reactions| Ok(yapping_core::bincode::serialize(reactions)?))?;
+        save_if_changed(&account_dir.join("read.bin"), &read_up_to(shared_mut), &mut self.saved_read, |read| Ok(yapping_core::bincode::serialize(read)?))?;
+        save_if_changed(&account_dir.join("cleared.bin"), &shared_mut.cleared, &mut self.saved_cleared, cleared_to_bytes)?;
+
+        if shared_mut.e2e.take_dirty() {
+            std::fs::create_dir_all(&account_dir)?;
+            write_file(&account_dir.join("e2e.bin"), &shared_mut.e2e.to_bytes()?)?;
+        }
+
+        if self.dirty_chats.is_empty() && !self.dirty_account { return Ok(()); }
+
+        let chats_dir = self.account_dir(account).join("chats");
+        std::fs::create_dir_all(&chats_dir)?;
+
+        if let Some(user) = shared_mut.user.as_ref().filter(|user| user.uuid() == account) {
+            let users = shared_mut.users.values().cloned().collect::<Vec<_>>();
+            write_file(&self.account_dir(account).join("account.bin"), &yapping_core::bincode::serialize(&(user, users))?)?;
+        }
+
+        for chat_uuid in self.dirty_chats.drain() {
+            let path = chats_dir.join(std::format!("{}.bin", chat_uuid.to_string()));
+
+            match shared_mut.chats.get(&chat_uuid) {
+                Some(chat) => {
+                    write_file(&path, &yapping_core::bincode::serialize(chat)?)?;
+                    self.saved_chats.insert(chat_uuid);
+                },
+                None => {
+                    if path.exists() { std::fs::remove_file(path)?; }
+                    self.saved_chats.remove(&chat_uuid);
+                },
+            }
+        }
+        self.dirty_account = false;
+
+        Ok(())
+    }
+}
+// Private
+impl LocalStore {
+    fn account_dir(&self, user_uuid: UUID) -> PathBuf {
+        self.root.join(user_uuid.to_string())
+    }
+
+    fn save_settings(&mut self, shared_mut: &SharedMut) -> Result<(), StdError> {
+        if self.saved_clock == Some(shared_mut.clock_format) { return Ok(()); }
+
+        std::fs::create_dir_all(&self.root)?;
+        write_file(&self.root.join("clock_24h"), &yapping_core::bincode::serialize(&(shared_mut.clock_format == ClockFormat::H24))?)?;
+        self.saved_clock = Some(shared_mut.clock_format);
+
+        Ok(())
+    }
+
+    fn switch_account(&mut self, user_uuid: UUID, shared_mut: &mut SharedMut) -> Result<(), StdError> {
+        // Whatever belongs to the previous account is written before being dropped.
+        self.flush(shared_mut)?;
+
+        if self.account.is_some() {
+            shared_mut.chats.clear();
+            shared_mut.chat_states.clear();
+            shared_mut.users.clear();
+            shared_mut.drafts.clear();
+            shared_mut.scheduled.clear();
+            shared_mut.bookmarks.clear();
+            shared_mut.muted.clear();
+            shared_mut.cleared.clear();
+        }
+
+        self.load_account(user_uuid, shared_mut)?;
+
+        std::fs::create_dir_all(&self.root)?;
+        write_file(&self.root.join("last_account"), &yapping_core::bincode::serialize(&user_uuid)?)?;
+        self.mark_account();
+
+        Ok(())
+    }
+
+    fn load_account(&mut self, user_uuid: UUID, shared_mut: &mut SharedMut) -> Result<(), StdError> {
+        self.account = Some(user_uuid);
+        self.saved_chats.clear();
+        self.dirty_chats.clear();
+
+        if let Some((_, users)) = self.read_account_file(user_uuid)? {
+            shared_mut.users.extend(users.into_iter().map(|user| (user.uuid(), user)));
+        }
+
+        let account_dir = self.account_dir(user_uuid);
+
+        self.saved_drafts = load_file(&account_dir.join("drafts.bin"), |bytes| Ok(yapping_core::bincode::deserialize(bytes)?))?;
+        shared_mut.drafts.extend(self.saved_drafts.clone());
+
+        // Whatever came due while the app was closed is sent once we are connected.
+        self.saved_scheduled = load_file(&account_dir.join("scheduled.bin"), list_from_bytes)?;
+        shared_mut.scheduled.extend(self.saved_scheduled.clone());
+
+        self.saved_bookmarks = load_file(&account_dir.join("bookmarks.bin"), list_from_bytes)?;
+        shared_mut.bookmarks.extend(self.saved_bookmarks.clone());
+
+        self.saved_muted = load_file(&account_dir.join("muted.bin"), |bytes| Ok(yapping_core::bincode::deserialize(bytes)?))?;
+        shared_mut.muted.extend(self.saved_muted.clone());
+
+        self.saved_cleared = load_file(&account_dir.join("cleared.bin"), cleared_from_bytes)?;
+        shared_mut.cleared.extend(self.saved_cleared.clone());
+
+        // Until the Server sends the Chats.
+        self.saved_pins = load_file(&account_dir.join("pins.bin"), |bytes| Ok(yapping_core::bincode::deserialize(bytes)?))?;
+        for (chat_uuid, pinned) in &self.saved_pins {
+            shared_mut.chat_states.entry(*chat_uuid).or_default().set_pinned(pinned.clone());
+        }
+        self.saved_reactions = load_file(&account_dir.join("reactions.bin"), |bytes| Ok(yapping_core::bincode::deserialize(bytes)?))?;
+        for (chat_uuid, reactions) in &self.saved_reactions {
+            shared_mut.chat_states.entry(*chat_uuid).or_default().set_reactions(reactions.clone());
+        }
+        // The Server only sends read receipts as notifications, so these are the only ones we have after a restart.
+        self.saved_read = load_file(&account_dir.join("read.bin"), |bytes| Ok(yapping_core::bincode::deserialize(bytes)?))?;
+        for (chat_uuid, read_up_to) in &self.saved_read {
+            shared_mut.chat_states.entry(*chat_uuid).or_default().set_read_up_to(read_up_to.clone());
+        }
+
+        // Losing the identity key would break every encrypted Chat, so an unreadable file is an error.
+        shared_mut.e2e = load_file(&account_dir.join("e2e.bin"), E2e::from_bytes)?;
+        shared_mut.e2e.ensure_identity();
+
+        let chats_dir = account_dir.join("chats");
+        if !chats_dir.exists() { return Ok(()); }
+
+        for entry in std::fs::read_dir(chats_dir)? {
+            let path = entry?.path();
+            if path.extension() != Some("bin".as_ref()) { continue; }
+
+            match read_chat(&path) {
+                Ok(chat) => {
+                    self.saved_chats.insert(chat.uuid());
+                    let _ = shared_mut.chats.entry(chat.uuid()).or_insert(chat);
+                },
+                Err(e) => warn!("In LocalStore::load_account: Ignoring unreadable cache file {:?}: {e}", path),
+            }
+        }
+        info!("Loaded {} cached chats", shared_mut.chats.len());
+
+        Ok(())
+    }
+
+    fn read_account_file(&self, user_uuid: UUID) -> Result<Option<(User, Vec<User>)>, StdError> {
+        let path = self.account_dir(user_uuid).join("account.bin");
+        if !path.exists() { return Ok(None); }
+
+        Ok(Some(yapping_core::bincode::deserialize(&std::fs::read(path)?)?))
+    }
+}
+
+// Pins live in the ChatStates, only the Chats that have any are written.
+fn pins(shared_mut: &SharedMut) -> HashMap<UUID, Vec<(UUID, UUID)>> {
+    shared_mut.chat_states
+        .iter()
+        .filter(|(_, chat_state)| !chat_state.pinned().is_empty())
+        .map(|(chat_uuid, chat_state)| (*chat_uuid, chat_state.pinned().to_vec()))
+        .collect()
+}
+
+// Same for reactions.
+fn reactions(shared_mut: &SharedMut) -> HashMap<UUID, HashMap<UUID, Vec<(String, Vec<UUID>)>>> {
+    shared_mut.chat_states
+        .iter()
+        .filter(|(_, chat_state)| !chat_state.all_reactions().is_empty())
+        .map(|(chat_uuid, chat_state)| (*chat_uuid, chat_state.all_reactions().clone()))
+        .collect()
+}
+
+// And for read receipts.
+fn read_up_to(shared_mut: &SharedMut) -> HashMap<UUID, HashMap<UUID, UUID>> {
+    shared_mut.chat_states
+        .iter()
+        .filter(|(_, chat_state)| !chat_state.read_up_to().is_empty())
+        .map(|(chat_uuid, chat_state)| (*chat_uuid, chat_state.read_up_to().clone()))
+        .collect()
+}
+
+// Unix milliseconds, so a message sent in the same second as the /clear doesn't come back.
+fn cleared_to_bytes(cleared: &HashMap<UUID, DateTime<Local>>) -> Result<Vec<u8>, StdError> {
+    let cleared = cleared
+        .iter()
+        .map(|(chat_uuid, date_time)| (*chat_uuid, date_time.timestamp_millis()))
+        .collect::<HashMap<_, _>>();
+
+    Ok(yapping_core::bincode::serialize(&cleared)?)
+}
+
+fn cleared_from_bytes(bytes: &[u8]) -> Result<HashMap<UUID, DateTime<Local>>, StdError> {
+    let cleared: HashMap<UUID, i64> = yapping_core::bincode::deserialize(bytes)?;
+
+    Ok(cleared
+        .into_iter()
+        .filter_map(|(chat_uuid, millis)| Some((chat_uuid, Local.timestamp_millis_opt(millis).single()?)))
+        .collect())
+}
+
+/// Per-account data kept on disk as plain tuples, so the files don't depend on how types like chrono's serialize.
+pub(crate) trait Stored: Sized {
+    type Bytes: Serialize + DeserializeOwned;
+
+    fn to_stored(&self) -> Self::Bytes;
+    // None drops the entry, e.g. a timestamp that can't be read back.
+    fn from_stored(bytes: Self::Bytes) -> Option<Self>;
+}
+
+pub(crate) fn list_to_bytes<T: Stored>(list: &[T]) -> Result<Vec<u8>, StdError> {
+    Ok(yapping_core::bincode::serialize(&list.iter().map(T::to_stored).collect::<Vec<_>>())?)
+}
+
+pub(crate) fn list_from_bytes<T: Stored>(bytes: &[u8]) -> Result<Vec<T>, StdError> {
+    let list: Vec<T::Bytes> = yapping_core::bincode::deserialize(bytes)?;
+
+    Ok(list.into_iter().filter_map(T::from_stored).collect())
+}
+
+// Writes the file when the value differs from what was last written.
+fn save_if_changed<T: Clone + PartialEq>(path: &Path, value: &T, saved: &mut T, to_bytes: impl Fn(&T) -> Result<Vec<u8>, StdError>) -> Result<(), StdError> {
+    if value == saved { return Ok(()); }
+
+    if let Some(dir) = path.parent() { std::fs::create_dir_all(dir)?; }
+    write_file(path, &to_bytes(value)?)?;
+    *saved = value.clone();
+
+    Ok(())
+}
+
+// The default when there's no file yet.
+fn load_file<T: Default>(path: &Path, from_bytes: impl Fn(&[u8]) -> Result<T, StdError>) -> Result<T, StdError> {
+    if !path.exists() { return Ok(T::default()); }
+
+    from_bytes(&std::fs::read(path)?)
+}
+
+fn read_chat(path: &Path) -> Result<Chat, StdError> {
+    Ok(yapping_core::bincode::deserialize(&std::fs::read(path)?)?)
+}
+
+// Writes to a temporary file first, so a crash never leaves half a file behind.
+fn write_file(path: &Path, bytes: &[u8]) -> Result<(), StdError> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, bytes)?;
+    std::fs::rename(tmp, path)?;
+
+    Ok(())
+}
+
+pub(crate) fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("yapping")
+}
+
+#[cfg(test)]
+mod tests {
+    use yapping_core::chrono::{Local, TimeZone};
+
+    use crate::client_manager::{forget_chat, ForegroundState};
+
+    use super::*;
+
+    fn empty_shared_mut() -> SharedMut {
+        SharedMut {
+            user: None,
+            chats: HashMap::default(),
+            chat_states: HashMap::default(),
+            users: HashMap::default(),
+            foreground_state: ForegroundState::VALIDATION,
+            config: false,
+            jump_to: None,
+            offline: false,
+            clock_format: ClockFormat::H24,
+            drafts: HashMap::default(),
+            e2e: E2e::default(),
+            scheduled: Vec::default(),
+            bookmarks: Vec::default(),
+            muted: HashSet::default(),
+            cleared: HashMap::default(),
+        }
+    }
+
+    #[test]
+    fn lists_round_trip() {
+        let send_at = Local.timestamp_opt(Local::now().timestamp() + 60, 0).unwrap();
+        let scheduled = vec![ScheduledMessage::new(UUID::generate(), String::from("hi"), send_at)];
+        assert_eq!(list_from_bytes::<ScheduledMessage>(&list_to_bytes(&scheduled).unwrap()).unwrap(), scheduled);
+
+        let mut bookmark = Bookmark::new(UUID::generate(), UUID::generate(), UUID::generate(), String::from("hi"));
+        // Stored with second precision.
+        bookmark.saved_at = Local.timestamp_opt(bookmark.saved_at.timestamp(), 0).unwrap();
+        let bookmarks = vec![bookmark];
+        assert_eq!(list_from_bytes::<Bookmark>(&list_to_bytes(&bookmarks).unwrap()).unwrap(), bookmarks);
+    }
+
+    #[test]
+    fn account_round_trip() {
+        let root = std::env::temp_dir().join(std::format!("yapping_test_{}", UUID::generate().to_string()));
+        let user_uuid = UUID::generate();
+        let chat = Chat::new("group", vec![user_uuid, UUID::generate()]);
+        let chat_uuid = chat.uuid();
+        let message_uuid = UUID::generate();
+
+        let mut shared_mut = empty_shared_mut();
+        let mut store = LocalStore::with_root(root.clone());
+        store.switch_account(user_uuid, &mut shared_mut).unwrap();
+
+        let _ = shared_mut.chats.insert(chat_uuid, chat);
+        store.mark_chat(chat_uuid);
+        let _ = shared_mut.drafts.insert(chat_uuid, String::from("draft"));
+        let _ = shared_mut.muted.insert(chat_uuid);
+        shared_mut.chat_states.entry(chat_uuid).or_default().pin(message_uuid, user_uuid);
+        store.flush(&mut shared_mut).unwrap();
+
+        // A new session picks everything up.
+        let mut loaded = empty_shared_mut();
+        let mut store = LocalStore::with_root(root.clone());
+        store.load_last_account(&mut loaded).unwrap();
+        assert!(loaded.chats.contains_key(&chat_uuid));
+        assert_eq!(loaded.drafts.get(&chat_uuid).map(String::as_str), Some("draft"));
+        assert!(loaded.muted.contains(&chat_uuid));
+        assert_eq!(loaded.chat_states.get(&chat_uuid).map(|state| state.pinned().to_vec()), Some(vec![(message_uuid, user_uuid)]));
+
+        // Leaving the Chat removes its file and what was kept about it.
+        forget_chat(&mut loaded, chat_uuid);
+        store.flush(&mut loaded).unwrap();
+
+        let mut reloaded = empty_shared_mut();
+        LocalStore::with_root(root.clone()).load_last_account(&mut reloaded).unwrap();
+        assert!(reloaded.chats.is_empty());
+        assert!(reloaded.drafts.is_empty());
+        assert!(reloaded.muted.is_empty());
+        assert!(reloaded.chat_states.get(&chat_uuid).map_or(true, |state| state.pinned().is_empty()));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -5,8 +5,25 @@ mod client_layer;
 mod server_coms;
 mod gui;
 mod client_manager;
+mod chat_state;
+mod local_store;
+mod export;
+mod e2e;
+mod scheduled;
+mod expiry;
+mod bookmarks;
 
 fn main() {
+    // Headless export: `client export --email <email> --chat <chat> ...`
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("export") {
+        if let Err(e) = export::run_cli(&args[2..]) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if cfg!(debug_assertions) {
         std::env::set_var("LOG", "4");
     }
@@ -0,0 +1,137 @@
+use yapping_core::{chrono::{self, Local, NaiveDateTime, NaiveTime, TimeZone}, l3gion_rust::UUID};
+
+use crate::local_store::Stored;
+
+/// Hint for the custom time field of the schedule popup.
+pub(crate) const SEND_AT_HINT: &str = "14:30, 2024-03-05 14:30, +45m, +2h or +1d";
+
+/// A message written now and sent by the client later, the Server only sees it once it's due.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ScheduledMessage {
+    pub(crate) uuid: UUID,
+    pub(crate) chat_uuid: UUID,
+    // Composer text, with mentions encoded.
+    pub(crate) text: String,
+    pub(crate) send_at: chrono::DateTime<Local>,
+}
+impl ScheduledMessage {
+    pub(crate) fn new(chat_uuid: UUID, text: String, send_at: chrono::DateTime<Local>) -> Self {
+        Self {
+            uuid: UUID::generate(),
+            chat_uuid,
+            text,
+            send_at,
+        }
+    }
+
+    pub(crate) fn is_due(&self, now: &chrono::DateTime<Local>) -> bool {
+        self.send_at <= *now
+    }
+}
+
+impl Stored for ScheduledMessage {
+    // UUID, Chat UUID, text, unix timestamp.
+    type Bytes = (UUID, UUID, String, i64);
+
+    fn to_stored(&self) -> Self::Bytes {
+        (self.uuid, self.chat_uuid, self.text.clone(), self.send_at.timestamp())
+    }
+
+    fn from_stored((uuid, chat_uuid, text, timestamp): Self::Bytes) -> Option<Self> {
+        Some(Self {
+            uuid,
+            chat_uuid,
+            text,
+            send_at: Local.timestamp_opt(timestamp, 0).single()?,
+        })
+    }
+}
+
+/// Quick choices of the schedule popup, only the ones still ahead of now.
+pub(crate) fn presets(now: &chrono::DateTime<Local>) -> Vec<(&'static str, chrono::DateTime<Local>)> {
+    let today = now.date_naive();
+    let at = |date: chrono::NaiveDate, hour: u32| Local.from_local_datetime(&date.and_hms_opt(hour, 0, 0)?).earliest();
+
+    [
+        ("In 1 hour", Some(*now + chrono::Duration::hours(1))),
+        ("Tonight at 20:00", at(today, 20)),
+        ("Tomorrow at 9:00", today.succ_opt().and_then(|tomorrow| at(tomorrow, 9))),
+    ]
+    .into_iter()
+    .filter_map(|(label, send_at)| send_at.filter(|send_at| send_at > now).map(|send_at| (label, send_at)))
+    .collect()
+}
+
+/// Parses the custom time typed on the schedule popup, see SEND_AT_HINT.
+pub(crate) fn parse_send_at(text: &str, now: &chrono::DateTime<Local>) -> Result<chrono::DateTime<Local>, String> {
+    let text = text.trim();
+
+    let send_at = if let Some(relative) = text.strip_prefix('+') {
+        let (amount, unit) = relative.split_at(relative.char_indices().last().map_or(0, |(i, _)| i));
+        let amount: i64 = amount.parse().map_err(|_| std::format!("Invalid amount in {text}"))?;
+
+        let duration = match unit {
+            "m" => chrono::Duration::try_minutes(amount),
+            "h" => chrono::Duration::try_hours(amount),
+            "d" => chrono::Duration::try_days(amount),
+            _ => return Err(std::format!("Unknown unit in {text}, use m, h or d")),
+        };
+        duration
+            .and_then(|duration| now.checked_add_signed(duration))
+            .ok_or_else(|| std::format!("{text} is too far away"))?
+    }
+    else if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M") {
+        // A time that already passed today means tomorrow.
+        let mut date = now.date_naive();
+        if time <= now.time() {
+            date = date.succ_opt().ok_or("Invalid date")?;
+        }
+        local(date.and_time(time))?
+    }
+    else if let Ok(date_time) = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M") {
+        local(date_time)?
+    }
+    else { return Err(std::format!("Can't read {text}, try {SEND_AT_HINT}")); };
+
+    if send_at <= *now { return Err(String::from("That time already passed")); }
+
+    Ok(send_at)
+}
+
+fn local(date_time: NaiveDateTime) -> Result<chrono::DateTime<Local>, String> {
+    Local.from_local_datetime(&date_time).earliest().ok_or_else(|| String::from("That time doesn't exist here"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noon() -> chrono::DateTime<Local> {
+        local(NaiveDateTime::parse_from_str("2024-03-05 12:00", "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn relative_times() {
+        let now = noon();
+
+        assert_eq!(parse_send_at("+45m", &now), Ok(now + chrono::Duration::minutes(45)));
+        assert_eq!(parse_send_at("+2h", &now), Ok(now + chrono::Duration::hours(2)));
+        assert_eq!(parse_send_at(" +1d ", &now), Ok(now + chrono::Duration::days(1)));
+        assert!(parse_send_at("+2w", &now).is_err());
+        assert!(parse_send_at("+h", &now).is_err());
+        assert!(parse_send_at("+9999999999999d", &now).is_err());
+        assert!(parse_send_at(&std::format!("+{}m", i64::MAX), &now).is_err());
+    }
+
+    #[test]
+    fn absolute_times() {
+        let now = noon();
+
+        assert_eq!(parse_send_at("14:30", &now).map(|t| t.format("%Y-%m-%d %H:%M").to_string()), Ok(String::from("2024-03-05 14:30")));
+        // Already passed today.
+        assert_eq!(parse_send_at("09:00", &now).map(|t| t.format("%Y-%m-%d %H:%M").to_string()), Ok(String::from("2024-03-06 09:00")));
+        assert_eq!(parse_send_at("2024-04-01 08:15", &now).map(|t| t.format("%Y-%m-%d %H:%M").to_string()), Ok(String::from("2024-04-01 08:15")));
+        assert!(parse_send_at("2024-03-01 08:15", &now).is_err());
+        assert!(parse_send_at("tomorrow", &now).is_err());
+    }
+}
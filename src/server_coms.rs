@@ -8,6 +8,9 @@ use yapping_core::l3gion_rust::sllog::{error, info, warn};
 use yapping_core::l3gion_rust::{AsLgTime, LgTimer, StdError, UUID};
 use yapping_core::client_server_coms::{ComsManager, Response, ServerMessage, ServerMessageContent};
 
+// Time between connection attempts while offline, also used to check if the connection is still alive.
+const RECONNECT_INTERVAL_SECONDS: u32 = 5;
+
 pub(crate) struct ServerCommunication {
     connected: bool,
     timer: LgTimer,
@@ -32,6 +35,19 @@ impl ServerCommunication {
         self.connected
     }
 
+    /// Seconds until the next connection attempt.
+    pub(crate) fn reconnect_in(&self) -> u32 {
+        RECONNECT_INTERVAL_SECONDS.saturating_sub(self.timer.elapsed().get_seconds() as u32)
+    }
+
+    /// Tries to connect right away instead of waiting for the next attempt.
+    pub(crate) fn retry_now(&mut self) -> Result<(), StdError> {
+        self.timer.restart();
+        if self.is_connected() { return Ok(()); }
+
+        self.try_connect(&self.server_ip.clone())
+    }
+
     pub(crate) fn sent_responded(&mut self) -> Vec<(ServerMessage, Response)> {
         self.manager.sent_responded()
     }
@@ -41,7 +57,7 @@ impl ServerCommunication {
     }
     
     pub(crate) fn on_update(&mut self) -> Result<(), StdError> {
-        if self.timer.elapsed() >= 5_u32.s() {
+        if self.timer.elapsed() >= RECONNECT_INTERVAL_SECONDS.s() {
             self.timer.restart();
 
             if !self.is_connected() {